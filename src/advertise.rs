@@ -0,0 +1,137 @@
+use std::{net::SocketAddr, time::Duration};
+
+use log::{info, warn};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use nix::unistd::gethostname;
+use tokio::time::timeout;
+
+use crate::VERSION;
+
+const SERVICE_TYPE: &str = "_mpv-web._tcp.local.";
+const FALLBACK_HOST_NAME: &str = "mpv-web-client";
+const UNREGISTER_TIMEOUT_SEC: u64 = 1;
+
+pub struct Advertisement {
+  daemon: ServiceDaemon,
+  fullname: String,
+}
+
+pub fn advertise(addr: SocketAddr) -> Result<Advertisement, mdns_sd::Error> {
+  if addr.ip().is_loopback() {
+    warn!(
+      "advertised address {addr} is a loopback address - it will not be reachable from other devices"
+    );
+  }
+
+  let service = service_info(addr)?;
+  let daemon = ServiceDaemon::new()?;
+  let fullname = service.get_fullname().to_owned();
+  daemon.register(service)?;
+  info!(
+    "advertising \"{fullname}\" on port {} over mDNS",
+    addr.port()
+  );
+
+  Ok(Advertisement { daemon, fullname })
+}
+
+fn service_info(addr: SocketAddr) -> Result<ServiceInfo, mdns_sd::Error> {
+  let host_name = gethostname()
+    .ok()
+    .and_then(|name| name.into_string().ok())
+    .unwrap_or(FALLBACK_HOST_NAME.to_owned());
+  let properties = [("version", VERSION), ("path", "/")];
+  if addr.ip().is_unspecified() {
+    // listening on all interfaces - the daemon advertises the addresses of each of them
+    let service = ServiceInfo::new(
+      SERVICE_TYPE,
+      &host_name,
+      &format!("{host_name}.local."),
+      (),
+      addr.port(),
+      &properties[..],
+    )?
+    .enable_addr_auto();
+    return Ok(service);
+  }
+
+  ServiceInfo::new(
+    SERVICE_TYPE,
+    &host_name,
+    &format!("{host_name}.local."),
+    addr.ip(),
+    addr.port(),
+    &properties[..],
+  )
+}
+
+impl Advertisement {
+  pub async fn stop(self) {
+    // waiting for the status makes sure the goodbye packet is sent before the daemon shuts down
+    match self.daemon.unregister(&self.fullname) {
+      Ok(status) => {
+        let unregistered = timeout(
+          Duration::from_secs(UNREGISTER_TIMEOUT_SEC),
+          status.recv_async(),
+        )
+        .await;
+        match unregistered {
+          Ok(Ok(_)) => {}
+          Ok(Err(err)) => warn!(
+            "could not confirm unregistering of mDNS service \"{}\": {err}",
+            self.fullname
+          ),
+          Err(_) => warn!(
+            "could not confirm unregistering of mDNS service \"{}\" within {UNREGISTER_TIMEOUT_SEC}s",
+            self.fullname
+          ),
+        }
+      }
+      Err(err) => warn!(
+        "could not unregister mDNS service \"{}\": {err}",
+        self.fullname
+      ),
+    }
+    if let Err(err) = self.daemon.shutdown() {
+      warn!("could not shut down mDNS daemon: {err}");
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::{IpAddr, Ipv4Addr};
+
+  use tokio::net::TcpListener;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn service_record_carries_bound_port() {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let service = service_info(addr).unwrap();
+
+    assert_ne!(addr.port(), 0);
+    assert_eq!(service.get_port(), addr.port());
+    assert!(
+      service
+        .get_addresses()
+        .contains(&IpAddr::V4(Ipv4Addr::LOCALHOST))
+    );
+    assert_eq!(service.get_type(), SERVICE_TYPE);
+  }
+
+  #[tokio::test]
+  async fn unspecified_address_is_advertised_on_every_interface() {
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let service = service_info(addr).unwrap();
+
+    assert_eq!(service.get_port(), addr.port());
+    assert!(service.get_addresses().is_empty());
+    assert!(service.is_addr_auto());
+  }
+}
@@ -1,75 +1,546 @@
 use std::{
   collections::{HashMap, hash_map::Iter},
-  mem::take,
-  path::PathBuf,
+  ffi::OsStr,
+  io::{self, ErrorKind},
+  path::{Path, PathBuf},
   process::Stdio,
-  time::Duration,
+  sync::{Arc, Mutex, PoisonError},
+  time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use futures::future::{join, join_all};
 use log::{debug, error, info, warn};
 use nix::{
+  errno::Errno,
   sys::signal::{self, Signal},
   unistd::Pid,
 };
 use rand::{Rng, rng};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use tokio::{
-  fs::{File, OpenOptions, remove_file},
+  fs::{File, OpenOptions, create_dir_all, remove_file, write},
   io::{BufReader, BufWriter},
+  net::TcpStream,
   process::{Child, Command},
   select, spawn,
-  task::JoinHandle,
-  time::sleep,
+  sync::OnceCell,
+  task::{JoinError, JoinHandle},
+  time::{interval, sleep, timeout},
 };
 use uuid::{Builder, Uuid};
 
-use crate::common::tarflate::compress_files;
+use crate::{
+  api_servers::recent_output::{
+    OutputLine, OutputStream, RecentOutput, SharedRecentOutput, capture_output,
+  },
+  common::{semver::Semver, tarflate::compress_files},
+};
+
+pub mod recent_output;
+
+#[derive(Debug, Error)]
+pub enum ApiServersErr {
+  #[error("No api server instance with uuid {0} exists")]
+  InstanceNotFound(Uuid),
+  #[error("No api server instance with name \"{0}\" exists")]
+  NameNotFound(String),
+  #[error("api server instance with uuid {0} is remote and was not spawned by this client")]
+  RemoteInstance(Uuid),
+  #[error("api server instance with uuid {0} was spawned by this client and is not remote")]
+  LocalInstance(Uuid),
+  #[error("address \"{0}\" of a remote api server is not in host:port format")]
+  InvalidAddress(String),
+  #[error("api server instance with name \"{name}\" already exists with uuid {uuid}")]
+  NameTaken { name: String, uuid: Uuid },
+  #[error(
+    "saved api server \"{name}\" is already running at {address} outside of this client and cannot be tracked; stop it before respawning"
+  )]
+  AlreadyRunning { name: String, address: String },
+  #[error("api instance on address {address} did not become reachable within {timeout_secs}s")]
+  NotReachable { address: String, timeout_secs: u64 },
+  #[error("could not find a free port for an api instance: {0}")]
+  NoFreePort(#[source] io::Error),
+  #[error(
+    "{LOCAL_SERVER_BIN_NAME} binary not found on PATH; install it or add its location to PATH"
+  )]
+  BinaryNotFound,
+  #[error("could not spawn an api instance on address {address}: {source}")]
+  SpawnFailed {
+    address: String,
+    #[source]
+    source: io::Error,
+  },
+  #[error("could not send SIGTERM to instance with pid {pid}: {source}")]
+  SignalFailed {
+    pid: u32,
+    #[source]
+    source: Errno,
+  },
+  #[error("could not kill instance with uuid {uuid}: {source}")]
+  KillFailed {
+    uuid: Uuid,
+    #[source]
+    source: io::Error,
+  },
+  #[error("could not await on instance closure: {0}")]
+  WaitFailed(#[source] io::Error),
+  #[error("could not join spawned compression task: {0}")]
+  CompressionTaskFailed(#[source] JoinError),
+  #[error("could not compress archive: {0}")]
+  CompressionFailed(String),
+  #[error("{msg}: {source}")]
+  LogFile {
+    msg: String,
+    #[source]
+    source: io::Error,
+  },
+}
 
 pub struct ApiServerInstance {
   pub name: String,
-  pub local: bool,
   pub address: String,
+  pub started_at: SystemTime,
+  process: Option<LocalProcess>,
+}
+
+struct LocalProcess {
   handle: Child,
+  recent_output: SharedRecentOutput,
+  output_filenames: (String, String),
+  output_capture: Option<JoinHandle<()>>,
+  server_args: ServerArguments,
+  restart: Option<RestartState>,
+}
+
+#[derive(Default)]
+struct RestartState {
+  exited_cleanly: bool,
+  attempts: u8,
+  last_attempt_at: Option<Instant>,
+  next_attempt_at: Option<Instant>,
+}
+
+impl ApiServerInstance {
+  pub fn is_local(&self) -> bool {
+    self.process.is_some()
+  }
+
+  pub fn pid(&self) -> Option<u32> {
+    self.process.as_ref()?.handle.id()
+  }
+
+  fn local_process(&self, uuid: &Uuid) -> Result<&LocalProcess, ApiServersErr> {
+    self
+      .process
+      .as_ref()
+      .ok_or(ApiServersErr::RemoteInstance(*uuid))
+  }
 }
 
 pub struct ApiServersService {
   instances: HashMap<Uuid, ApiServerInstance>,
+  // stopped instances are forgotten, so their archives are looked up by the name they had
+  logs_archives: HashMap<String, String>,
+  saved_servers: Vec<SavedServer>,
+  saved_servers_path: PathBuf,
   logs_dir: PathBuf,
-  logs_join_handles: Vec<JoinHandle<()>>,
+  recent_output_capacity: usize,
+  stop_grace_period: Duration,
+  ready_timeout: Duration,
+}
+
+// an instance removed from the service, whose process is still exiting
+pub struct PendingStop {
+  uuid: Uuid,
+  name: String,
+  process: LocalProcess,
+  logs_dir: PathBuf,
+  stop_grace_period: Duration,
+}
+
+pub struct StoppedInstance {
+  name: String,
+  logs_archive: String,
+  server_args: ServerArguments,
+  restart: bool,
+}
+
+impl PendingStop {
+  pub async fn wait(mut self) -> Result<StoppedInstance, ApiServersErr> {
+    let uuid = self.uuid;
+    let result = match timeout(self.stop_grace_period, self.process.handle.wait()).await {
+      Ok(result) => result,
+      Err(_) => {
+        warn!(
+          "instance with uuid {uuid} did not exit within {}s after SIGTERM, sending SIGKILL",
+          self.stop_grace_period.as_secs()
+        );
+        self
+          .process
+          .handle
+          .kill()
+          .await
+          .map_err(|err| ApiServersErr::KillFailed { uuid, source: err })?;
+        self.process.handle.wait().await
+      }
+    }
+    .map_err(ApiServersErr::WaitFailed)?;
+    info!("instance with uuid {uuid} closed with result: {result}");
+    if let Some(output_capture) = self.process.output_capture.take()
+      && let Err(err) = output_capture.await
+    {
+      warn!("could not finish writing streams of instance with uuid {uuid}: {err}");
+    }
+    if let Err(archive_err) =
+      ApiServersService::archive_logs(&self.logs_dir, &uuid, &self.process.output_filenames).await
+    {
+      error!("could not archive logs for {uuid}: {archive_err}");
+      return Err(archive_err);
+    }
+
+    Ok(StoppedInstance {
+      name: self.name,
+      logs_archive: ApiServersService::get_logs_archive_filename(&uuid),
+      server_args: self.process.server_args,
+      restart: self.process.restart.is_some(),
+    })
+  }
 }
 
 const LOCAL_SERVER_IP_ADDR: &str = "127.0.0.1";
 const LOCAL_SERVER_BIN_NAME: &str = "mpv-web-api";
 const ADDR_ARG: &str = "--addr";
+
+pub fn find_local_server_binary() -> Option<PathBuf> {
+  let path = std::env::var_os("PATH")?;
+  std::env::split_paths(&path)
+    .map(|dir| dir.join(LOCAL_SERVER_BIN_NAME))
+    .find(|candidate| candidate.is_file())
+}
+
+static LOCAL_SERVER_VERSION: OnceCell<Option<Semver>> = OnceCell::const_new();
+const VERSION_ARG: &str = "--version";
+const VERSION_PROBE_TIMEOUT_SEC: u64 = 5;
+// probed once, since the binary is not expected to change while the client runs
+pub async fn local_server_version() -> Option<Semver> {
+  *LOCAL_SERVER_VERSION
+    .get_or_init(|| async {
+      let binary = find_local_server_binary()?;
+      let probe = Command::new(&binary)
+        .arg(VERSION_ARG)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+      let output = match timeout(Duration::from_secs(VERSION_PROBE_TIMEOUT_SEC), probe).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(err)) => {
+          warn!(
+            "could not probe version of {}: {err}",
+            binary.to_string_lossy()
+          );
+          return None;
+        }
+        Err(_) => {
+          warn!(
+            "version probe of {} did not finish within {VERSION_PROBE_TIMEOUT_SEC}s",
+            binary.to_string_lossy()
+          );
+          return None;
+        }
+      };
+
+      let version = parse_version_output(&String::from_utf8_lossy(&output.stdout));
+      if version.is_none() {
+        debug!(
+          "could not find a version in the output of {} {VERSION_ARG}",
+          binary.to_string_lossy()
+        );
+      }
+      version
+    })
+    .await
+}
+
+// accepts outputs like "mpv-web-api 0.5.1" or "v0.5.1"
+fn parse_version_output(output: &str) -> Option<Semver> {
+  output
+    .split_whitespace()
+    .map(|word| word.trim_start_matches('v'))
+    .find_map(|word| word.parse().ok())
+}
+
 const DIR_ARG: &str = "--dir";
 const WATCH_DIR_ARG: &str = "--watch-dir";
 
-pub struct ServerArguments<'a> {
-  pub port: u16,
-  pub dir: &'a [String],
+const MAX_RESTART_ATTEMPTS: u8 = 5;
+const RESTART_BACKOFF_BASE_SEC: u64 = 1;
+const RESTART_ATTEMPTS_RESET_AFTER_SEC: u64 = 60;
+const READY_POLL_INTERVAL_MS: u64 = 100;
+const SUPERVISE_INTERVAL_MS: u64 = 500;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ServerArguments {
+  pub port: Option<u16>,
+  pub dir: Vec<String>,
   pub watch_dir: bool,
+  pub env: HashMap<String, String>,
+  pub clear_env: bool,
+  pub extra_args: Vec<String>,
+}
+
+pub struct SpawnOptions {
+  pub restart: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct SavedServer {
+  name: String,
+  restart: bool,
+  server_args: ServerArguments,
 }
 
 impl ApiServersService {
-  pub fn new(logs_dir: PathBuf) -> Self {
+  pub fn new(
+    logs_dir: PathBuf,
+    saved_servers_path: PathBuf,
+    recent_output_capacity: usize,
+    stop_grace_period: Duration,
+    ready_timeout: Duration,
+  ) -> Self {
     ApiServersService {
       instances: HashMap::new(),
+      logs_archives: HashMap::new(),
+      saved_servers: Self::load_saved_servers(&saved_servers_path),
+      saved_servers_path,
       logs_dir,
-      logs_join_handles: Vec::new(),
+      recent_output_capacity,
+      stop_grace_period,
+      ready_timeout,
     }
   }
 
-  pub async fn spawn<'a>(
+  pub async fn spawn(
     &mut self,
     name: String,
-    server_args: &ServerArguments<'a>,
-  ) -> Result<Uuid, String> {
-    let mut cmd = Command::new(LOCAL_SERVER_BIN_NAME);
+    mut server_args: ServerArguments,
+    options: SpawnOptions,
+  ) -> Result<Uuid, ApiServersErr> {
+    if let Some((uuid, _)) = self.find_by_name(&name) {
+      return Err(ApiServersErr::NameTaken { name, uuid: *uuid });
+    }
 
-    let address = format!("{}:{}", LOCAL_SERVER_IP_ADDR, server_args.port);
-    cmd.args([ADDR_ARG, &address]);
+    let port = match server_args.port {
+      Some(port) => port,
+      None => Self::find_free_port()?,
+    };
+    server_args.port = Some(port);
+    let address = format!("{}:{}", LOCAL_SERVER_IP_ADDR, port);
+    let uuid = Builder::from_random_bytes(rng().random()).into_uuid();
+    let started_at = SystemTime::now();
+    let output_filenames = Self::get_output_stream_filenames(&uuid, started_at);
+    let recent_output = Arc::new(Mutex::new(RecentOutput::new(self.recent_output_capacity)));
+    let (handle, output_capture) = Self::start_process(
+      LOCAL_SERVER_BIN_NAME.as_ref(),
+      &self.logs_dir,
+      &address,
+      &server_args,
+      &output_filenames,
+      recent_output.clone(),
+    )
+    .await?;
+
+    let instance = ApiServerInstance {
+      name,
+      address: address.clone(),
+      started_at,
+      process: Some(LocalProcess {
+        handle,
+        recent_output,
+        output_filenames,
+        output_capture: Some(output_capture),
+        server_args: server_args.clone(),
+        restart: options.restart.then(RestartState::default),
+      }),
+    };
+    self.instances.insert(uuid, instance);
+
+    let name = self.instances[&uuid].name.clone();
+    self.saved_servers.retain(|saved| saved.name != name);
+    self.saved_servers.push(SavedServer {
+      name,
+      restart: options.restart,
+      server_args,
+    });
+    self.persist_saved_servers().await;
+
+    Ok(uuid)
+  }
+
+  pub async fn respawn_saved(&mut self) -> Vec<(String, Result<Uuid, ApiServersErr>)> {
+    let mut outcomes = Vec::new();
+    for saved in self.saved_servers.clone() {
+      if self.find_by_name(&saved.name).is_some() {
+        continue;
+      }
 
-    for dir in server_args.dir {
+      let outcome = self.respawn(saved.clone()).await;
+      outcomes.push((saved.name, outcome));
+    }
+
+    outcomes
+  }
+
+  async fn respawn(&mut self, saved: SavedServer) -> Result<Uuid, ApiServersErr> {
+    // a process left running by a previous client has no handle here, so it could not be stopped
+    // or supervised - it is left alone and stays saved, to be respawned once its port is free
+    if let Some(port) = saved.server_args.port {
+      let address = format!("{}:{}", LOCAL_SERVER_IP_ADDR, port);
+      if TcpStream::connect(&address).await.is_ok() {
+        return Err(ApiServersErr::AlreadyRunning {
+          name: saved.name,
+          address,
+        });
+      }
+    }
+
+    let options = SpawnOptions {
+      restart: saved.restart,
+    };
+    self.spawn(saved.name, saved.server_args, options).await
+  }
+
+  fn load_saved_servers(path: &Path) -> Vec<SavedServer> {
+    let content = match std::fs::read_to_string(path) {
+      Ok(content) => content,
+      Err(err) if err.kind() == ErrorKind::NotFound => return Vec::new(),
+      Err(err) => {
+        warn!(
+          "could not read saved api servers from {}: {err}",
+          path.to_string_lossy()
+        );
+        return Vec::new();
+      }
+    };
+
+    serde_json::from_str(&content).unwrap_or_else(|err| {
+      warn!(
+        "could not parse saved api servers from {}: {err}",
+        path.to_string_lossy()
+      );
+      Vec::new()
+    })
+  }
+
+  async fn persist_saved_servers(&self) {
+    let content = match serde_json::to_string_pretty(&self.saved_servers) {
+      Ok(content) => content,
+      Err(err) => {
+        warn!("could not serialize saved api servers: {err}");
+        return;
+      }
+    };
+
+    if let Err(err) = write(&self.saved_servers_path, content).await {
+      warn!(
+        "could not save api servers to {}: {err}",
+        self.saved_servers_path.to_string_lossy()
+      );
+    }
+  }
+
+  async fn forget_saved_server(&mut self, name: &str) {
+    let saved_count = self.saved_servers.len();
+    self.saved_servers.retain(|saved| saved.name != name);
+    if self.saved_servers.len() != saved_count {
+      self.persist_saved_servers().await;
+    }
+  }
+
+  pub fn ready_timeout(&self) -> Duration {
+    self.ready_timeout
+  }
+
+  pub fn register(&mut self, name: String, address: String) -> Result<Uuid, ApiServersErr> {
+    let is_host_port = address
+      .trim()
+      .rsplit_once(':')
+      .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+    if !is_host_port {
+      return Err(ApiServersErr::InvalidAddress(address));
+    }
+    if let Some((uuid, _)) = self.find_by_name(&name) {
+      return Err(ApiServersErr::NameTaken { name, uuid: *uuid });
+    }
+
+    let uuid = Builder::from_random_bytes(rng().random()).into_uuid();
+    info!("registering remote api server \"{name}\" at {address} with uuid {uuid}");
+    let instance = ApiServerInstance {
+      name,
+      address,
+      started_at: SystemTime::now(),
+      process: None,
+    };
+    self.instances.insert(uuid, instance);
+
+    Ok(uuid)
+  }
+
+  // only forgets the instance - a remote server is not owned by this client, so it keeps running
+  pub fn unregister(&mut self, uuid: &Uuid) -> Result<(), ApiServersErr> {
+    let instance = self
+      .instances
+      .get(uuid)
+      .ok_or(ApiServersErr::InstanceNotFound(*uuid))?;
+    if instance.is_local() {
+      return Err(ApiServersErr::LocalInstance(*uuid));
+    }
+
+    if let Some(instance) = self.instances.remove(uuid) {
+      info!(
+        "unregistered remote api server \"{}\" at {} with uuid {uuid}",
+        instance.name, instance.address
+      );
+    }
+    Ok(())
+  }
+
+  fn find_free_port() -> Result<u16, ApiServersErr> {
+    let listener =
+      std::net::TcpListener::bind((LOCAL_SERVER_IP_ADDR, 0)).map_err(ApiServersErr::NoFreePort)?;
+    let port = listener
+      .local_addr()
+      .map_err(ApiServersErr::NoFreePort)?
+      .port();
+    Ok(port)
+  }
+
+  async fn start_process(
+    program: &OsStr,
+    logs_dir: &Path,
+    address: &str,
+    server_args: &ServerArguments,
+    output_filenames: &(String, String),
+    recent_output: SharedRecentOutput,
+  ) -> Result<(Child, JoinHandle<()>), ApiServersErr> {
+    let (stdout_name, stderr_name) = output_filenames;
+    // the logs dir is created at startup, but it could have been removed since then
+    create_dir_all(logs_dir)
+      .await
+      .map_err(|err| ApiServersErr::LogFile {
+        msg: format!(
+          "could not create logs directory {}",
+          logs_dir.to_string_lossy()
+        ),
+        source: err,
+      })?;
+    let stdout_file_writer = Self::get_stream_file_writer(logs_dir, stdout_name).await?;
+    let stderr_file_writer = Self::get_stream_file_writer(logs_dir, stderr_name).await?;
+
+    let mut cmd = Command::new(program);
+    cmd.args([ADDR_ARG, address]);
+
+    for dir in &server_args.dir {
       cmd.args([DIR_ARG, dir]);
     }
 
@@ -77,161 +548,437 @@ impl ApiServersService {
       cmd.arg(WATCH_DIR_ARG);
     }
 
+    // passed verbatim to the api server binary only - no shell is involved
+    cmd.args(&server_args.extra_args);
+
+    if server_args.clear_env {
+      cmd.env_clear();
+    }
+    cmd.envs(&server_args.env);
+
+    // dropping an instance without stopping it (e.g. on panic) must not leave an orphaned process
     let mut handle = cmd
+      .kill_on_drop(true)
       .stdout(Stdio::piped())
       .stderr(Stdio::piped())
       .spawn()
-      .map_err(|err| format!("could not spawn an api instance on address {address}: {err}"))?;
-
-    let mut stdout = handle.stdout.take().unwrap();
-    let mut stderr = handle.stderr.take().unwrap();
+      .map_err(|err| match err.kind() {
+        ErrorKind::NotFound => ApiServersErr::BinaryNotFound,
+        _ => ApiServersErr::SpawnFailed {
+          address: address.to_owned(),
+          source: err,
+        },
+      })?;
 
-    let uuid = Builder::from_random_bytes(rng().random()).into_uuid();
-    let (stdout_name, stderr_name) = Self::get_output_stream_filenames(&uuid);
-    let mut stdout_file_writer = self.get_stream_file_writer(&stdout_name).await?;
-    let mut stderr_file_writer = self.get_stream_file_writer(&stderr_name).await?;
-    let join_handle = spawn(async move {
-      let stdout_fut = tokio::io::copy(&mut stdout, &mut stdout_file_writer);
-      let stderr_fut = tokio::io::copy(&mut stderr, &mut stderr_file_writer);
+    let stdout = handle.stdout.take().unwrap();
+    let stderr = handle.stderr.take().unwrap();
 
+    let stdout_fut = capture_output(
+      stdout,
+      stdout_file_writer,
+      OutputStream::Stdout,
+      recent_output.clone(),
+    );
+    let stderr_fut = capture_output(
+      stderr,
+      stderr_file_writer,
+      OutputStream::Stderr,
+      recent_output,
+    );
+    let output_capture = spawn(async move {
       _ = join(stdout_fut, stderr_fut).await;
     });
-    self.logs_join_handles.push(join_handle);
 
-    let instance = ApiServerInstance {
-      name,
-      local: true,
-      address,
-      handle,
+    Ok((handle, output_capture))
+  }
+
+  pub async fn supervise(&mut self) {
+    let mut restarts_due: Vec<Uuid> = Vec::new();
+    for (uuid, instance) in self.instances.iter_mut() {
+      let Some(process) = instance.process.as_mut() else {
+        continue;
+      };
+      let Some(restart) = process.restart.as_mut() else {
+        continue;
+      };
+      if restart.exited_cleanly {
+        continue;
+      }
+
+      let status = match process.handle.try_wait() {
+        Ok(Some(status)) => status,
+        Ok(None) => continue,
+        Err(err) => {
+          warn!("could not check the status of instance with uuid {uuid}: {err}");
+          continue;
+        }
+      };
+
+      // a clean exit was requested by the server itself, so it is not treated as a crash
+      if status.success() {
+        info!("instance with uuid {uuid} exited with {status}; not restarting it");
+        restart.exited_cleanly = true;
+        continue;
+      }
+
+      if let Some(attempt_at) = restart.next_attempt_at {
+        if Instant::now() >= attempt_at {
+          restarts_due.push(*uuid);
+        }
+        continue;
+      }
+
+      if restart
+        .last_attempt_at
+        .is_some_and(|at| at.elapsed() >= Duration::from_secs(RESTART_ATTEMPTS_RESET_AFTER_SEC))
+      {
+        restart.attempts = 0;
+      }
+
+      if restart.attempts >= MAX_RESTART_ATTEMPTS {
+        error!(
+          "instance with uuid {uuid} exited with {status}; giving up after {} restart attempts",
+          restart.attempts
+        );
+        process.restart = None;
+        continue;
+      }
+
+      let delay = Duration::from_secs(RESTART_BACKOFF_BASE_SEC << restart.attempts);
+      warn!(
+        "instance with uuid {uuid} exited unexpectedly with {status}; restarting in {} seconds",
+        delay.as_secs()
+      );
+      restart.next_attempt_at = Some(Instant::now() + delay);
+    }
+
+    for uuid in restarts_due {
+      if let Err(err) = self.restart_process(&uuid).await {
+        error!("could not restart instance with uuid {uuid}: {err}");
+      }
+    }
+  }
+
+  async fn restart_process(&mut self, uuid: &Uuid) -> Result<(), ApiServersErr> {
+    let instance = self
+      .instances
+      .get_mut(uuid)
+      .ok_or(ApiServersErr::InstanceNotFound(*uuid))?;
+    let Some(process) = instance.process.as_mut() else {
+      return Ok(());
     };
-    self.instances.insert(uuid, instance);
 
-    Ok(uuid)
+    let attempt = match process.restart.as_mut() {
+      Some(restart) => {
+        restart.attempts += 1;
+        restart.last_attempt_at = Some(Instant::now());
+        restart.next_attempt_at = None;
+        restart.attempts
+      }
+      None => return Ok(()),
+    };
+
+    if let Some(output_capture) = process.output_capture.take()
+      && let Err(err) = output_capture.await
+    {
+      warn!("could not finish writing streams of instance with uuid {uuid}: {err}");
+    }
+
+    let (handle, output_capture) = Self::start_process(
+      LOCAL_SERVER_BIN_NAME.as_ref(),
+      &self.logs_dir,
+      &instance.address,
+      &process.server_args,
+      &process.output_filenames,
+      process.recent_output.clone(),
+    )
+    .await?;
+    process.handle = handle;
+    process.output_capture = Some(output_capture);
+    instance.started_at = SystemTime::now();
+    info!("restarted instance with uuid {uuid}, attempt {attempt}/{MAX_RESTART_ATTEMPTS}");
+
+    Ok(())
   }
 
   pub async fn get_logs_readers(
     &self,
     uuid: &Uuid,
-  ) -> Result<(BufReader<File>, BufReader<File>), String> {
-    if !self.instances.contains_key(uuid) {
-      return Err(format!("No api server instance with uuid {uuid} exists"));
-    }
+  ) -> Result<(BufReader<File>, BufReader<File>), ApiServersErr> {
+    let instance = self
+      .instances
+      .get(uuid)
+      .ok_or(ApiServersErr::InstanceNotFound(*uuid))?;
 
-    let (stdout_filename, stderr_filename) = Self::get_output_stream_filenames(uuid);
+    let (stdout_filename, stderr_filename) = &instance.local_process(uuid)?.output_filenames;
     Ok((
-      self.get_stream_file_reader(&stdout_filename).await?,
-      self.get_stream_file_reader(&stderr_filename).await?,
+      self.get_stream_file_reader(stdout_filename).await?,
+      self.get_stream_file_reader(stderr_filename).await?,
     ))
   }
 
+  pub fn get_recent_output(
+    &self,
+    uuid: &Uuid,
+    lines: usize,
+  ) -> Result<Vec<OutputLine>, ApiServersErr> {
+    let instance = self
+      .instances
+      .get(uuid)
+      .ok_or(ApiServersErr::InstanceNotFound(*uuid))?;
+
+    Ok(
+      instance
+        .local_process(uuid)?
+        .recent_output
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .last(lines),
+    )
+  }
+
+  pub fn get(&self, uuid: &Uuid) -> Option<&ApiServerInstance> {
+    self.instances.get(uuid)
+  }
+
+  pub fn find_by_name(&self, name: &str) -> Option<(&Uuid, &ApiServerInstance)> {
+    self
+      .instances
+      .iter()
+      .find(|(_, instance)| instance.name == name)
+  }
+
   pub fn server_instances(&'_ self) -> Iter<'_, Uuid, ApiServerInstance> {
     self.instances.iter()
   }
 
   pub async fn shutdown(&mut self, shutdown_timeout: u32) {
     select! {
-      _ = join_all(take(&mut self.logs_join_handles)) => {
-        debug!("finished writing all streams from api servers");
-        if let Err(err) = self.archive_all_instances_logs().await {
-          error!("could not archive instances logs after shutdown signal: {err}");
+      result = self.stop_local_instances() => {
+        match result {
+          Ok(()) => debug!("stopped all api servers and finished writing all of their streams"),
+          Err(errs) => {
+            for err in errs {
+              error!("could not stop api server after shutdown signal: {err}");
+            }
+          }
         }
       }
       _ = sleep(Duration::from_secs(shutdown_timeout.into())) => {
-        warn!("forcing shutdown due to timeout on waiting for all streams of {shutdown_timeout} seconds")
+        warn!("forcing shutdown due to timeout on waiting for all api servers of {shutdown_timeout} seconds")
       }
     }
   }
 
-  async fn archive_all_instances_logs(&mut self) -> Result<(), String> {
-    for uuid in self.instances.keys() {
-      self.archive_logs(uuid).await?;
+  // the instance is signalled and forgotten right away, so the service does not have to be held
+  // while the process exits - the returned stop is waited for outside of it
+  pub async fn begin_stop(&mut self, uuid: &Uuid) -> Result<PendingStop, ApiServersErr> {
+    let pending = self.take_instance(uuid)?;
+    self.forget_saved_server(&pending.name).await;
+
+    Ok(pending)
+  }
+
+  // same as begin_stop, but the instance stays saved, so it is spawned again once stopped
+  pub fn begin_restart(&mut self, name: &str) -> Result<PendingStop, ApiServersErr> {
+    let uuid = *self
+      .find_by_name(name)
+      .ok_or(ApiServersErr::NameNotFound(name.to_owned()))?
+      .0;
+    info!("restarting instance \"{name}\" with uuid {uuid}");
+
+    self.take_instance(&uuid)
+  }
+
+  pub async fn finish_restart(&mut self, stopped: StoppedInstance) -> Result<Uuid, ApiServersErr> {
+    let name = stopped.name.clone();
+    let server_args = stopped.server_args.clone();
+    let options = SpawnOptions {
+      restart: stopped.restart,
+    };
+    self.finish_stop(stopped);
+
+    self.spawn(name, server_args, options).await
+  }
+
+  pub fn finish_stop(&mut self, stopped: StoppedInstance) {
+    self
+      .logs_archives
+      .insert(stopped.name, stopped.logs_archive);
+  }
+
+  // stops the process without removing it from saved servers, so it can be respawned later
+  fn take_instance(&mut self, uuid: &Uuid) -> Result<PendingStop, ApiServersErr> {
+    let instance = self
+      .instances
+      .get(uuid)
+      .ok_or(ApiServersErr::InstanceNotFound(*uuid))?;
+    match instance.local_process(uuid)?.handle.id() {
+      Some(id) => match signal::kill(Pid::from_raw(id as i32), Signal::SIGTERM) {
+        Ok(()) => {}
+        Err(Errno::ESRCH) => info!("instance pid: {id}; uuid: {uuid} has already exited"),
+        Err(err) => {
+          return Err(ApiServersErr::SignalFailed {
+            pid: id,
+            source: err,
+          });
+        }
+      },
+      None => info!("instance with uuid {uuid} has already exited"),
     }
 
-    Ok(())
+    let Some((name, process)) = self
+      .instances
+      .remove(uuid)
+      .and_then(|inst| Some((inst.name, inst.process?)))
+    else {
+      return Err(ApiServersErr::RemoteInstance(*uuid));
+    };
+
+    Ok(PendingStop {
+      uuid: *uuid,
+      name,
+      process,
+      logs_dir: self.logs_dir.clone(),
+      stop_grace_period: self.stop_grace_period,
+    })
   }
 
-  pub async fn stop(&mut self, uuid: &Uuid) -> Result<(), String> {
-    let mut instance = self.instances.remove(uuid).ok_or(format!(
-      "could not find api server instance with uuid {}",
-      &uuid
-    ))?;
-    let id = instance
-      .handle
-      .id()
-      .ok_or(format!("instance with uuid {} has already finished", uuid))?;
-
-    signal::kill(Pid::from_raw(id as i32), Signal::SIGTERM).unwrap();
-    let result = instance
-      .handle
-      .wait()
-      .await
-      .map_err(|err| format!("could not await on instance closure: {err}"))?;
-    info!(
-      "instance pid: {id}; uuid: {} closed with result: {result}",
-      &uuid
-    );
-    let archive_result = self.archive_logs(uuid).await;
-    if let Err(archive_err) = archive_result {
-      error!("could not archive logs for {}: {archive_err}", uuid);
-      return Err(archive_err);
+  pub async fn begin_stop_all(&mut self) -> (Vec<PendingStop>, Vec<String>) {
+    let result = self.begin_stop_local_instances();
+    self.saved_servers.clear();
+    self.persist_saved_servers().await;
+
+    result
+  }
+
+  fn begin_stop_local_instances(&mut self) -> (Vec<PendingStop>, Vec<String>) {
+    let uuids: Vec<Uuid> = self
+      .instances
+      .iter()
+      .filter(|(_, instance)| instance.is_local())
+      .map(|(uuid, _)| *uuid)
+      .collect();
+    let mut pending: Vec<PendingStop> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+    for uuid in uuids {
+      match self.take_instance(&uuid) {
+        Ok(stop) => pending.push(stop),
+        Err(err) => errors.push(format!("instance with uuid {uuid}: {err}")),
+      }
     }
 
-    Ok(())
+    (pending, errors)
+  }
+
+  async fn stop_local_instances(&mut self) -> Result<(), Vec<String>> {
+    let (pending, mut errors) = self.begin_stop_local_instances();
+    let (stopped, wait_errors) = wait_for_stops(pending).await;
+    errors.extend(wait_errors);
+    for stopped in stopped {
+      self.finish_stop(stopped);
+    }
+
+    if errors.is_empty() {
+      Ok(())
+    } else {
+      Err(errors)
+    }
   }
 
-  async fn archive_logs(&self, uuid: &Uuid) -> Result<(), String> {
-    let (stdout, stderr) = Self::get_output_stream_filenames(uuid);
-    let mut stdout_path = PathBuf::from(&self.logs_dir.clone());
-    stdout_path.push(stdout);
-    let mut stderr_path = PathBuf::from(&self.logs_dir.clone());
-    stderr_path.push(stderr);
-    let mut archive_path = self.logs_dir.clone();
-    archive_path.push(format!("{}_logs_archive.tar.gz", uuid));
+  async fn archive_logs(
+    logs_dir: &Path,
+    uuid: &Uuid,
+    output_filenames: &(String, String),
+  ) -> Result<(), ApiServersErr> {
+    let (stdout, stderr) = output_filenames;
+    let stdout_path = logs_dir.join(stdout);
+    let stderr_path = logs_dir.join(stderr);
+    let archive_path = logs_dir.join(Self::get_logs_archive_filename(uuid));
 
     let paths_to_compress = [stdout_path.clone(), stderr_path.clone()];
     spawn(async move { compress_files(&archive_path, &paths_to_compress) })
       .await
-      .map_err(|err| format!("could not join spawned compression task: {err}"))?
-      .map_err(|reason| format!("could not compress archive: {reason}"))?;
+      .map_err(ApiServersErr::CompressionTaskFailed)?
+      .map_err(ApiServersErr::CompressionFailed)?;
 
     remove_file(&stdout_path)
       .await
-      .map_err(|err| format!("could not remove stdout output: {err}"))?;
+      .map_err(|err| ApiServersErr::LogFile {
+        msg: "could not remove stdout output".to_owned(),
+        source: err,
+      })?;
     remove_file(&stderr_path)
       .await
-      .map_err(|err| format!("could not remove stderr output: {err}"))?;
+      .map_err(|err| ApiServersErr::LogFile {
+        msg: "could not remove stderr output".to_owned(),
+        source: err,
+      })?;
 
     Ok(())
   }
 
-  fn get_output_stream_filenames(uuid: &Uuid) -> (String, String) {
+  // returns the filename of the latest archive of the instance with the name, along with its reader
+  pub async fn get_logs_archive_reader(
+    &self,
+    name: &str,
+  ) -> Result<Option<(&str, BufReader<File>)>, ApiServersErr> {
+    let Some(filename) = self.logs_archives.get(name) else {
+      return Ok(None);
+    };
+    let mut path = self.logs_dir.clone();
+    path.push(filename);
+
+    match File::open(&path).await {
+      Ok(file) => Ok(Some((filename, BufReader::new(file)))),
+      Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+      Err(err) => Err(ApiServersErr::LogFile {
+        msg: format!("could not open logs archive {}", path.to_string_lossy()),
+        source: err,
+      }),
+    }
+  }
+
+  fn get_logs_archive_filename(uuid: &Uuid) -> String {
+    format!("{}_logs_archive.tar.gz", uuid)
+  }
+
+  fn get_output_stream_filenames(uuid: &Uuid, spawned_at: SystemTime) -> (String, String) {
+    let timestamp = spawned_at
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_secs();
     (
-      format!("mwa_{}_stdout", uuid),
-      format!("mwa_{}_stderr", uuid),
+      format!("mwa_{timestamp}_{uuid}_stdout"),
+      format!("mwa_{timestamp}_{uuid}_stderr"),
     )
   }
 
-  async fn get_stream_file_writer(&self, filename: &str) -> Result<BufWriter<File>, String> {
-    let mut path = self.logs_dir.clone();
+  async fn get_stream_file_writer(
+    logs_dir: &Path,
+    filename: &str,
+  ) -> Result<BufWriter<File>, ApiServersErr> {
+    let mut path = logs_dir.to_path_buf();
     path.push(filename);
 
     let target_file = OpenOptions::default()
       .create(true)
       .read(false)
-      .write(true)
+      .append(true)
       .open(&path)
       .await
-      .map_err(|err| {
-        format!(
-          "could not open file for stdout writing {}: {err}",
+      .map_err(|err| ApiServersErr::LogFile {
+        msg: format!(
+          "could not open file for stdout writing {}",
           &path.to_string_lossy()
-        )
+        ),
+        source: err,
       })?;
 
     Ok(BufWriter::new(target_file))
   }
 
-  async fn get_stream_file_reader(&self, filename: &str) -> Result<BufReader<File>, String> {
+  async fn get_stream_file_reader(&self, filename: &str) -> Result<BufReader<File>, ApiServersErr> {
     let mut path = self.logs_dir.clone();
     path.push(filename);
 
@@ -241,13 +988,151 @@ impl ApiServersService {
       .write(false)
       .open(&path)
       .await
-      .map_err(|err| {
-        format!(
-          "could not open file for stdout writing {}: {err}",
+      .map_err(|err| ApiServersErr::LogFile {
+        msg: format!(
+          "could not open file for reading {}",
           &path.to_string_lossy()
-        )
+        ),
+        source: err,
       })?;
 
     Ok(BufReader::new(target_file))
   }
 }
+
+// processes exit concurrently, so a single slow one delays the others by its grace period at most
+pub async fn wait_for_stops(pending: Vec<PendingStop>) -> (Vec<StoppedInstance>, Vec<String>) {
+  let mut stopped: Vec<StoppedInstance> = Vec::new();
+  let mut errors: Vec<String> = Vec::new();
+  let results = join_all(pending.into_iter().map(|stop| {
+    let uuid = stop.uuid;
+    async move { (uuid, stop.wait().await) }
+  }))
+  .await;
+  for (uuid, result) in results {
+    match result {
+      Ok(instance) => stopped.push(instance),
+      Err(err) => errors.push(format!("instance with uuid {uuid}: {err}")),
+    }
+  }
+
+  (stopped, errors)
+}
+
+// polled without holding the service, so other routes are not blocked while the instance starts
+pub async fn wait_until_ready(address: &str, ready_timeout: Duration) -> Result<(), ApiServersErr> {
+  let poll = async {
+    while TcpStream::connect(address).await.is_err() {
+      sleep(Duration::from_millis(READY_POLL_INTERVAL_MS)).await;
+    }
+  };
+
+  timeout(ready_timeout, poll)
+    .await
+    .map_err(|_| ApiServersErr::NotReachable {
+      address: address.to_owned(),
+      timeout_secs: ready_timeout.as_secs(),
+    })
+}
+
+pub fn spawn_supervisor(service: Arc<tokio::sync::Mutex<ApiServersService>>) -> JoinHandle<()> {
+  spawn(async move {
+    let mut ticker = interval(Duration::from_millis(SUPERVISE_INTERVAL_MS));
+    loop {
+      ticker.tick().await;
+      service.lock().await.supervise().await;
+    }
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::os::unix::fs::PermissionsExt;
+
+  use super::*;
+
+  fn fixture_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mwc-api-servers-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("fixture dir could not be created");
+    dir
+  }
+
+  // runs until killed, regardless of the arguments it gets
+  fn write_sleeping_server(dir: &Path) -> PathBuf {
+    let path = dir.join("sleeping-server");
+    std::fs::write(&path, "#!/bin/sh\nexec sleep 60\n").expect("stub could not be written");
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+      .expect("stub could not be made executable");
+    path
+  }
+
+  fn server_args() -> ServerArguments {
+    ServerArguments {
+      port: None,
+      dir: Vec::new(),
+      watch_dir: false,
+      env: HashMap::new(),
+      clear_env: false,
+      extra_args: Vec::new(),
+    }
+  }
+
+  fn process_is_running(pid: u32) -> bool {
+    // a zombie has already exited and only waits to be reaped
+    std::fs::read_to_string(format!("/proc/{pid}/stat"))
+      .map(|stat| !stat.contains(") Z "))
+      .unwrap_or(false)
+  }
+
+  #[tokio::test]
+  async fn missing_logs_dir_is_created_on_start() {
+    let dir = fixture_dir("missing-logs");
+    let program = write_sleeping_server(&dir);
+    let logs_dir = dir.join("removed").join("logs");
+
+    let (handle, _) = ApiServersService::start_process(
+      program.as_os_str(),
+      &logs_dir,
+      "127.0.0.1:0",
+      &server_args(),
+      &("stdout".to_owned(), "stderr".to_owned()),
+      Arc::new(Mutex::new(RecentOutput::new(1))),
+    )
+    .await
+    .unwrap();
+    drop(handle);
+
+    assert!(logs_dir.is_dir());
+    assert!(logs_dir.join("stdout").is_file());
+    assert!(logs_dir.join("stderr").is_file());
+  }
+
+  #[tokio::test]
+  async fn dropped_process_is_killed() {
+    let dir = fixture_dir("kill-on-drop");
+    let program = write_sleeping_server(&dir);
+
+    let (handle, _) = ApiServersService::start_process(
+      program.as_os_str(),
+      &dir.join("logs"),
+      "127.0.0.1:0",
+      &server_args(),
+      &("stdout".to_owned(), "stderr".to_owned()),
+      Arc::new(Mutex::new(RecentOutput::new(1))),
+    )
+    .await
+    .unwrap();
+    let pid = handle.id().expect("process has no pid");
+    assert!(process_is_running(pid));
+    drop(handle);
+
+    for _ in 0..100 {
+      if !process_is_running(pid) {
+        return;
+      }
+      tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("process {pid} is still running");
+  }
+}
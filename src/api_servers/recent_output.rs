@@ -0,0 +1,151 @@
+use std::{
+  collections::VecDeque,
+  sync::{Arc, Mutex, PoisonError},
+};
+
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+  Stdout,
+  Stderr,
+}
+
+#[derive(Serialize, Clone)]
+pub struct OutputLine {
+  pub stream: OutputStream,
+  pub line: String,
+}
+
+pub struct RecentOutput {
+  lines: VecDeque<OutputLine>,
+  capacity: usize,
+}
+
+impl RecentOutput {
+  pub fn new(capacity: usize) -> Self {
+    RecentOutput {
+      lines: VecDeque::with_capacity(capacity),
+      capacity,
+    }
+  }
+
+  pub fn push(&mut self, stream: OutputStream, line: String) {
+    if self.capacity == 0 {
+      return;
+    }
+
+    if self.lines.len() == self.capacity {
+      self.lines.pop_front();
+    }
+    self.lines.push_back(OutputLine { stream, line });
+  }
+
+  pub fn last(&self, count: usize) -> Vec<OutputLine> {
+    let skipped = self.lines.len().saturating_sub(count);
+    self.lines.iter().skip(skipped).cloned().collect()
+  }
+}
+
+pub type SharedRecentOutput = Arc<Mutex<RecentOutput>>;
+
+const OUTPUT_CHUNK_SIZE: usize = 8 * 1024;
+const LINE_SEPARATOR: u8 = b'\n';
+pub async fn capture_output<R, W>(
+  mut reader: R,
+  mut writer: W,
+  stream: OutputStream,
+  recent_output: SharedRecentOutput,
+) -> Result<(), std::io::Error>
+where
+  R: AsyncRead + Unpin,
+  W: AsyncWrite + Unpin,
+{
+  let mut chunk = vec![0u8; OUTPUT_CHUNK_SIZE];
+  let mut partial_line: Vec<u8> = Vec::new();
+  loop {
+    let read = reader.read(&mut chunk).await?;
+    if read == 0 {
+      break;
+    }
+
+    writer.write_all(&chunk[..read]).await?;
+    writer.flush().await?;
+
+    partial_line.extend_from_slice(&chunk[..read]);
+    while let Some(separator_idx) = partial_line.iter().position(|b| *b == LINE_SEPARATOR) {
+      let line: Vec<u8> = partial_line.drain(..=separator_idx).collect();
+      push_line(&recent_output, stream, &line[..separator_idx]);
+    }
+  }
+
+  if !partial_line.is_empty() {
+    push_line(&recent_output, stream, &partial_line);
+  }
+
+  Ok(())
+}
+
+fn push_line(recent_output: &SharedRecentOutput, stream: OutputStream, line: &[u8]) {
+  recent_output
+    .lock()
+    .unwrap_or_else(PoisonError::into_inner)
+    .push(stream, String::from_utf8_lossy(line).into_owned());
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+
+  use super::{OutputStream, RecentOutput, capture_output};
+
+  fn lines(output: &RecentOutput, count: usize) -> Vec<String> {
+    output
+      .last(count)
+      .into_iter()
+      .map(|line| line.line)
+      .collect()
+  }
+
+  #[test]
+  fn keeps_only_the_most_recent_lines() {
+    let mut output = RecentOutput::new(2);
+    output.push(OutputStream::Stdout, "first".to_string());
+    output.push(OutputStream::Stderr, "second".to_string());
+    output.push(OutputStream::Stdout, "third".to_string());
+
+    assert_eq!(lines(&output, 10), ["second", "third"]);
+    assert_eq!(lines(&output, 1), ["third"]);
+  }
+
+  #[test]
+  fn zero_capacity_drops_every_line() {
+    let mut output = RecentOutput::new(0);
+    output.push(OutputStream::Stdout, "ignored".to_string());
+
+    assert!(output.last(10).is_empty());
+  }
+
+  #[tokio::test]
+  async fn capture_splits_chunks_into_lines() {
+    let recent_output = Arc::new(Mutex::new(RecentOutput::new(10)));
+    let mut forwarded = Vec::new();
+
+    capture_output(
+      &b"first\nsecond\nunterminated"[..],
+      &mut forwarded,
+      OutputStream::Stdout,
+      recent_output.clone(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(forwarded, b"first\nsecond\nunterminated");
+    assert_eq!(
+      lines(&recent_output.lock().unwrap(), 10),
+      ["first", "second", "unterminated"]
+    );
+  }
+}
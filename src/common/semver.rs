@@ -1,8 +1,8 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
 use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(PartialEq, Eq, Hash, PartialOrd, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy, Debug)]
 pub struct Semver {
   major: usize,
   minor: usize,
@@ -16,7 +16,7 @@ impl<'de> Deserialize<'de> for Semver {
     D: Deserializer<'de>,
   {
     let version = String::deserialize(deserializer)?;
-    Semver::from_string(&version).map_err(serde::de::Error::custom)
+    version.parse().map_err(serde::de::Error::custom)
   }
 }
 
@@ -30,7 +30,7 @@ impl Serialize for Semver {
 }
 
 impl Semver {
-  fn from_string(source: &String) -> Result<Self, String> {
+  fn from_string(source: &str) -> Result<Self, String> {
     let mut split_version = source.split(VERSION_SEMVER_SEPARATOR).map(|chunk| {
       chunk
         .parse::<usize>()
@@ -46,6 +46,14 @@ impl Semver {
     })
   }
 
+  pub fn major(&self) -> usize {
+    self.major
+  }
+
+  pub fn minor(&self) -> usize {
+    self.minor
+  }
+
   fn string_representation(&self) -> String {
     [self.major, self.minor, self.patch]
       .map(|chunk| chunk.to_string())
@@ -53,11 +61,19 @@ impl Semver {
   }
 }
 
+impl FromStr for Semver {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Semver::from_string(s)
+  }
+}
+
 impl TryFrom<&String> for Semver {
   type Error = String;
 
   fn try_from(value: &String) -> Result<Self, Self::Error> {
-    Semver::from_string(value)
+    value.parse()
   }
 }
 
@@ -65,7 +81,7 @@ impl TryFrom<String> for Semver {
   type Error = String;
 
   fn try_from(value: String) -> Result<Self, Self::Error> {
-    Semver::from_string(&value)
+    value.parse()
   }
 }
 
@@ -80,3 +96,45 @@ impl Display for Semver {
     write!(f, "{}", self.string_representation())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::Semver;
+
+  #[test]
+  fn parses_version_string() {
+    let version: Semver = "1.2.3".parse().expect("version is valid");
+
+    assert_eq!(version.major(), 1);
+    assert_eq!(version.minor(), 2);
+    assert_eq!(version.to_string(), "1.2.3");
+  }
+
+  #[test]
+  fn fills_missing_parts_with_zeros() {
+    assert_eq!("2".parse::<Semver>().unwrap().to_string(), "2.0.0");
+  }
+
+  #[test]
+  fn rejects_non_numeric_parts() {
+    let err = "1.x.3".parse::<Semver>().unwrap_err();
+
+    assert!(err.contains("\"1.x.3\""), "{err}");
+  }
+
+  #[test]
+  fn try_from_matches_parse() {
+    let source = String::from("0.10.2");
+
+    assert_eq!(Semver::try_from(&source), source.parse());
+    assert!(Semver::try_from(String::from("v1.0.0")).is_err());
+  }
+
+  #[test]
+  fn orders_numerically() {
+    let older: Semver = "0.9.0".parse().unwrap();
+    let newer: Semver = "0.10.0".parse().unwrap();
+
+    assert!(older < newer);
+  }
+}
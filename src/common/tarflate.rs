@@ -1,88 +1,75 @@
-use flate2::{
-  Compression,
-  bufread::{GzDecoder, GzEncoder},
-};
+use async_compression::tokio::bufread::GzipDecoder;
+use flate2::{Compression, write::GzEncoder};
 use std::{
-  fs::{OpenOptions, remove_file},
-  io::{BufReader, BufWriter, Seek, copy},
+  fs::OpenOptions,
+  io::{BufWriter, ErrorKind, Write},
   path::{Path, PathBuf},
 };
-use tar::{Archive, Builder};
+use tar::Builder;
+use tokio::io::{AsyncBufRead, AsyncReadExt, copy, sink};
 
 pub fn compress_files<T>(out: &T, src_paths: &[T]) -> Result<(), String>
 where
   T: AsRef<Path>,
 {
-  let mut temp_tar_file_path = PathBuf::from(out.as_ref());
-  temp_tar_file_path.set_extension("temp");
-  let mut temp_tar_file = OpenOptions::new()
+  let target_archive_path = PathBuf::from(out.as_ref());
+  let target_archive_file = OpenOptions::new()
     .create(true)
     .truncate(true)
-    .read(true)
+    .read(false)
     .write(true)
-    .open(&temp_tar_file_path)
+    .open(&target_archive_path)
     .map_err(|err| format!("could not open file for stdout writing: {err}",))?;
-  let mut writer = BufWriter::new(&temp_tar_file);
-  let mut archive_builder = Builder::new(&mut writer);
+  let archive_encoder = GzEncoder::new(BufWriter::new(target_archive_file), Compression::fast());
+  let mut archive_builder = Builder::new(archive_encoder);
 
   for src_path in src_paths {
     let archive_path = PathBuf::from(src_path.as_ref().file_name().ok_or(format!(
       "provided file name {} can't be archived",
       src_path.as_ref().to_string_lossy()
     ))?);
-    archive_builder
-      .append_path_with_name(src_path, &archive_path)
-      .map_err(|err| {
-        format!(
-          "could not put {} into archive: {err}",
-          &archive_path.to_string_lossy()
-        )
-      })?;
+    let append_result = if src_path.as_ref().is_dir() {
+      archive_builder.append_dir_all(&archive_path, src_path)
+    } else {
+      archive_builder.append_path_with_name(src_path, &archive_path)
+    };
+    append_result.map_err(|err| {
+      format!(
+        "could not put {} into archive: {err}",
+        &archive_path.to_string_lossy()
+      )
+    })?;
   }
-  archive_builder
-    .finish()
-    .map_err(|err| format!("could not finish creating a temporary tar archive: {err}"))?;
-  drop(archive_builder);
-  drop(writer);
-
-  temp_tar_file
-    .seek(std::io::SeekFrom::Start(0))
-    .map_err(|err| format!("could not seek temporary tar file: {err}"))?;
-
-  let reader = BufReader::new(&temp_tar_file);
-  let mut archive_encoder = GzEncoder::new(reader, Compression::fast());
-  let target_archive_path = PathBuf::from(out.as_ref());
-  let target_archive_file = OpenOptions::new()
-    .create(true)
-    .truncate(true)
-    .read(false)
-    .write(true)
-    .open(&target_archive_path)
-    .map_err(|err| format!("could not open file for stdout writing: {err}",))?;
-  let mut archive_writer = BufWriter::new(target_archive_file);
 
-  copy(&mut archive_encoder, &mut archive_writer).map_err(|err| {
+  let archive_encoder = archive_builder
+    .into_inner()
+    .map_err(|err| format!("could not finish creating a tar archive: {err}"))?;
+  let mut archive_writer = archive_encoder.finish().map_err(|err| {
     format!(
       "could not create compressed archive file in {}: {err}",
       &target_archive_path.to_string_lossy()
     )
   })?;
-
-  remove_file(temp_tar_file_path)
-    .map_err(|err| format!("could not remove temp tar file: {err}"))?;
+  archive_writer.flush().map_err(|err| {
+    format!(
+      "could not flush compressed archive file in {}: {err}",
+      &target_archive_path.to_string_lossy()
+    )
+  })?;
 
   Ok(())
 }
 
-pub fn extract_archive<T>(src_path: T, out_dir: T) -> Result<(), String>
+pub async fn extract_archive<T>(src_path: T, out_dir: T) -> Result<(), String>
 where
   T: AsRef<Path>,
 {
-  let src_file_open_handle = std::fs::OpenOptions::new()
+  let src_file_open_handle = tokio::fs::OpenOptions::new()
     .create(false)
     .read(true)
     .write(false)
     .open(&src_path)
+    .await
     .map_err(|err| {
       format!(
         "could not open source archive {}: {err}",
@@ -90,48 +77,200 @@ where
       )
     })?;
 
-  let mut temp_inflated_file_path = PathBuf::from(src_path.as_ref());
-  temp_inflated_file_path.set_extension("inflated.temp");
-
-  let mut temp_inflated_file_open_handle = std::fs::OpenOptions::new()
-    .create(true)
-    .truncate(true)
-    .read(true)
-    .write(true)
-    .open(&temp_inflated_file_path)
+  let src_pkg_reader = tokio::io::BufReader::new(src_file_open_handle);
+  extract_archive_stream(src_pkg_reader, &out_dir)
+    .await
     .map_err(|err| {
       format!(
-        "could not open temporary inflated file {}: {err}",
-        temp_inflated_file_path.to_string_lossy()
+        "could not extract archive {}: {err}",
+        src_path.as_ref().to_string_lossy()
       )
     })?;
 
-  let src_pkg_reader = BufReader::new(src_file_open_handle);
-  let mut decoder = GzDecoder::new(src_pkg_reader);
-  let mut inflated_writer = BufWriter::new(&temp_inflated_file_open_handle);
-  copy(&mut decoder, &mut inflated_writer)
-    .map_err(|err| format!("could not inflate archive: {err}"))?;
-  drop(inflated_writer);
-
-  temp_inflated_file_open_handle
-    .seek(std::io::SeekFrom::Start(0))
-    .map_err(|err| format!("could not seek temporary inflated file: {err}"))?;
+  Ok(())
+}
 
-  let mut tar_archive = Archive::new(temp_inflated_file_open_handle);
-  tar_archive.unpack(&out_dir).map_err(|err| {
+pub async fn extract_archive_stream<R, T>(src: R, out_dir: T) -> Result<R, String>
+where
+  R: AsyncBufRead + Unpin,
+  T: AsRef<Path>,
+{
+  let decoder = GzipDecoder::new(src);
+  let mut tar_archive = tokio_tar::Archive::new(decoder);
+  tar_archive.unpack(&out_dir).await.map_err(|err| {
     format!(
-      "could unpack tar archive {} to {}: {err}",
-      temp_inflated_file_path.to_string_lossy(),
+      "could unpack tar archive to {}: {err}",
       out_dir.as_ref().to_string_lossy()
     )
   })?;
 
-  remove_file(&temp_inflated_file_path).map_err(|err| {
-    format!(
-      "could not remove temporary inflated file {}: {err}",
-      temp_inflated_file_path.to_string_lossy()
+  // the tar end-of-archive marker may precede the end of the gzip stream, so the rest is drained
+  // to let callers account for every byte of the source
+  let mut decoder = tar_archive
+    .into_inner()
+    .map_err(|_| "could not release the tar archive reader".to_owned())?;
+  copy(&mut decoder, &mut sink())
+    .await
+    .map_err(|err| format!("could not read the remainder of the archive: {err}"))?;
+
+  Ok(decoder.into_inner())
+}
+
+const GZIP_MAGIC_BYTES: [u8; 2] = [0x1f, 0x8b];
+pub async fn is_gzip_archive<T>(src_path: T) -> Result<bool, std::io::Error>
+where
+  T: AsRef<Path>,
+{
+  let mut src_file = tokio::fs::OpenOptions::new()
+    .create(false)
+    .read(true)
+    .write(false)
+    .open(&src_path)
+    .await?;
+
+  let mut header = [0u8; GZIP_MAGIC_BYTES.len()];
+  match src_file.read_exact(&mut header).await {
+    Ok(_) => Ok(header == GZIP_MAGIC_BYTES),
+    Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(false),
+    Err(err) => Err(err),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::{Path, PathBuf};
+
+  use flate2::{Compression, write::GzEncoder};
+
+  use super::{compress_files, extract_archive, extract_archive_stream, is_gzip_archive};
+
+  fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mwc-tarflate-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("test dir could not be created");
+    dir
+  }
+
+  fn gzipped_tar(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (name, content) in files {
+      let mut header = tar::Header::new_gnu();
+      header.set_size(content.len() as u64);
+      header.set_mode(0o644);
+      header.set_cksum();
+      archive
+        .append_data(&mut header, name, content.as_bytes())
+        .expect("file could not be added");
+    }
+    archive
+      .into_inner()
+      .and_then(|encoder| encoder.finish())
+      .expect("archive could not be written")
+  }
+
+  fn read(path: &Path) -> String {
+    std::fs::read_to_string(path).unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+  }
+
+  #[tokio::test]
+  async fn extracts_archive_tree() {
+    let dir = test_dir("extract");
+    let archive_path = dir.join("pkg.tar.gz");
+    std::fs::write(
+      &archive_path,
+      gzipped_tar(&[
+        ("index.html", "<html></html>"),
+        ("assets/app.js", "console.log(1);"),
+        ("assets/nested/style.css", "body {}"),
+      ]),
     )
-  })?;
+    .unwrap();
+    let out_dir = dir.join("out");
 
-  Ok(())
+    extract_archive(&archive_path, &out_dir)
+      .await
+      .expect("extraction failed");
+
+    assert_eq!(read(&out_dir.join("index.html")), "<html></html>");
+    assert_eq!(read(&out_dir.join("assets/app.js")), "console.log(1);");
+    assert_eq!(read(&out_dir.join("assets/nested/style.css")), "body {}");
+  }
+
+  #[tokio::test]
+  async fn stream_extraction_consumes_whole_source() {
+    let dir = test_dir("stream");
+    let archive = gzipped_tar(&[("index.html", "<html></html>")]);
+
+    let remaining = extract_archive_stream(archive.as_slice(), dir.join("out"))
+      .await
+      .expect("extraction failed");
+
+    assert!(remaining.is_empty());
+    assert_eq!(read(&dir.join("out").join("index.html")), "<html></html>");
+  }
+
+  #[tokio::test]
+  async fn rejects_corrupted_archive() {
+    let dir = test_dir("corrupted");
+    let mut archive = gzipped_tar(&[("index.html", "<html></html>")]);
+    archive.truncate(archive.len() / 2);
+
+    let result = extract_archive_stream(archive.as_slice(), dir.join("out")).await;
+
+    assert!(result.is_err());
+  }
+
+  #[tokio::test]
+  async fn compressed_files_and_directories_round_trip() {
+    let dir = test_dir("round-trip");
+    let logs_dir = dir.join("logs");
+    std::fs::create_dir_all(logs_dir.join("nested")).unwrap();
+    std::fs::write(logs_dir.join("server.log"), "server output").unwrap();
+    std::fs::write(logs_dir.join("nested").join("old.log"), "old output").unwrap();
+    std::fs::write(dir.join("single.log"), "single output").unwrap();
+    let archive_path = dir.join("logs.tar.gz");
+
+    compress_files(&archive_path, &[dir.join("single.log"), logs_dir]).expect("compression failed");
+    let out_dir = dir.join("out");
+    extract_archive(&archive_path, &out_dir)
+      .await
+      .expect("extraction failed");
+
+    assert_eq!(read(&out_dir.join("single.log")), "single output");
+    assert_eq!(read(&out_dir.join("logs/server.log")), "server output");
+    assert_eq!(read(&out_dir.join("logs/nested/old.log")), "old output");
+  }
+
+  #[tokio::test]
+  async fn compression_leaves_only_the_archive() {
+    let dir = test_dir("single-pass");
+    std::fs::write(dir.join("server.log"), "server output").unwrap();
+    let archive_path = dir.join("server.tar.gz");
+
+    compress_files(&archive_path, &[dir.join("server.log")]).expect("compression failed");
+
+    let mut entries: Vec<_> = std::fs::read_dir(&dir)
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name())
+      .collect();
+    entries.sort();
+    assert_eq!(entries, ["server.log", "server.tar.gz"]);
+    extract_archive(&archive_path, &dir.join("out"))
+      .await
+      .expect("extraction failed");
+    assert_eq!(read(&dir.join("out").join("server.log")), "server output");
+  }
+
+  #[tokio::test]
+  async fn detects_gzip_magic_bytes() {
+    let dir = test_dir("magic");
+    std::fs::write(dir.join("pkg.tar.gz"), gzipped_tar(&[("index.html", "")])).unwrap();
+    std::fs::write(dir.join("notes.txt"), "plain text").unwrap();
+    std::fs::write(dir.join("empty"), "").unwrap();
+
+    assert!(is_gzip_archive(dir.join("pkg.tar.gz")).await.unwrap());
+    assert!(!is_gzip_archive(dir.join("notes.txt")).await.unwrap());
+    assert!(!is_gzip_archive(dir.join("empty")).await.unwrap());
+    assert!(is_gzip_archive(dir.join("missing")).await.is_err());
+  }
 }
@@ -0,0 +1,243 @@
+use std::{collections::BTreeMap, net::Ipv4Addr, path::PathBuf};
+
+use clap::{ArgMatches, parser::ValueSource};
+use ipnet::IpNet;
+use serde::Deserialize;
+use thiserror::Error;
+
+use mpv_web_client::UpdateTrack;
+
+use crate::{Args, LogFormat, LogLevel, validate_mime_type};
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+  ip_address: Option<Ipv4Addr>,
+  port: Option<u16>,
+  socket_retries: Option<u8>,
+  port_fallback: Option<bool>,
+  interface: Option<String>,
+  pkg: Option<PathBuf>,
+  serve_dir: Option<PathBuf>,
+  watch: Option<bool>,
+  data_dir: Option<PathBuf>,
+  update: Option<bool>,
+  update_track: Option<UpdateTrack>,
+  force_outdated: Option<bool>,
+  offline: Option<bool>,
+  stream_install: Option<bool>,
+  idle_shutdown_timeout: Option<u32>,
+  enable_idle_shutdown_timeout: Option<bool>,
+  open: Option<bool>,
+  advertise: Option<bool>,
+  qr: Option<bool>,
+  content_security_policy: Option<String>,
+  stream_chunk_size: Option<usize>,
+  min_compression_size: Option<u64>,
+  mime_type: Option<BTreeMap<String, String>>,
+  api_server_recent_output_lines: Option<usize>,
+  api_server_stop_grace_period: Option<u32>,
+  api_server_ready_timeout: Option<u32>,
+  frontend_init_timeout: Option<u32>,
+  max_request_body_size: Option<usize>,
+  log_level: Option<LogLevel>,
+  log_file: Option<PathBuf>,
+  log_format: Option<LogFormat>,
+  api_user: Option<String>,
+  api_password: Option<String>,
+  api_allow: Option<Vec<IpNet>>,
+  shutdown_token: Option<String>,
+  rate_limit: Option<u32>,
+  rate_limit_burst: Option<u32>,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigFileErr {
+  #[error("could not read config file {}: {}", .0.to_string_lossy(), .1)]
+  ReadFailed(PathBuf, #[source] std::io::Error),
+  #[error("could not parse config file {}: {}", .0.to_string_lossy(), .1)]
+  ParseFailed(PathBuf, #[source] toml::de::Error),
+  #[error("invalid config file value: {0}")]
+  InvalidValue(String),
+}
+
+pub fn read_config_file(path: &PathBuf) -> Result<ConfigFile, ConfigFileErr> {
+  let content =
+    std::fs::read_to_string(path).map_err(|err| ConfigFileErr::ReadFailed(path.clone(), err))?;
+  let config: ConfigFile =
+    toml::from_str(&content).map_err(|err| ConfigFileErr::ParseFailed(path.clone(), err))?;
+
+  if config.port == Some(0) {
+    return Err(ConfigFileErr::InvalidValue(
+      "port has to be in range 1..=65535".to_owned(),
+    ));
+  }
+
+  if config.rate_limit == Some(0) || config.rate_limit_burst == Some(0) {
+    return Err(ConfigFileErr::InvalidValue(
+      "rate_limit and rate_limit_burst have to be greater than 0".to_owned(),
+    ));
+  }
+
+  if config.stream_chunk_size == Some(0) {
+    return Err(ConfigFileErr::InvalidValue(
+      "stream_chunk_size has to be greater than 0".to_owned(),
+    ));
+  }
+
+  for (extension, mime_type) in config.mime_type.iter().flatten() {
+    validate_mime_type(extension, mime_type)
+      .map_err(|err| ConfigFileErr::InvalidValue(format!("mime_type.{extension}: {err}")))?;
+  }
+
+  Ok(config)
+}
+
+// Values from the config file are used only for options not provided on the command line.
+macro_rules! merge {
+  ($args:ident, $matches:ident, $config:ident, $field:ident) => {
+    if $matches.value_source(stringify!($field)) != Some(ValueSource::CommandLine)
+      && let Some(value) = $config.$field
+    {
+      $args.$field = value;
+    }
+  };
+  ($args:ident, $matches:ident, $config:ident, $field:ident, optional) => {
+    if $matches.value_source(stringify!($field)) != Some(ValueSource::CommandLine)
+      && $config.$field.is_some()
+    {
+      $args.$field = $config.$field;
+    }
+  };
+}
+
+impl ConfigFile {
+  pub fn merge_into(self, args: &mut Args, matches: &ArgMatches) {
+    let config = self;
+    merge!(args, matches, config, ip_address);
+    merge!(args, matches, config, port, optional);
+    merge!(args, matches, config, socket_retries);
+    merge!(args, matches, config, port_fallback);
+    merge!(args, matches, config, interface, optional);
+    merge!(args, matches, config, pkg, optional);
+    merge!(args, matches, config, serve_dir, optional);
+    merge!(args, matches, config, watch);
+    merge!(args, matches, config, data_dir, optional);
+    merge!(args, matches, config, update);
+    merge!(args, matches, config, update_track);
+    merge!(args, matches, config, force_outdated);
+    merge!(args, matches, config, offline);
+    merge!(args, matches, config, stream_install);
+    merge!(args, matches, config, idle_shutdown_timeout);
+    merge!(args, matches, config, enable_idle_shutdown_timeout);
+    merge!(args, matches, config, open);
+    merge!(args, matches, config, advertise);
+    merge!(args, matches, config, qr);
+    merge!(args, matches, config, content_security_policy, optional);
+    merge!(args, matches, config, stream_chunk_size);
+    merge!(args, matches, config, min_compression_size);
+    if matches.value_source("mime_type") != Some(ValueSource::CommandLine)
+      && let Some(mime_type) = config.mime_type
+    {
+      args.mime_type = mime_type.into_iter().collect();
+    }
+    merge!(args, matches, config, api_server_recent_output_lines);
+    merge!(args, matches, config, api_server_stop_grace_period);
+    merge!(args, matches, config, api_server_ready_timeout);
+    merge!(args, matches, config, frontend_init_timeout);
+    merge!(args, matches, config, max_request_body_size);
+    merge!(args, matches, config, log_level);
+    merge!(args, matches, config, log_file, optional);
+    merge!(args, matches, config, log_format);
+    merge!(args, matches, config, api_user, optional);
+    merge!(args, matches, config, api_password, optional);
+    merge!(args, matches, config, api_allow);
+    merge!(args, matches, config, shutdown_token, optional);
+    merge!(args, matches, config, rate_limit, optional);
+    merge!(args, matches, config, rate_limit_burst, optional);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{net::Ipv4Addr, path::PathBuf};
+
+  use clap::{CommandFactory, FromArgMatches};
+  use mpv_web_client::DEFAULT_SOCKET_RETRIES;
+
+  use super::{ConfigFileErr, read_config_file};
+  use crate::Args;
+
+  fn write_config(name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("mwc-config-{name}-{}.toml", std::process::id()));
+    std::fs::write(&path, content).expect("config file could not be written");
+    path
+  }
+
+  fn args_with_config(config_path: &PathBuf, cli_args: &[&str]) -> Args {
+    let matches = Args::command().get_matches_from(
+      ["mpv-web-client", "--config", config_path.to_str().unwrap()]
+        .iter()
+        .chain(cli_args),
+    );
+    let mut args = Args::from_arg_matches(&matches).unwrap();
+    read_config_file(config_path)
+      .unwrap()
+      .merge_into(&mut args, &matches);
+    args
+  }
+
+  #[test]
+  fn config_values_override_defaults() {
+    let path = write_config(
+      "defaults",
+      "ip_address = \"0.0.0.0\"\nport = 4000\noffline = true\n",
+    );
+
+    let args = args_with_config(&path, &[]);
+
+    assert_eq!(args.ip_address, Ipv4Addr::UNSPECIFIED);
+    assert_eq!(args.port, Some(4000));
+    assert!(args.offline);
+    assert_eq!(args.socket_retries, DEFAULT_SOCKET_RETRIES);
+  }
+
+  #[test]
+  fn command_line_values_override_config() {
+    let path = write_config(
+      "cli",
+      "ip_address = \"0.0.0.0\"\nport = 4000\nsocket_retries = 3\n",
+    );
+
+    let args = args_with_config(&path, &["--port", "5000", "--ip-address", "127.0.0.2"]);
+
+    assert_eq!(args.port, Some(5000));
+    assert_eq!(args.ip_address, Ipv4Addr::new(127, 0, 0, 2));
+    assert_eq!(args.socket_retries, 3);
+  }
+
+  #[test]
+  fn missing_config_file_is_an_error() {
+    let path = std::env::temp_dir().join(format!("mwc-config-missing-{}.toml", std::process::id()));
+
+    assert!(matches!(
+      read_config_file(&path),
+      Err(ConfigFileErr::ReadFailed(..))
+    ));
+  }
+
+  #[test]
+  fn rejects_unknown_and_invalid_values() {
+    let unknown = write_config("unknown", "not_an_option = true\n");
+    assert!(matches!(
+      read_config_file(&unknown),
+      Err(ConfigFileErr::ParseFailed(..))
+    ));
+
+    let invalid = write_config("invalid", "port = 0\n");
+    assert!(matches!(
+      read_config_file(&invalid),
+      Err(ConfigFileErr::InvalidValue(_))
+    ));
+  }
+}
@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::{ListenerError, frontend::FrontendPkgErr};
+
+#[derive(Debug, Error)]
+pub enum Error {
+  #[error("could not prepare project directories: {0}")]
+  ProjectDirs(#[source] std::io::Error),
+  #[error("directory to serve {} does not exist", .0.to_string_lossy())]
+  ServeDirMissing(PathBuf),
+  #[error("frontend init failed: {0}")]
+  FrontendInit(#[from] FrontendPkgErr),
+  #[error("rate limit and its burst must allow at least one request")]
+  InvalidRateLimit,
+  #[error(transparent)]
+  Listener(#[from] ListenerError),
+  #[error("error encountered while serving frontend: {0}")]
+  Serve(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Error;
+  use crate::frontend::{FrontendPkgErr, releases::ReleaseFetchErr};
+
+  fn source_chain(err: &dyn std::error::Error) -> Vec<String> {
+    let mut chain = Vec::new();
+    let mut source = err.source();
+    while let Some(err) = source {
+      chain.push(err.to_string());
+      source = err.source();
+    }
+    chain
+  }
+
+  #[test]
+  fn frontend_init_keeps_source_chain() {
+    let err = Error::from(FrontendPkgErr::RemoteReleaseCheckFailure(
+      ReleaseFetchErr::EmptyDownload,
+    ));
+
+    assert_eq!(
+      source_chain(&err),
+      [
+        FrontendPkgErr::RemoteReleaseCheckFailure(ReleaseFetchErr::EmptyDownload).to_string(),
+        ReleaseFetchErr::EmptyDownload.to_string(),
+      ]
+    );
+  }
+
+  #[test]
+  fn project_dirs_keeps_io_source() {
+    let err = Error::ProjectDirs(std::io::Error::other("disk unavailable"));
+
+    assert_eq!(source_chain(&err), ["disk unavailable"]);
+  }
+}
@@ -1,12 +1,23 @@
-use log::{error, info, warn};
-use std::{fmt::Display, path::PathBuf};
+use clap::ValueEnum;
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use std::{
+  fmt::Display,
+  path::{Path, PathBuf},
+  time::Duration,
+};
+use thiserror::Error;
+use tokio::{fs::remove_file, sync::RwLock, time::timeout};
 
 use crate::{
+  Config,
+  api_servers::local_server_version,
   common::semver::Semver,
   frontend::{
     pkg::repository::PackagesRepository,
     releases::{
       Release, ReleaseFetchErr, Version, fetch_remote_frontend_package_release, get_remote_release,
+      get_remote_releases, stream_remote_frontend_package_release,
     },
   },
 };
@@ -16,40 +27,204 @@ pub mod releases;
 
 pub const DEFAULT_ENTRYPOINT_FILE_NAME: &str = "index.html";
 
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateTrack {
+  Major,
+  Minor,
+  #[default]
+  Any,
+}
+
+impl UpdateTrack {
+  fn allows(&self, local: &Semver, remote: &Semver) -> bool {
+    match self {
+      UpdateTrack::Major => local.major() == remote.major(),
+      UpdateTrack::Minor => local.major() == remote.major() && local.minor() == remote.minor(),
+      UpdateTrack::Any => true,
+    }
+  }
+}
+
+impl Display for UpdateTrack {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      UpdateTrack::Major => write!(f, "major"),
+      UpdateTrack::Minor => write!(f, "minor"),
+      UpdateTrack::Any => write!(f, "any"),
+    }
+  }
+}
+
 pub async fn init_frontend(
-  pkg: Option<PathBuf>,
-  update: bool,
-  force_outdated: bool,
+  config: &Config,
   pkgs_repository: &mut PackagesRepository,
-) -> Result<(), String> {
+) -> Result<(), FrontendPkgErr> {
   pkgs_repository.init().await;
 
-  let mut pkg_path = pkg;
-  if pkg_path.is_none()
-    && let Some(new_release) = remote_frontend_release_available(update, pkgs_repository).await
-  {
-    info!(
-      "fetching new frontend package version \"{}\"",
-      new_release.name
-    );
-    pkg_path = fetch_new_frontend_release(&new_release).await;
+  let force_outdated = config.force_outdated;
+  let network_timeout = Duration::from_secs(config.frontend_init_timeout.into());
+  let user_provided_pkg = config.pkg.is_some();
+  let mut pkg_path = config.pkg.clone();
+  if config.offline && pkg_path.is_none() {
+    info!("running in offline mode - skipping check for remote frontend releases");
+  }
+
+  if pkg_path.is_none() && !config.offline {
+    let network_phase = async {
+      let new_release = remote_frontend_release_available(
+        &config.releases_url,
+        config.update,
+        config.update_track,
+        pkgs_repository,
+      )
+      .await?;
+      info!(
+        "fetching new frontend package version \"{}\"",
+        new_release.name
+      );
+      if config.stream_install {
+        stream_new_frontend_release(&new_release, pkgs_repository.frontend_temp_dir())
+          .await
+          .then_some(FetchedPackage::Extracted)
+      } else {
+        fetch_new_frontend_release(&new_release, pkgs_repository.temp_dir())
+          .await
+          .map(FetchedPackage::Archive)
+      }
+    };
+
+    // a hanging remote must not keep the server from serving the installed package
+    match timeout(network_timeout, network_phase).await {
+      Ok(Some(FetchedPackage::Archive(path))) => pkg_path = Some(path),
+      Ok(Some(FetchedPackage::Extracted)) => {
+        if let Err(err) = pkgs_repository
+          .install_extracted_package(force_outdated)
+          .await
+        {
+          error!("install of streamed frontend package failed: {err}");
+        }
+      }
+      Ok(None) => {}
+      Err(_) => warn!(
+        "check and fetch of remote frontend releases did not finish within {} seconds - proceeding with the installed package",
+        network_timeout.as_secs()
+      ),
+    }
   }
 
   if let Some(path) = pkg_path {
     pkgs_repository
-      .install_package(path, force_outdated)
-      .await
-      .map_err(|err| format!("frontend package install failed: {err}"))?;
+      .install_package(path.clone(), force_outdated)
+      .await?;
+
+    if !user_provided_pkg {
+      remove_fetched_package(&path).await;
+    }
+  }
+
+  check_frontend_pkg(pkgs_repository).await
+}
+
+pub async fn reload_frontend(
+  update_track: UpdateTrack,
+  force_outdated: bool,
+  offline: bool,
+  stream_install: bool,
+  releases_url: &str,
+  pkgs_repository: &RwLock<PackagesRepository>,
+) {
+  pkgs_repository.write().await.init().await;
+  if offline {
+    info!("running in offline mode - skipping check for remote frontend releases");
+  } else {
+    // the repository is locked for writing only for the install, so files are served during the fetch
+    let new_release = remote_frontend_release_available(
+      releases_url,
+      true,
+      update_track,
+      &*pkgs_repository.read().await,
+    )
+    .await;
+    if let Some(new_release) = new_release {
+      info!(
+        "fetching new frontend package version \"{}\"",
+        new_release.name
+      );
+      let (temp_dir, frontend_temp_dir) = {
+        let pkgs_repository = pkgs_repository.read().await;
+        (
+          pkgs_repository.temp_dir(),
+          pkgs_repository.frontend_temp_dir(),
+        )
+      };
+      if stream_install {
+        match stream_remote_frontend_package_release(&new_release, frontend_temp_dir).await {
+          Ok(()) => {
+            if let Err(err) = pkgs_repository
+              .write()
+              .await
+              .install_extracted_package(force_outdated)
+              .await
+            {
+              error!("install of streamed frontend package failed: {err}");
+            }
+          }
+          Err(err) => error!("streaming of remote frontend package failed: {err}"),
+        }
+      } else if let Some(path) = fetch_new_frontend_release(&new_release, temp_dir).await {
+        match pkgs_repository
+          .write()
+          .await
+          .install_package(path.clone(), force_outdated)
+          .await
+        {
+          Ok(()) => remove_fetched_package(&path).await,
+          Err(err) => error!("frontend package install failed: {err}"),
+        }
+      }
+    }
+  }
+
+  let pkgs_repository = pkgs_repository.read().await;
+  match check_frontend_pkg(&pkgs_repository).await {
+    Ok(()) => match pkgs_repository.get_installed() {
+      Ok(pkg) => info!(
+        "frontend reloaded - serving version \"{}\"",
+        pkg.manifest.version_info.version
+      ),
+      Err(err) => error!("frontend reload failed: {err}"),
+    },
+    Err(err) => error!("frontend reload failed: {err}"),
+  }
+}
+
+enum FetchedPackage {
+  Archive(PathBuf),
+  Extracted,
+}
+
+async fn stream_new_frontend_release(new_release: &Release, out_dir: PathBuf) -> bool {
+  match stream_remote_frontend_package_release(new_release, out_dir).await {
+    Ok(()) => true,
+    Err(err) => {
+      error!("streaming of remote frontend package failed: {err}");
+      false
+    }
   }
+}
 
-  match check_frontend_pkg(pkgs_repository).await {
-    Ok(_) => Ok(()),
-    Err(err) => Err(format!("frontend init failed: {err}")),
+pub async fn remove_fetched_package(path: &Path) {
+  if let Err(err) = remove_file(path).await {
+    warn!(
+      "could not remove fetched frontend package {}: {err}",
+      path.to_string_lossy()
+    );
   }
 }
 
-async fn fetch_new_frontend_release(new_release: &Release) -> Option<PathBuf> {
-  match fetch_remote_frontend_package_release(new_release).await {
+async fn fetch_new_frontend_release(new_release: &Release, temp_dir: PathBuf) -> Option<PathBuf> {
+  match fetch_remote_frontend_package_release(new_release, temp_dir).await {
     Ok(path_pkg) => Some(path_pkg),
     Err(err) => {
       error!("fetch of remote frontend package failed: {err}");
@@ -59,10 +234,12 @@ async fn fetch_new_frontend_release(new_release: &Release) -> Option<PathBuf> {
 }
 
 async fn remote_frontend_release_available(
+  releases_url: &str,
   allow_updates: bool,
+  update_track: UpdateTrack,
   pkgs_repository: &PackagesRepository,
 ) -> Option<Release> {
-  match check_for_newer_remote_release(pkgs_repository).await {
+  match check_for_newer_remote_release(releases_url, pkgs_repository, update_track).await {
     Ok(result) => match result {
       RemoteReleaseCheckResult::UpToDate(local) => {
         info!("local frontend version \"{local}\" is up to date");
@@ -101,12 +278,40 @@ pub async fn check_frontend_pkg(pkgs_repo: &PackagesRepository) -> Result<(), Fr
   let frontend_entrypoint_path = frontend_entrypoint
     .as_deref()
     .unwrap_or(DEFAULT_ENTRYPOINT_FILE_NAME);
-  match pkgs_repo.get_installed_file(frontend_entrypoint_path).await {
-    Ok(_) => Ok(()),
-    Err(err) => Err(FrontendPkgErr::EntrypointNotFound(format!(
+  if let Err(err) = pkgs_repo.get_installed_file(frontend_entrypoint_path).await {
+    return Err(FrontendPkgErr::EntrypointNotFound(format!(
       "entrypoint file {frontend_entrypoint_path} does not exist in project home directory: {err}"
-    ))),
+    )));
+  }
+
+  // the frontend can still be served, only the api servers spawned for it may misbehave
+  if let Err(err) = check_api_compatibility(pkgs_repo).await {
+    warn!("{err}");
+  }
+  Ok(())
+}
+
+pub async fn check_api_compatibility(pkgs_repo: &PackagesRepository) -> Result<(), FrontendPkgErr> {
+  let Some(required) = pkgs_repo
+    .get_installed()?
+    .manifest
+    .version_info
+    .min_api_version
+  else {
+    return Ok(());
+  };
+  let Some(available) = local_server_version().await else {
+    debug!("version of local api server is unknown - skipping compatibility check");
+    return Ok(());
+  };
+
+  if available < required {
+    return Err(FrontendPkgErr::ApiVersionUnsupported {
+      required,
+      available,
+    });
   }
+  Ok(())
 }
 
 enum RemoteReleaseCheckResult {
@@ -115,25 +320,36 @@ enum RemoteReleaseCheckResult {
   RemoteNecessary(Release),
 }
 async fn check_for_newer_remote_release(
+  releases_url: &str,
   pkgs_repo: &PackagesRepository,
+  update_track: UpdateTrack,
 ) -> Result<RemoteReleaseCheckResult, FrontendPkgErr> {
-  let release = get_remote_release(Version::Latest)
-    .await
-    .map_err(FrontendPkgErr::RemoteReleaseCheckFailure)?;
-
-  info!(
-    "the latest remote frontend version is \"{}\"",
-    release.version
-  );
-  let remote_version = release.version;
   let local_version = match pkgs_repo.get_installed() {
     Ok(installed) => installed.manifest.version_info.version,
     Err(_) => {
       warn!("could not infer local frontend package version");
+      let release = get_remote_release(releases_url, Version::Latest)
+        .await
+        .map_err(FrontendPkgErr::RemoteReleaseCheckFailure)?;
       return Ok(RemoteReleaseCheckResult::RemoteNecessary(release));
     }
   };
 
+  let release = match update_track {
+    UpdateTrack::Any => get_remote_release(releases_url, Version::Latest)
+      .await
+      .map_err(FrontendPkgErr::RemoteReleaseCheckFailure)?,
+    track => match get_latest_remote_release_in_track(releases_url, &local_version, track).await? {
+      Some(release) => release,
+      None => return Ok(RemoteReleaseCheckResult::UpToDate(local_version)),
+    },
+  };
+
+  info!(
+    "the latest remote frontend version in \"{update_track}\" update track is \"{}\"",
+    release.version
+  );
+  let remote_version = release.version;
   if local_version >= remote_version {
     Ok(RemoteReleaseCheckResult::UpToDate(local_version))
   } else {
@@ -144,46 +360,86 @@ async fn check_for_newer_remote_release(
   }
 }
 
+async fn get_latest_remote_release_in_track(
+  releases_url: &str,
+  local_version: &Semver,
+  update_track: UpdateTrack,
+) -> Result<Option<Release>, FrontendPkgErr> {
+  let releases = get_remote_releases(releases_url)
+    .await
+    .map_err(FrontendPkgErr::RemoteReleaseCheckFailure)?;
+
+  Ok(
+    releases
+      .into_iter()
+      .find(|release| update_track.allows(local_version, &release.version)),
+  )
+}
+
+#[derive(Debug, Error)]
 pub enum FrontendPkgErr {
+  #[error("frontend cannot be served due to lack of an entrypoint file: {0}")]
   EntrypointNotFound(String),
+  #[error("package install failed: {0}")]
   PkgInstallFailed(String),
+  #[error("frontend package could not be unpacked: {0}")]
   PkgUnpackErr(String),
+  #[error("provided pkg file is invalid: {0}")]
   PkgInvalid(String),
+  #[error(
+    "provided frontend package has outdated version \"{0}\" compared to currently installed version \"{1}\""
+  )]
   PkgOutdated(String, String),
+  #[error("frontend package manifest is in incorrect format: {0}")]
   ManifestInvalid(String),
+  #[error("frontend package manifest is not a valid toml document: {0}")]
+  ManifestSyntaxInvalid(String),
+  #[error(
+    "installed frontend requires mpv-web-api version {required} or newer, but version {available} is available"
+  )]
+  ApiVersionUnsupported { required: Semver, available: Semver },
+  #[error("frontend package is not available: {0}")]
   PackageUnavailable(String),
-  HomeDirInaccessible(std::io::Error),
-  RemoteReleaseCheckFailure(ReleaseFetchErr),
+  #[error("requested file {} is a directory", .0.to_string_lossy())]
+  FileIsDirectory(PathBuf),
+  #[error("requested file {} points outside of the served directory", .0.to_string_lossy())]
+  FileOutsideDir(PathBuf),
+  #[error("the program could not read it's home directory: {0}")]
+  HomeDirInaccessible(#[source] std::io::Error),
+  #[error("check for the latest version failed: {0}")]
+  RemoteReleaseCheckFailure(#[source] ReleaseFetchErr),
 }
 
-impl Display for FrontendPkgErr {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    match self {
-      FrontendPkgErr::PkgInvalid(err) => write!(f, "provided pkg file is invalid: {err}"),
-      FrontendPkgErr::PkgInstallFailed(err) => write!(f, "package install failed: {err}"),
-      FrontendPkgErr::EntrypointNotFound(error) => write!(
-        f,
-        "frontend cannot be served due to lack of an entrypoint file: {error}"
-      ),
-      FrontendPkgErr::HomeDirInaccessible(error) => {
-        write!(f, "the program could not read it's home directory: {error}")
-      }
-      FrontendPkgErr::PkgUnpackErr(error) => {
-        write!(f, "frontend package could not be unpacked: {error}")
-      }
-      FrontendPkgErr::PkgOutdated(tmp_version, home_version) => write!(
-        f,
-        "provided frontend package has outdated version \"{tmp_version}\" compared to currently installed version \"{home_version}\""
-      ),
-      FrontendPkgErr::ManifestInvalid(msg) => {
-        write!(f, "frontend package manifest is in incorrect format: {msg}")
-      }
-      FrontendPkgErr::PackageUnavailable(msg) => {
-        write!(f, "frontend package is not available: {msg}")
-      }
-      FrontendPkgErr::RemoteReleaseCheckFailure(err) => {
-        write!(f, "check for the latest version failed: {err}")
-      }
-    }
+#[cfg(test)]
+mod tests {
+  use super::UpdateTrack;
+  use crate::common::semver::Semver;
+
+  fn version(version: &str) -> Semver {
+    version.parse().unwrap()
+  }
+
+  #[test]
+  fn major_track_allows_only_same_major() {
+    let local = version("1.2.3");
+    assert!(UpdateTrack::Major.allows(&local, &version("1.9.0")));
+    assert!(UpdateTrack::Major.allows(&local, &version("1.2.4")));
+    assert!(!UpdateTrack::Major.allows(&local, &version("2.0.0")));
+    assert!(!UpdateTrack::Major.allows(&local, &version("0.9.0")));
+  }
+
+  #[test]
+  fn minor_track_allows_only_same_major_and_minor() {
+    let local = version("1.2.3");
+    assert!(UpdateTrack::Minor.allows(&local, &version("1.2.9")));
+    assert!(!UpdateTrack::Minor.allows(&local, &version("1.3.0")));
+    assert!(!UpdateTrack::Minor.allows(&local, &version("2.2.0")));
+  }
+
+  #[test]
+  fn any_track_allows_every_version() {
+    let local = version("1.2.3");
+    assert!(UpdateTrack::Any.allows(&local, &version("1.2.4")));
+    assert!(UpdateTrack::Any.allows(&local, &version("5.0.0")));
   }
 }
@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::path::Path;
 use tokio::{fs::OpenOptions, io::AsyncReadExt};
 
@@ -6,18 +6,37 @@ use crate::{common::semver::Semver, frontend::FrontendPkgErr};
 
 pub const PKG_MANIFEST_NAME: &str = "pkg_manifest.toml";
 
-#[derive(Deserialize, PartialEq, Clone)]
+#[derive(Serialize, PartialEq, Clone)]
 pub struct VersionInfo {
   pub version: Semver,
   pub commit: String,
   pub entrypoint: Option<String>,
+  pub build_time: Option<String>,
+  pub min_api_version: Option<Semver>,
+  pub title: Option<String>,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Clone)]
 pub struct Manifest {
   pub version_info: VersionInfo,
 }
 
+// every field is optional so that missing ones can be reported by their full name
+#[derive(Deserialize)]
+struct RawVersionInfo {
+  version: Option<String>,
+  commit: Option<String>,
+  entrypoint: Option<String>,
+  build_time: Option<String>,
+  min_api_version: Option<String>,
+  title: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawManifest {
+  version_info: Option<RawVersionInfo>,
+}
+
 pub async fn parse_package_manifest<T>(path: T) -> Result<Manifest, FrontendPkgErr>
 where
   T: AsRef<Path>,
@@ -41,8 +60,55 @@ where
     .read_to_string(&mut toml_content)
     .await
     .map_err(|err| FrontendPkgErr::ManifestInvalid(err.to_string()))?;
-  let manifest: Manifest = toml::from_str(toml_content.as_ref())
-    .map_err(|err| FrontendPkgErr::ManifestInvalid(err.to_string()))?;
+  let table: toml::Table = toml::from_str(toml_content.as_ref())
+    .map_err(|err| FrontendPkgErr::ManifestSyntaxInvalid(err.to_string()))?;
+  let raw_manifest = RawManifest::deserialize(table)
+    .map_err(|err| FrontendPkgErr::ManifestInvalid(err.message().to_owned()))?;
+
+  validate_manifest(raw_manifest)
+}
+
+fn validate_manifest(raw_manifest: RawManifest) -> Result<Manifest, FrontendPkgErr> {
+  let raw_version_info = raw_manifest
+    .version_info
+    .ok_or_else(|| missing_field_err("version_info"))?;
+  let raw_version = raw_version_info
+    .version
+    .ok_or_else(|| missing_field_err("version_info.version"))?;
+  let version: Semver = raw_version.parse().map_err(|err| {
+    FrontendPkgErr::ManifestInvalid(format!(
+      "manifest field version_info.version \"{raw_version}\" is not a valid version: {err}"
+    ))
+  })?;
+  let commit = raw_version_info
+    .commit
+    .ok_or_else(|| missing_field_err("version_info.commit"))?;
+  let entrypoint = raw_version_info
+    .entrypoint
+    .filter(|entrypoint| !entrypoint.trim().is_empty());
+  let min_api_version = raw_version_info
+    .min_api_version
+    .map(|raw_min_api_version| {
+      raw_min_api_version.parse::<Semver>().map_err(|err| {
+        FrontendPkgErr::ManifestInvalid(format!(
+          "manifest field version_info.min_api_version \"{raw_min_api_version}\" is not a valid version: {err}"
+        ))
+      })
+    })
+    .transpose()?;
+
+  Ok(Manifest {
+    version_info: VersionInfo {
+      version,
+      commit,
+      entrypoint,
+      build_time: raw_version_info.build_time,
+      min_api_version,
+      title: raw_version_info.title,
+    },
+  })
+}
 
-  Ok(manifest)
+fn missing_field_err(field: &str) -> FrontendPkgErr {
+  FrontendPkgErr::ManifestInvalid(format!("manifest missing required field {field}"))
 }
@@ -1,18 +1,21 @@
 use std::{
   fs::create_dir_all,
-  path::{Path, PathBuf},
+  path::{Component, Path, PathBuf},
 };
 
-use log::{debug, info, warn};
+use log::{debug, error, info, warn};
 use tokio::fs::{remove_dir_all, rename};
 
 use crate::{
-  common::{semver::Semver, tarflate::extract_archive},
+  common::{
+    semver::Semver,
+    tarflate::{extract_archive, is_gzip_archive},
+  },
   frontend::{
-    FrontendPkgErr,
+    DEFAULT_ENTRYPOINT_FILE_NAME, FrontendPkgErr,
     pkg::manifest::{Manifest, PKG_MANIFEST_NAME, parse_package_manifest},
   },
-  project_paths::{get_frontend_dir, get_frontend_temp_dir, get_project_home_dir},
+  project_paths::{get_frontend_dir, get_frontend_temp_dir, get_temp_dir},
 };
 
 #[derive(Clone)]
@@ -21,18 +24,28 @@ pub struct Package {
 }
 
 pub struct PackagesRepository {
+  home_dir: PathBuf,
   installed: Option<Package>,
   temp: Option<Package>,
 }
 
 impl PackagesRepository {
-  pub fn new() -> Self {
+  pub fn new(home_dir: PathBuf) -> Self {
     PackagesRepository {
+      home_dir,
       installed: None,
       temp: None,
     }
   }
 
+  pub fn temp_dir(&self) -> PathBuf {
+    get_temp_dir(&self.home_dir)
+  }
+
+  pub fn frontend_temp_dir(&self) -> PathBuf {
+    get_frontend_temp_dir(&self.home_dir)
+  }
+
   pub async fn init(&mut self) {
     if let Err(err) = self.check_installed().await {
       debug!("initial installed package check unsuccessful: {err}");
@@ -57,9 +70,8 @@ impl PackagesRepository {
     }
   }
 
-  async fn check_installed(&mut self) -> Result<Package, FrontendPkgErr> {
-    let mut path = get_project_home_dir().map_err(FrontendPkgErr::HomeDirInaccessible)?;
-    path.push(PKG_MANIFEST_NAME);
+  pub async fn check_installed(&mut self) -> Result<Package, FrontendPkgErr> {
+    let path = self.home_dir.join(PKG_MANIFEST_NAME);
     match parse_package_manifest(path).await {
       Ok(m) => {
         let package = Package { manifest: m };
@@ -71,8 +83,7 @@ impl PackagesRepository {
   }
 
   async fn check_temp(&mut self) -> Result<Package, FrontendPkgErr> {
-    let mut path = get_frontend_temp_dir();
-    path.push(PKG_MANIFEST_NAME);
+    let path = self.frontend_temp_dir().join(PKG_MANIFEST_NAME);
     match parse_package_manifest(path).await {
       Ok(m) => {
         let package = Package { manifest: m };
@@ -91,26 +102,61 @@ impl PackagesRepository {
   where
     T: AsRef<Path> + From<PathBuf> + Send + Sync + 'static,
   {
-    tokio::task::spawn_blocking(|| extract_archive(pkg_path, get_frontend_temp_dir().into()))
+    self.unpack_package(pkg_path).await?;
+    self.install_extracted_package(force_outdated).await
+  }
+
+  // extracts the package to the temp dir, from which install_extracted_package picks it up
+  pub async fn unpack_package<T>(&mut self, pkg_path: T) -> Result<(), FrontendPkgErr>
+  where
+    T: AsRef<Path> + From<PathBuf> + Send + Sync + 'static,
+  {
+    match is_gzip_archive(&pkg_path).await {
+      Ok(true) => {}
+      Ok(false) => {
+        return Err(FrontendPkgErr::PkgInvalid("not a gzip archive".to_owned()));
+      }
+      Err(err) => {
+        return Err(FrontendPkgErr::PkgInvalid(format!(
+          "could not read package file {}: {err}",
+          pkg_path.as_ref().to_string_lossy()
+        )));
+      }
+    };
+
+    // leftovers of an earlier unpack must not be mixed into this package
+    let frontend_temp_dir = self.frontend_temp_dir();
+    if frontend_temp_dir.exists()
+      && let Err(err) = remove_dir_all(&frontend_temp_dir).await
+    {
+      return Err(FrontendPkgErr::PkgInstallFailed(format!(
+        "could not clear the temporary frontend directory at path {}: {err}",
+        frontend_temp_dir.to_string_lossy()
+      )));
+    }
+    self.temp = None;
+
+    extract_archive(pkg_path, frontend_temp_dir.into())
       .await
-      .map_err(|e| {
-        FrontendPkgErr::PkgInstallFailed(format!(
-          "issue with joining on blocking task for frontend extraction: {e}"
-        ))
-      })?
-      .map_err(FrontendPkgErr::PkgUnpackErr)?;
-    let temp_version = self.check_temp().await?.manifest.version_info.version;
+      .map_err(FrontendPkgErr::PkgUnpackErr)
+  }
+
+  pub async fn install_extracted_package(
+    &mut self,
+    force_outdated: bool,
+  ) -> Result<(), FrontendPkgErr> {
+    let temp_version_info = self.check_temp().await?.manifest.version_info;
+    let temp_version = temp_version_info.version;
+    let temp_entrypoint = temp_version_info
+      .entrypoint
+      .unwrap_or_else(|| DEFAULT_ENTRYPOINT_FILE_NAME.to_owned());
 
     match self.check_temp_pkg_manifest_against_installed_one().await {
       Ok(()) => {}
       Err(err) => {
         match &err {
-          FrontendPkgErr::PkgOutdated(provided_version, served_version) => {
-            if force_outdated {
-              info!("forcing outdated version \"{provided_version}\" over \"{served_version}\"");
-            } else {
-              return Err(err);
-            }
+          FrontendPkgErr::PkgOutdated(provided_version, served_version) if force_outdated => {
+            info!("forcing outdated version \"{provided_version}\" over \"{served_version}\"");
           }
           _ => {
             return Err(err);
@@ -119,15 +165,25 @@ impl PackagesRepository {
       }
     };
 
-    tokio::task::spawn_blocking(move || copy_frontend_pkg_to_home(&temp_version))
-      .await
-      .map_err(|e| {
-        FrontendPkgErr::PkgInstallFailed(format!(
-          "issue with joining on blocking task for frontend move: {e}"
-        ))
-      })??;
+    let frontend_dir = get_frontend_dir(&self.home_dir);
+    let frontend_temp_dir = self.frontend_temp_dir();
+    let copy_source_dir = frontend_temp_dir.clone();
+    let copy_result = tokio::task::spawn_blocking(move || {
+      copy_frontend_pkg_to_home(
+        &copy_source_dir,
+        &frontend_dir,
+        &temp_version,
+        &temp_entrypoint,
+      )
+    })
+    .await
+    .map_err(|e| {
+      FrontendPkgErr::PkgInstallFailed(format!(
+        "issue with joining on blocking task for frontend move: {e}"
+      ))
+    });
 
-    let frontend_temp_dir = get_frontend_temp_dir();
+    // a failed install must not leave files behind that would be mixed into the next one
     if let Err(e) = remove_dir_all(&frontend_temp_dir).await {
       warn!(
         "could not remove the temporary frontend directory at path {}: reason: {e}",
@@ -135,8 +191,9 @@ impl PackagesRepository {
       );
     };
     self.temp = None;
+    copy_result??;
 
-    move_manifest_to_project_home(&temp_version).await?;
+    move_manifest_to_project_home(&self.home_dir, &temp_version).await?;
     self.check_installed().await?;
 
     Ok(())
@@ -149,7 +206,7 @@ impl PackagesRepository {
   where
     T: AsRef<Path>,
   {
-    let mut src_path = get_frontend_dir().map_err(FrontendPkgErr::HomeDirInaccessible)?;
+    let mut src_path = get_frontend_dir(&self.home_dir);
     let version = self
       .get_installed()?
       .manifest
@@ -157,19 +214,8 @@ impl PackagesRepository {
       .version
       .to_string();
     src_path.push(version);
-    src_path.push(name);
-
-    let src_file_open_result = tokio::fs::OpenOptions::default()
-      .create(false)
-      .read(true)
-      .write(false)
-      .open(&src_path)
-      .await;
-
-    match src_file_open_result {
-      Ok(src_file) => Ok((src_file, src_path)),
-      Err(err) => Err(FrontendPkgErr::HomeDirInaccessible(err)),
-    }
+
+    open_file_in_dir(&src_path, name).await
   }
 
   async fn check_temp_pkg_manifest_against_installed_one(&mut self) -> Result<(), FrontendPkgErr> {
@@ -192,19 +238,82 @@ impl PackagesRepository {
   }
 }
 
-fn copy_frontend_pkg_to_home(version: &Semver) -> Result<(), FrontendPkgErr> {
-  let frontend_temp_dir = get_frontend_temp_dir();
-  let mut install_frontend_dir = get_frontend_dir().map_err(FrontendPkgErr::HomeDirInaccessible)?;
-  install_frontend_dir.push(version.to_string());
+const STAGING_DIR_SUFFIX: &str = ".staging";
+const REPLACED_DIR_SUFFIX: &str = ".replaced";
+// the package is copied next to the installed versions first and swapped in only when complete,
+// so a failure at any point leaves the previously installed version serving
+fn copy_frontend_pkg_to_home(
+  frontend_temp_dir: &Path,
+  frontend_dir: &Path,
+  version: &Semver,
+  entrypoint: &str,
+) -> Result<(), FrontendPkgErr> {
+  let install_frontend_dir = frontend_dir.join(version.to_string());
+  let staging_dir = frontend_dir.join(format!("{version}{STAGING_DIR_SUFFIX}"));
+  let replaced_dir = frontend_dir.join(format!("{version}{REPLACED_DIR_SUFFIX}"));
+
+  for leftover_dir in [&staging_dir, &replaced_dir] {
+    if leftover_dir.exists() {
+      remove_dir_all_logged(leftover_dir);
+    }
+  }
+
+  if let Err(err) = copy_frontend_pkg_to_dir(frontend_temp_dir, &staging_dir, entrypoint) {
+    remove_dir_all_logged(&staging_dir);
+    return Err(err);
+  }
+
+  let replaces_existing = install_frontend_dir.exists();
+  if replaces_existing {
+    std::fs::rename(&install_frontend_dir, &replaced_dir).map_err(|err| {
+      remove_dir_all_logged(&staging_dir);
+      FrontendPkgErr::PkgInstallFailed(format!(
+        "could not move aside the existing install dir {}: {err}",
+        install_frontend_dir.to_string_lossy()
+      ))
+    })?;
+  }
+
+  if let Err(err) = std::fs::rename(&staging_dir, &install_frontend_dir) {
+    if replaces_existing && let Err(err) = std::fs::rename(&replaced_dir, &install_frontend_dir) {
+      error!(
+        "could not restore the previous install dir {}: {err}",
+        install_frontend_dir.to_string_lossy()
+      );
+    }
+    remove_dir_all_logged(&staging_dir);
+    return Err(FrontendPkgErr::PkgInstallFailed(format!(
+      "could not move the staged package to {}: {err}",
+      install_frontend_dir.to_string_lossy()
+    )));
+  }
 
-  for entry_result in walkdir::WalkDir::new(&frontend_temp_dir) {
+  if replaces_existing {
+    remove_dir_all_logged(&replaced_dir);
+  }
+  Ok(())
+}
+
+fn remove_dir_all_logged(dir: &Path) {
+  if let Err(err) = std::fs::remove_dir_all(dir) {
+    warn!(
+      "could not remove the directory {}: {err}",
+      dir.to_string_lossy()
+    );
+  }
+}
+
+fn copy_frontend_pkg_to_dir(
+  frontend_temp_dir: &Path,
+  install_frontend_dir: &Path,
+  entrypoint: &str,
+) -> Result<(), FrontendPkgErr> {
+  for entry_result in walkdir::WalkDir::new(frontend_temp_dir) {
     let entry = entry_result.map_err(|err| {
       FrontendPkgErr::PkgInstallFailed(format!("could not walk through frontend temp dir: {err}"))
     })?;
 
-    let mut tgt_path = install_frontend_dir.clone();
-    let stripped_path = entry.path().strip_prefix(&frontend_temp_dir).unwrap();
-    tgt_path.push(stripped_path);
+    let tgt_path = install_path(frontend_temp_dir, install_frontend_dir, entry.path())?;
     if entry.file_type().is_dir() {
       create_dir_all(&tgt_path).map_err(|err| {
         FrontendPkgErr::PkgInstallFailed(format!(
@@ -217,23 +326,149 @@ fn copy_frontend_pkg_to_home(version: &Semver) -> Result<(), FrontendPkgErr> {
     }
   }
 
+  if !install_frontend_dir.join(entrypoint).is_file() {
+    return Err(FrontendPkgErr::PkgInvalid(format!(
+      "package does not contain its entrypoint file {entrypoint}"
+    )));
+  }
   Ok(())
 }
 
-async fn move_manifest_to_project_home(version: &Semver) -> Result<(), FrontendPkgErr> {
-  let mut frontend_dir = get_frontend_dir().map_err(FrontendPkgErr::HomeDirInaccessible)?;
+fn install_path(
+  frontend_temp_dir: &Path,
+  install_frontend_dir: &Path,
+  entry_path: &Path,
+) -> Result<PathBuf, FrontendPkgErr> {
+  let stripped_path = entry_path.strip_prefix(frontend_temp_dir).map_err(|err| {
+    FrontendPkgErr::PkgUnpackErr(format!(
+      "unpacked entry {} is outside of the frontend temp dir: {err}",
+      entry_path.to_string_lossy()
+    ))
+  })?;
+
+  Ok(install_frontend_dir.join(stripped_path))
+}
+
+async fn move_manifest_to_project_home(
+  home_dir: &Path,
+  version: &Semver,
+) -> Result<(), FrontendPkgErr> {
+  let mut frontend_dir = get_frontend_dir(home_dir);
   frontend_dir.push(version.to_string());
   let manifest_file_path = {
     let mut path = frontend_dir.clone();
     path.push(PKG_MANIFEST_NAME);
     path
   };
-  let new_manifest_file_path = {
-    let mut path = get_project_home_dir().map_err(FrontendPkgErr::HomeDirInaccessible)?;
-    path.push(PKG_MANIFEST_NAME);
-    path
-  };
+  let new_manifest_file_path = home_dir.join(PKG_MANIFEST_NAME);
   rename(manifest_file_path, new_manifest_file_path)
     .await
     .map_err(FrontendPkgErr::HomeDirInaccessible)
 }
+
+// only plain relative names stay within the directory they are joined to
+pub fn is_contained_path(name: &Path) -> bool {
+  name
+    .components()
+    .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+// directories are not served - callers fall back to the entrypoint instead
+pub async fn open_file_in_dir<T>(
+  dir: &Path,
+  name: T,
+) -> Result<(tokio::fs::File, PathBuf), FrontendPkgErr>
+where
+  T: AsRef<Path>,
+{
+  if !is_contained_path(name.as_ref()) {
+    return Err(FrontendPkgErr::FileOutsideDir(name.as_ref().to_path_buf()));
+  }
+
+  let src_path = dir.join(name);
+  let src_meta = tokio::fs::metadata(&src_path)
+    .await
+    .map_err(FrontendPkgErr::HomeDirInaccessible)?;
+  if src_meta.is_dir() {
+    return Err(FrontendPkgErr::FileIsDirectory(src_path));
+  }
+
+  let src_file_open_result = tokio::fs::OpenOptions::default()
+    .create(false)
+    .read(true)
+    .write(false)
+    .open(&src_path)
+    .await;
+
+  match src_file_open_result {
+    Ok(src_file) => Ok((src_file, src_path)),
+    Err(err) => Err(FrontendPkgErr::HomeDirInaccessible(err)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn fixture_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mwc-repository-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    create_dir_all(dir.join("assets")).unwrap();
+    std::fs::write(dir.join("assets").join("app.js"), "fixture").unwrap();
+    dir
+  }
+
+  #[test]
+  fn entry_outside_temp_dir_is_rejected() {
+    let temp_dir = Path::new("/data/temp/frontend");
+    let install_dir = Path::new("/data/frontend/1.0.0");
+
+    let result = install_path(temp_dir, install_dir, Path::new("/data/other/index.html"));
+
+    match result {
+      Err(FrontendPkgErr::PkgUnpackErr(msg)) => {
+        assert!(msg.contains("/data/other/index.html"), "{msg}")
+      }
+      other => panic!("expected PkgUnpackErr, got {other:?}"),
+    }
+    assert_eq!(
+      install_path(temp_dir, install_dir, &temp_dir.join("js/app.js")).unwrap(),
+      install_dir.join("js/app.js")
+    );
+  }
+
+  #[test]
+  fn symlinked_entry_is_not_copied() {
+    let root = fixture_dir("symlink");
+    let temp_dir = root.join("temp");
+    let frontend_dir = root.join("frontend");
+    create_dir_all(&temp_dir).unwrap();
+    create_dir_all(&frontend_dir).unwrap();
+    std::fs::write(temp_dir.join("index.html"), "fixture").unwrap();
+    std::os::unix::fs::symlink(root.join("assets"), temp_dir.join("linked")).unwrap();
+    let version: Semver = "1.0.0".parse().unwrap();
+
+    copy_frontend_pkg_to_home(&temp_dir, &frontend_dir, &version, "index.html").unwrap();
+
+    let install_dir = frontend_dir.join("1.0.0");
+    assert!(install_dir.join("index.html").is_file());
+    assert!(!install_dir.join("linked").exists());
+  }
+
+  #[tokio::test]
+  async fn directory_is_not_opened_as_file() {
+    let dir = fixture_dir("directory");
+
+    let result = open_file_in_dir(&dir, "assets").await;
+
+    match result {
+      Err(FrontendPkgErr::FileIsDirectory(path)) => assert_eq!(path, dir.join("assets")),
+      other => panic!(
+        "expected FileIsDirectory, got {:?}",
+        other.map(|(_, path)| path)
+      ),
+    }
+    let (_, path) = open_file_in_dir(&dir, "assets/app.js").await.unwrap();
+    assert_eq!(path, dir.join("assets").join("app.js"));
+  }
+}
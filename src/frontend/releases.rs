@@ -1,17 +1,36 @@
-use std::{fmt::Display, path::PathBuf};
+use std::{
+  fmt::Display,
+  io::ErrorKind,
+  path::{Path, PathBuf},
+  sync::{
+    LazyLock,
+    atomic::{AtomicUsize, Ordering},
+  },
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures::{StreamExt, stream::unfold};
 
-use hyper::StatusCode;
-use reqwest::{Client, IntoUrl, Request};
+use hyper::{
+  StatusCode,
+  header::{CONTENT_RANGE, HeaderValue, LINK, RANGE, RETRY_AFTER},
+};
+use log::{debug, info, warn};
+use reqwest::{Client, IntoUrl, Request, redirect::Policy};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use tokio::{
-  fs::OpenOptions,
-  io::{AsyncWriteExt, BufWriter},
+  fs::{OpenOptions, metadata, remove_dir_all},
+  io::{AsyncWrite, AsyncWriteExt, BufWriter},
 };
 
-use crate::{common::semver::Semver, project_paths::get_temp_dir};
+use tokio_util::io::StreamReader;
+
+use crate::common::{semver::Semver, tarflate::extract_archive_stream};
 
 #[derive(Deserialize)]
 struct Asset {
+  pub name: String,
   pub browser_download_url: String,
   pub content_type: String,
   pub size: usize,
@@ -21,13 +40,36 @@ struct Asset {
 struct RemoteRelease {
   pub tag_name: String,
   pub name: String,
-  pub body: String,
+  #[serde(default)]
+  pub body: Option<String>,
   pub assets: Vec<Asset>,
 }
 
+const FRONTEND_PKG_EXTENSIONS: [&str; 2] = [".tar.gz", ".tgz"];
+const FRONTEND_PKG_CONTENT_TYPES: [&str; 2] = ["application/gzip", "application/x-gzip"];
+
 impl RemoteRelease {
-  fn is_asset_a_frontend_package(asset: &Asset) -> bool {
-    asset.content_type == "application/gzip"
+  fn is_asset_named_as_frontend_package(asset: &Asset) -> bool {
+    FRONTEND_PKG_EXTENSIONS
+      .iter()
+      .any(|ext| asset.name.ends_with(ext))
+  }
+
+  fn is_asset_typed_as_frontend_package(asset: &Asset) -> bool {
+    FRONTEND_PKG_CONTENT_TYPES.contains(&asset.content_type.as_str())
+  }
+
+  fn find_frontend_package_asset(&self) -> Option<&Asset> {
+    self
+      .assets
+      .iter()
+      .find(|asset| RemoteRelease::is_asset_named_as_frontend_package(asset))
+      .or_else(|| {
+        self
+          .assets
+          .iter()
+          .find(|asset| RemoteRelease::is_asset_typed_as_frontend_package(asset))
+      })
   }
 }
 
@@ -36,9 +78,7 @@ impl TryFrom<RemoteRelease> for Release {
 
   fn try_from(val: RemoteRelease) -> Result<Release, std::string::String> {
     let download = val
-      .assets
-      .iter()
-      .find(|asset| RemoteRelease::is_asset_a_frontend_package(asset))
+      .find_frontend_package_asset()
       .map(|asset| ReleaseDownloadInfo {
         url: asset.browser_download_url.to_owned(),
         size: asset.size,
@@ -46,7 +86,7 @@ impl TryFrom<RemoteRelease> for Release {
 
     Ok(Release {
       name: val.name,
-      description: val.body,
+      description: val.body.unwrap_or_default(),
       version: val
         .tag_name
         .try_into()
@@ -76,23 +116,50 @@ pub enum Version {
   Semver(Semver),
 }
 
-const RELEASES_URL: &str = "https://api.github.com/repos/sarpt/mpv-web-front/releases";
-pub async fn get_remote_release(version: Version) -> Result<Release, ReleaseFetchErr> {
-  let client = Client::new();
+const MAX_REDIRECTS: usize = 5;
+// shared by all release operations, so the connection pool is reused between them
+static HTTP_CLIENT: LazyLock<Result<Client, reqwest::Error>> = LazyLock::new(|| {
+  Client::builder()
+    .redirect(Policy::custom(|attempt| {
+      if attempt.previous().len() > MAX_REDIRECTS {
+        return attempt.error(format!("exceeded the limit of {MAX_REDIRECTS} redirects"));
+      }
+
+      debug!(
+        "following redirect to host \"{}\"",
+        attempt.url().host_str().unwrap_or_default()
+      );
+      attempt.follow()
+    }))
+    .build()
+});
+
+fn http_client() -> Result<&'static Client, ReleaseFetchErr> {
+  HTTP_CLIENT
+    .as_ref()
+    .map_err(|err| ReleaseFetchErr::ClientInitFailed(err.to_string()))
+}
+
+pub async fn get_remote_release(
+  releases_url: &str,
+  version: Version,
+) -> Result<Release, ReleaseFetchErr> {
+  let client = http_client()?;
 
   let url = match version {
-    Version::Latest => format!("{RELEASES_URL}/latest"),
-    Version::Semver(semver) => format!("{RELEASES_URL}/tags/{semver}"),
+    Version::Latest => format!("{releases_url}/latest"),
+    Version::Semver(semver) => format!("{releases_url}/tags/{semver}"),
   };
-  let request = get_request(&client, &url)?;
+  let request = get_request(client, &url)?;
 
   let response = client
     .execute(request)
     .await
     .map_err(ReleaseFetchErr::RemoteFetchFailed)?;
-  if response.status() != StatusCode::OK {
+  if response.status() == StatusCode::NOT_FOUND {
     return Err(ReleaseFetchErr::NotFound(version));
   }
+  ensure_success_status(&response)?;
 
   let response_text = response.text().await.map_err(|err| {
     ReleaseFetchErr::ResponseParseFailure(format!("could not retrieve text response : {err}"))
@@ -107,59 +174,332 @@ pub async fn get_remote_release(version: Version) -> Result<Release, ReleaseFetc
   Ok(release)
 }
 
-pub async fn fetch_remote_frontend_package_release(
+const RELEASES_PER_PAGE: u8 = 100;
+pub async fn get_remote_releases(releases_url: &str) -> Result<Vec<Release>, ReleaseFetchErr> {
+  let client = http_client()?;
+
+  let mut releases: Vec<Release> = Vec::new();
+  let mut next_page_url = Some(format!("{releases_url}?per_page={RELEASES_PER_PAGE}"));
+  while let Some(url) = next_page_url.take() {
+    let request = get_request(client, &url)?;
+    let response = client
+      .execute(request)
+      .await
+      .map_err(ReleaseFetchErr::RemoteFetchFailed)?;
+    ensure_success_status(&response)?;
+
+    next_page_url = get_next_page_url(&response);
+    let response_text = response.text().await.map_err(|err| {
+      ReleaseFetchErr::ResponseParseFailure(format!("could not retrieve text response : {err}"))
+    })?;
+
+    let page: Vec<RemoteRelease> = serde_json::from_str(&response_text).map_err(|err| {
+      ReleaseFetchErr::ResponseParseFailure(format!("response has invalid JSON: {err}"))
+    })?;
+    for remote_release in page {
+      match Release::try_from(remote_release) {
+        Ok(release) => releases.push(release),
+        Err(err) => warn!("skipping remote release: {err}"),
+      }
+    }
+  }
+
+  releases.sort_by_key(|release| std::cmp::Reverse(release.version));
+  Ok(releases)
+}
+
+const LINK_NEXT_PAGE_REL: &str = "rel=\"next\"";
+fn get_next_page_url(response: &reqwest::Response) -> Option<String> {
+  let link = response.headers().get(LINK)?.to_str().ok()?;
+
+  link.split(',').find_map(|entry| {
+    let (url, params) = entry.split_once(';')?;
+    if !params
+      .split(';')
+      .any(|param| param.trim() == LINK_NEXT_PAGE_REL)
+    {
+      return None;
+    }
+
+    Some(
+      url
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .to_owned(),
+    )
+  })
+}
+
+pub async fn stream_remote_frontend_package_release<T>(
   release: &Release,
-) -> Result<PathBuf, ReleaseFetchErr> {
+  out_dir: T,
+) -> Result<(), ReleaseFetchErr>
+where
+  T: AsRef<Path>,
+{
   let download = match &release.download {
     Some(download) => download,
     None => {
       return Err(ReleaseFetchErr::NoPkgAssets);
     }
   };
+  if download.size == 0 {
+    return Err(ReleaseFetchErr::EmptyPkgAsset);
+  }
 
-  let client = Client::new();
-  let request = get_request(&client, &download.url)?;
-  let mut response = client
-    .execute(request)
-    .await
-    .map_err(ReleaseFetchErr::RemoteFetchFailed)?;
+  // leftovers of an earlier unpack must not be mixed into this package
+  let out_dir = out_dir.as_ref();
+  if let Err(err) = remove_dir_all(out_dir).await
+    && err.kind() != ErrorKind::NotFound
+  {
+    return Err(ReleaseFetchErr::WriteToDiskFailed(err));
+  }
+
+  let response = request_package(&download.url, 0).await?;
+  info!(
+    "streaming \"{}\" from host \"{}\"",
+    release.name,
+    response.url().host_str().unwrap_or_default()
+  );
+
+  let total_read = AtomicUsize::new(0);
+  let body_stream = unfold(response, |mut response| async move {
+    match response.chunk().await {
+      Ok(Some(chunk)) => Some((Ok(chunk), response)),
+      Ok(None) => None,
+      Err(err) => Some((Err(std::io::Error::other(err)), response)),
+    }
+  })
+  .inspect(|chunk| {
+    if let Ok(bytes) = chunk {
+      total_read.fetch_add(bytes.len(), Ordering::Relaxed);
+    }
+  });
+
+  let extraction_result =
+    extract_archive_stream(StreamReader::new(Box::pin(body_stream)), out_dir).await;
+  let total_read = total_read.load(Ordering::Relaxed);
+  // an empty body would otherwise surface only as an obscure extraction error
+  let extraction_result = if total_read == 0 {
+    Err(ReleaseFetchErr::EmptyDownload)
+  } else {
+    extraction_result
+      .map_err(ReleaseFetchErr::ExtractionFailed)
+      .and_then(|_| {
+        if total_read != download.size {
+          return Err(ReleaseFetchErr::SizeMismatch(total_read, download.size));
+        }
+        Ok(())
+      })
+  };
+
+  // files of a failed or short stream are already written and must not be installed later
+  if extraction_result.is_err()
+    && let Err(err) = remove_dir_all(out_dir).await
+  {
+    warn!(
+      "could not remove partially extracted package at path {}: {err}",
+      out_dir.to_string_lossy()
+    );
+  }
+  extraction_result
+}
+
+pub async fn fetch_remote_frontend_package_release<T>(
+  release: &Release,
+  temp_dir: T,
+) -> Result<PathBuf, ReleaseFetchErr>
+where
+  T: AsRef<Path>,
+{
+  fetch_remote_frontend_package_release_with_progress(release, temp_dir, |_, _| {}).await
+}
 
-  let mut target_path = get_temp_dir();
-  target_path.push(&release.name);
+// on_progress receives the number of bytes on disk and the expected package size after every chunk
+pub async fn fetch_remote_frontend_package_release_with_progress<T, F>(
+  release: &Release,
+  temp_dir: T,
+  on_progress: F,
+) -> Result<PathBuf, ReleaseFetchErr>
+where
+  T: AsRef<Path>,
+  F: Fn(usize, usize),
+{
+  let download = match &release.download {
+    Some(download) => download,
+    None => {
+      return Err(ReleaseFetchErr::NoPkgAssets);
+    }
+  };
+  if download.size == 0 {
+    return Err(ReleaseFetchErr::EmptyPkgAsset);
+  }
+
+  let target_path = temp_dir.as_ref().join(&release.name);
+
+  let partial_size = match metadata(&target_path).await {
+    Ok(meta) if meta.is_file() && meta.len() > 0 && (meta.len() as usize) < download.size => {
+      meta.len() as usize
+    }
+    _ => 0,
+  };
+
+  if partial_size > 0 {
+    info!(
+      "resuming download of \"{}\" from byte {partial_size}",
+      release.name
+    );
+  }
+  let mut response = request_package(&download.url, partial_size).await?;
+  let mut resumed = partial_size > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+  if partial_size > 0 && !resumed {
+    info!(
+      "remote ignored the range request for \"{}\", downloading from scratch",
+      release.name
+    );
+  }
+  // appending a range other than the requested one would corrupt the package
+  if resumed && content_range_start(&response) != Some(partial_size) {
+    info!(
+      "remote responded with a range not starting at byte {partial_size} for \"{}\", downloading from scratch",
+      release.name
+    );
+    response = request_package(&download.url, 0).await?;
+    resumed = false;
+  }
+  info!(
+    "downloading \"{}\" from host \"{}\"",
+    release.name,
+    response.url().host_str().unwrap_or_default()
+  );
 
   let tgt_file_open_result = OpenOptions::default()
     .create(true)
     .read(false)
     .write(true)
+    .append(resumed)
+    .truncate(!resumed)
     .open(&target_path)
     .await
     .map_err(ReleaseFetchErr::WriteToDiskFailed)?;
 
   let mut tgt_file_wrtier = BufWriter::new(tgt_file_open_result);
 
-  let mut total_written: usize = 0;
+  let already_written: usize = if resumed { partial_size } else { 0 };
+  let total_written = write_response_body(
+    &mut response,
+    &mut tgt_file_wrtier,
+    already_written,
+    |written| on_progress(written, download.size),
+  )
+  .await?;
+
+  // an empty file would otherwise surface only as an obscure extraction error
+  if total_written == 0 {
+    return Err(ReleaseFetchErr::EmptyDownload);
+  }
+  if total_written != download.size {
+    return Err(ReleaseFetchErr::SizeMismatch(total_written, download.size));
+  }
+
+  Ok(target_path)
+}
+
+// returns the number of written bytes, including already_written
+async fn write_response_body<W, F>(
+  response: &mut reqwest::Response,
+  writer: &mut W,
+  already_written: usize,
+  on_progress: F,
+) -> Result<usize, ReleaseFetchErr>
+where
+  W: AsyncWrite + Unpin,
+  F: Fn(usize),
+{
+  let mut total_written = already_written;
   while let Some(chunk) = response
     .chunk()
     .await
     .map_err(ReleaseFetchErr::RemoteFetchFailed)?
   {
-    let written = tgt_file_wrtier
-      .write(&chunk)
+    writer
+      .write_all(&chunk)
       .await
       .map_err(ReleaseFetchErr::WriteToDiskFailed)?;
-    total_written += written;
+    total_written += chunk.len();
+    on_progress(total_written);
   }
 
-  tgt_file_wrtier
+  writer
     .shutdown()
     .await
     .map_err(ReleaseFetchErr::WriteToDiskFailed)?;
+  Ok(total_written)
+}
 
-  if total_written != download.size {
-    return Err(ReleaseFetchErr::SizeMismatch(total_written, download.size));
+async fn request_package(
+  url: &str,
+  from_byte: usize,
+) -> Result<reqwest::Response, ReleaseFetchErr> {
+  let client = http_client()?;
+  let mut request = get_request(client, url)?;
+  if from_byte > 0
+    && let Ok(range) = HeaderValue::from_str(&format!("bytes={from_byte}-"))
+  {
+    request.headers_mut().insert(RANGE, range);
   }
 
-  Ok(target_path)
+  let response = client.execute(request).await.map_err(|err| {
+    if err.is_redirect() {
+      ReleaseFetchErr::TooManyRedirects(err)
+    } else {
+      ReleaseFetchErr::RemoteFetchFailed(err)
+    }
+  })?;
+  ensure_success_status(&response)?;
+  Ok(response)
+}
+
+const CONTENT_RANGE_UNIT_PREFIX: &str = "bytes ";
+// parses the first byte position out of "bytes <start>-<end>/<size>"
+fn content_range_start(response: &reqwest::Response) -> Option<usize> {
+  let content_range = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+  let (start, _) = content_range
+    .strip_prefix(CONTENT_RANGE_UNIT_PREFIX)?
+    .split_once('-')?;
+  start.trim().parse().ok()
+}
+
+const RATE_LIMIT_REMAINING_HEADER: &str = "x-ratelimit-remaining";
+const RATE_LIMIT_RESET_HEADER: &str = "x-ratelimit-reset";
+// github signals exhausted rate limits with either 403 or 429, see
+// https://docs.github.com/en/rest/using-the-rest-api/rate-limits-for-the-rest-api
+fn ensure_success_status(response: &reqwest::Response) -> Result<(), ReleaseFetchErr> {
+  let status = response.status();
+  if status.is_success() {
+    return Ok(());
+  }
+
+  let header = |name: &str| {
+    response
+      .headers()
+      .get(name)
+      .and_then(|value| value.to_str().ok())
+      .and_then(|value| value.parse::<u64>().ok())
+  };
+  let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+    || (status == StatusCode::FORBIDDEN && header(RATE_LIMIT_REMAINING_HEADER) == Some(0));
+  if rate_limited {
+    // secondary rate limits provide only a relative retry-after
+    let resets_at = match header(RETRY_AFTER.as_str()) {
+      Some(retry_after) => Some(SystemTime::now() + Duration::from_secs(retry_after)),
+      None => header(RATE_LIMIT_RESET_HEADER).map(|reset| UNIX_EPOCH + Duration::from_secs(reset)),
+    };
+    return Err(ReleaseFetchErr::RateLimited(status, resets_at));
+  }
+
+  Err(ReleaseFetchErr::UnexpectedStatus(status))
 }
 
 fn get_request<T>(client: &Client, url: T) -> Result<Request, ReleaseFetchErr>
@@ -178,27 +518,525 @@ where
     .map_err(ReleaseFetchErr::RemoteFetchFailed)
 }
 
+#[derive(Debug, Error)]
 pub enum ReleaseFetchErr {
+  #[error("could not initialize the http client: {0}")]
+  ClientInitFailed(String),
+  #[error("release doesn't have any frontend package assets")]
   NoPkgAssets,
+  #[error("release package asset declares a size of 0 bytes")]
+  EmptyPkgAsset,
+  #[error("downloaded package is empty - remote sent 0 bytes")]
+  EmptyDownload,
+  #[error("expected package size of {1} bytes but only {0} bytes written")]
   SizeMismatch(usize, usize),
-  WriteToDiskFailed(std::io::Error),
-  RemoteFetchFailed(reqwest::Error),
+  #[error("could not write file to disk: {0}")]
+  WriteToDiskFailed(#[source] std::io::Error),
+  #[error("could not fetch package file: {0}")]
+  RemoteFetchFailed(#[source] reqwest::Error),
+  #[error("could not follow redirects of package file: {0}")]
+  TooManyRedirects(#[source] reqwest::Error),
+  #[error("could not extract streamed package: {0}")]
+  ExtractionFailed(String),
+  #[error("could not find version {0:?} - remote responded with status 404 Not Found")]
   NotFound(Version),
+  #[error("remote rate limit exceeded (status {}){}", .0, .1.map(|resets_at| format!(" - resets in {} seconds", secs_until(resets_at))).unwrap_or_default())]
+  RateLimited(StatusCode, Option<SystemTime>),
+  #[error("remote responded with unexpected status {0}")]
+  UnexpectedStatus(StatusCode),
+  #[error("{0}")]
   ResponseParseFailure(String),
 }
 
-impl Display for ReleaseFetchErr {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    match self {
-      ReleaseFetchErr::NoPkgAssets => write!(f, "release doesn't have any frontend package assets"),
-      ReleaseFetchErr::WriteToDiskFailed(err) => write!(f, "could not write file to disk: {err}"),
-      ReleaseFetchErr::RemoteFetchFailed(err) => write!(f, "could not fetch package file: {err}"),
-      ReleaseFetchErr::ResponseParseFailure(msg) => write!(f, "{msg}"),
-      ReleaseFetchErr::NotFound(version) => write!(f, "could not fetch version {version:?}"),
-      ReleaseFetchErr::SizeMismatch(written, declared) => write!(
-        f,
-        "expected package size of {declared} bytes but only {written} bytes written"
-      ),
+pub fn secs_until(time: SystemTime) -> u64 {
+  time
+    .duration_since(SystemTime::now())
+    .unwrap_or_default()
+    .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+  };
+
+  use http_body_util::Full;
+  use hyper::{
+    Request, Response, StatusCode,
+    body::{Bytes, Incoming},
+    header::{CONTENT_RANGE, LINK, LOCATION, RANGE, RETRY_AFTER},
+    server::conn::http1,
+    service::service_fn,
+  };
+  use hyper_util::rt::TokioIo;
+  use tokio::net::TcpListener;
+
+  use super::{
+    Asset, MAX_REDIRECTS, RATE_LIMIT_REMAINING_HEADER, RATE_LIMIT_RESET_HEADER, Release,
+    ReleaseDownloadInfo, ReleaseFetchErr, RemoteRelease, Version, ensure_success_status,
+    fetch_remote_frontend_package_release, get_remote_release, get_remote_releases, http_client,
+    stream_remote_frontend_package_release, write_response_body,
+  };
+
+  // handlers receive the mock url, so responses can link back to it
+  async fn serve_mock<H>(handler: H) -> String
+  where
+    H: Fn(&Request<Incoming>, &str) -> Response<Full<Bytes>> + Send + Sync + 'static,
+  {
+    let listener = TcpListener::bind("127.0.0.1:0")
+      .await
+      .expect("mock could not bind");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+    let mock_url = url.clone();
+    tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        let handler = handler.clone();
+        let mock_url = mock_url.clone();
+        let service = service_fn(move |req| {
+          let response = handler(&req, &mock_url);
+          async move { Ok::<_, hyper::Error>(response) }
+        });
+        tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+      }
+    });
+
+    url
+  }
+
+  fn status_response(status: StatusCode) -> Response<Full<Bytes>> {
+    Response::builder()
+      .status(status)
+      .body(Full::default())
+      .unwrap()
+  }
+
+  fn asset(name: &str, content_type: &str) -> Asset {
+    Asset {
+      name: name.to_owned(),
+      browser_download_url: format!("https://example.com/{name}"),
+      content_type: content_type.to_owned(),
+      size: 1,
+    }
+  }
+
+  fn release(assets: Vec<Asset>) -> RemoteRelease {
+    RemoteRelease {
+      tag_name: "1.0.0".to_owned(),
+      name: "1.0.0".to_owned(),
+      body: None,
+      assets,
+    }
+  }
+
+  fn found_asset_name(release: &RemoteRelease) -> Option<&str> {
+    release
+      .find_frontend_package_asset()
+      .map(|asset| asset.name.as_str())
+  }
+
+  #[test]
+  fn prefers_asset_named_as_package() {
+    let release = release(vec![
+      asset("checksums.txt", "application/gzip"),
+      asset("frontend.tgz", "application/octet-stream"),
+    ]);
+
+    assert_eq!(found_asset_name(&release), Some("frontend.tgz"));
+  }
+
+  #[test]
+  fn accepts_both_package_extensions() {
+    for name in ["frontend.tar.gz", "frontend.tgz"] {
+      let release = release(vec![asset(name, "application/octet-stream")]);
+      assert_eq!(found_asset_name(&release), Some(name));
+    }
+  }
+
+  #[test]
+  fn falls_back_to_asset_typed_as_package() {
+    let release = release(vec![
+      asset("notes.txt", "text/plain"),
+      asset("frontend", "application/x-gzip"),
+    ]);
+
+    assert_eq!(found_asset_name(&release), Some("frontend"));
+  }
+
+  #[test]
+  fn finds_nothing_without_package_assets() {
+    let release = release(vec![asset("notes.txt", "text/plain")]);
+
+    assert_eq!(found_asset_name(&release), None);
+  }
+
+  fn response(status: StatusCode, headers: &[(&str, &str)]) -> reqwest::Response {
+    let mut builder = hyper::Response::builder().status(status);
+    for (name, value) in headers {
+      builder = builder.header(*name, *value);
+    }
+    builder.body(String::new()).unwrap().into()
+  }
+
+  #[test]
+  fn accepts_success_status() {
+    assert!(ensure_success_status(&response(StatusCode::OK, &[])).is_ok());
+  }
+
+  #[test]
+  fn reports_unexpected_status() {
+    for status in [StatusCode::NOT_FOUND, StatusCode::INTERNAL_SERVER_ERROR] {
+      assert!(matches!(
+        ensure_success_status(&response(status, &[])),
+        Err(ReleaseFetchErr::UnexpectedStatus(reported)) if reported == status
+      ));
+    }
+  }
+
+  #[test]
+  fn forbidden_with_remaining_requests_is_not_rate_limited() {
+    let response = response(
+      StatusCode::FORBIDDEN,
+      &[(RATE_LIMIT_REMAINING_HEADER, "10")],
+    );
+
+    assert!(matches!(
+      ensure_success_status(&response),
+      Err(ReleaseFetchErr::UnexpectedStatus(StatusCode::FORBIDDEN))
+    ));
+  }
+
+  #[test]
+  fn exhausted_primary_rate_limit_reports_reset_time() {
+    let response = response(
+      StatusCode::FORBIDDEN,
+      &[
+        (RATE_LIMIT_REMAINING_HEADER, "0"),
+        (RATE_LIMIT_RESET_HEADER, "1700000000"),
+      ],
+    );
+
+    assert!(matches!(
+      ensure_success_status(&response),
+      Err(ReleaseFetchErr::RateLimited(StatusCode::FORBIDDEN, Some(resets_at)))
+        if resets_at == UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    ));
+  }
+
+  #[test]
+  fn secondary_rate_limit_prefers_retry_after() {
+    let response = response(
+      StatusCode::TOO_MANY_REQUESTS,
+      &[
+        (RETRY_AFTER.as_str(), "60"),
+        (RATE_LIMIT_RESET_HEADER, "1700000000"),
+      ],
+    );
+
+    let Err(ReleaseFetchErr::RateLimited(StatusCode::TOO_MANY_REQUESTS, Some(resets_at))) =
+      ensure_success_status(&response)
+    else {
+      panic!("expected a rate limit error");
+    };
+    let resets_in = resets_at.duration_since(SystemTime::now()).unwrap();
+    assert!(resets_in > Duration::from_secs(50) && resets_in <= Duration::from_secs(60));
+  }
+
+  #[tokio::test]
+  async fn missing_tag_is_reported_as_not_found() {
+    let url = serve_mock(|req, _| match req.uri().path() {
+      "/releases/tags/1.0.0" => Response::new(Full::from(
+        r#"{"tag_name": "1.0.0", "name": "1.0.0", "assets": []}"#,
+      )),
+      _ => status_response(StatusCode::NOT_FOUND),
+    })
+    .await;
+    let releases_url = format!("{url}/releases");
+
+    let missing = get_remote_release(&releases_url, Version::Semver("9.9.9".parse().unwrap()));
+    assert!(matches!(
+      missing.await,
+      Err(ReleaseFetchErr::NotFound(Version::Semver(version))) if version.to_string() == "9.9.9"
+    ));
+    let existing = get_remote_release(&releases_url, Version::Semver("1.0.0".parse().unwrap()));
+    assert_eq!(existing.await.unwrap().version.to_string(), "1.0.0");
+  }
+
+  const PACKAGE: &[u8] = b"0123456789abcdefghij";
+  const PARTIAL_SIZE: usize = 8;
+
+  fn package_release(url: &str, size: usize) -> Release {
+    Release {
+      name: "pkg.tar.gz".to_owned(),
+      version: "1.0.0".parse().unwrap(),
+      description: String::new(),
+      download: Some(ReleaseDownloadInfo {
+        url: format!("{url}/pkg.tar.gz"),
+        size,
+      }),
+    }
+  }
+
+  fn fixture_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mwc-releases-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("fixture dir could not be created");
+    dir
+  }
+
+  #[derive(Clone, Copy)]
+  enum RangeSupport {
+    Honored,
+    Ignored,
+    Misaligned,
+  }
+
+  // serves PACKAGE and records the range header of every request
+  async fn serve_package(support: RangeSupport) -> (String, Arc<Mutex<Vec<Option<String>>>>) {
+    let requested_ranges = Arc::new(Mutex::new(Vec::new()));
+    let recorded_ranges = requested_ranges.clone();
+    let url = serve_mock(move |req, _| {
+      let range = req
+        .headers()
+        .get(RANGE)
+        .map(|range| range.to_str().unwrap().to_owned());
+      recorded_ranges.lock().unwrap().push(range.clone());
+      let start = match (range, support) {
+        (None, _) | (Some(_), RangeSupport::Ignored) => {
+          return Response::new(Full::from(PACKAGE));
+        }
+        (Some(_), RangeSupport::Misaligned) => 0,
+        (Some(range), RangeSupport::Honored) => range
+          .trim_start_matches("bytes=")
+          .trim_end_matches('-')
+          .parse()
+          .unwrap(),
+      };
+      Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(
+          CONTENT_RANGE,
+          format!("bytes {start}-{}/{}", PACKAGE.len() - 1, PACKAGE.len()),
+        )
+        .body(Full::from(&PACKAGE[start..]))
+        .unwrap()
+    })
+    .await;
+
+    (url, requested_ranges)
+  }
+
+  async fn fetch_after_partial_download(name: &str, support: RangeSupport) -> Vec<Option<String>> {
+    let temp_dir = fixture_dir(name);
+    let (url, requested_ranges) = serve_package(support).await;
+    let release = package_release(&url, PACKAGE.len());
+    std::fs::write(temp_dir.join(&release.name), &PACKAGE[..PARTIAL_SIZE])
+      .expect("partial download could not be written");
+
+    let path = fetch_remote_frontend_package_release(&release, &temp_dir)
+      .await
+      .expect("download failed");
+
+    assert_eq!(std::fs::read(path).unwrap(), PACKAGE);
+    requested_ranges.lock().unwrap().clone()
+  }
+
+  #[tokio::test]
+  async fn resumes_partial_download_with_range_request() {
+    let requested_ranges = fetch_after_partial_download("resume", RangeSupport::Honored).await;
+
+    assert_eq!(requested_ranges, [Some(format!("bytes={PARTIAL_SIZE}-"))]);
+  }
+
+  #[tokio::test]
+  async fn ignored_range_downloads_from_scratch() {
+    let requested_ranges = fetch_after_partial_download("ignored", RangeSupport::Ignored).await;
+
+    assert_eq!(requested_ranges, [Some(format!("bytes={PARTIAL_SIZE}-"))]);
+  }
+
+  #[tokio::test]
+  async fn misaligned_range_downloads_from_scratch() {
+    let requested_ranges =
+      fetch_after_partial_download("misaligned", RangeSupport::Misaligned).await;
+
+    assert_eq!(
+      requested_ranges,
+      [Some(format!("bytes={PARTIAL_SIZE}-")), None]
+    );
+  }
+
+  #[tokio::test]
+  async fn empty_download_is_rejected_by_both_fetch_paths() {
+    let temp_dir = fixture_dir("empty");
+    let url = serve_mock(|_, _| Response::new(Full::default())).await;
+    let release = package_release(&url, PACKAGE.len());
+
+    let fetched = fetch_remote_frontend_package_release(&release, &temp_dir).await;
+    assert!(matches!(fetched, Err(ReleaseFetchErr::EmptyDownload)));
+
+    let out_dir = temp_dir.join("frontend");
+    let streamed = stream_remote_frontend_package_release(&release, &out_dir).await;
+    assert!(matches!(streamed, Err(ReleaseFetchErr::EmptyDownload)));
+    assert!(!out_dir.exists());
+  }
+
+  fn releases_json(versions: &[&str]) -> String {
+    let releases: Vec<String> = versions
+      .iter()
+      .map(|version| format!(r#"{{"tag_name": "{version}", "name": "{version}", "assets": []}}"#))
+      .collect();
+    format!("[{}]", releases.join(","))
+  }
+
+  #[tokio::test]
+  async fn collects_releases_from_all_pages() {
+    let url = serve_mock(|req, mock_url| match req.uri().query() {
+      Some("per_page=100") => Response::builder()
+        .header(
+          LINK,
+          format!(
+            r#"<{mock_url}/releases?page=2>; rel="next", <{mock_url}/releases?page=2>; rel="last""#
+          ),
+        )
+        .body(Full::from(releases_json(&["1.0.0", "3.0.0"])))
+        .unwrap(),
+      Some("page=2") => Response::builder()
+        .header(
+          LINK,
+          format!(r#"<{mock_url}/releases?per_page=100>; rel="prev""#),
+        )
+        .body(Full::from(releases_json(&["2.0.0"])))
+        .unwrap(),
+      _ => status_response(StatusCode::NOT_FOUND),
+    })
+    .await;
+
+    let releases = get_remote_releases(&format!("{url}/releases"))
+      .await
+      .expect("releases could not be listed");
+
+    let versions: Vec<String> = releases
+      .iter()
+      .map(|release| release.version.to_string())
+      .collect();
+    assert_eq!(versions, ["3.0.0", "2.0.0", "1.0.0"]);
+  }
+
+  #[test]
+  fn release_operations_share_one_client() {
+    let first = http_client().expect("client could not be initialized");
+    let second = http_client().expect("client could not be initialized");
+
+    assert!(std::ptr::eq(first, second));
+  }
+
+  // /redirect/<n> redirects n more times before the package is served
+  async fn serve_redirect_chain() -> String {
+    serve_mock(|req, mock_url| {
+      let remaining: usize = match req.uri().path().strip_prefix("/redirect/") {
+        Some(remaining) => remaining.parse().unwrap(),
+        None => return Response::new(Full::from(PACKAGE)),
+      };
+      let location = match remaining {
+        0 => format!("{mock_url}/pkg.tar.gz"),
+        _ => format!("{mock_url}/redirect/{}", remaining - 1),
+      };
+      Response::builder()
+        .status(StatusCode::FOUND)
+        .header(LOCATION, location)
+        .body(Full::default())
+        .unwrap()
+    })
+    .await
+  }
+
+  fn redirected_release(url: &str, redirects: usize) -> Release {
+    let mut release = package_release(url, PACKAGE.len());
+    if let Some(download) = release.download.as_mut() {
+      // the first redirect is issued by the asset url itself
+      download.url = format!("{url}/redirect/{}", redirects - 1);
+    }
+    release
+  }
+
+  #[tokio::test]
+  async fn follows_redirects_up_to_the_limit() {
+    let temp_dir = fixture_dir("redirects");
+    let url = serve_redirect_chain().await;
+
+    let release = redirected_release(&url, MAX_REDIRECTS);
+    let path = fetch_remote_frontend_package_release(&release, &temp_dir)
+      .await
+      .expect("redirected download failed");
+    assert_eq!(std::fs::read(path).unwrap(), PACKAGE);
+
+    let release = redirected_release(&url, MAX_REDIRECTS + 1);
+    let fetched = fetch_remote_frontend_package_release(&release, &temp_dir).await;
+    assert!(
+      matches!(fetched, Err(ReleaseFetchErr::TooManyRedirects(_))),
+      "{fetched:?}"
+    );
+  }
+
+  // accepts at most a few bytes per write, like a slow pipe or a nearly full disk
+  struct ShortWriter {
+    written: Vec<u8>,
+  }
+
+  const SHORT_WRITE_SIZE: usize = 3;
+
+  impl tokio::io::AsyncWrite for ShortWriter {
+    fn poll_write(
+      mut self: std::pin::Pin<&mut Self>,
+      _: &mut std::task::Context<'_>,
+      buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+      let accepted = buf.len().min(SHORT_WRITE_SIZE);
+      self.written.extend_from_slice(&buf[..accepted]);
+      std::task::Poll::Ready(Ok(accepted))
+    }
+
+    fn poll_flush(
+      self: std::pin::Pin<&mut Self>,
+      _: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+      std::task::Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+      self: std::pin::Pin<&mut Self>,
+      _: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+      std::task::Poll::Ready(Ok(()))
     }
   }
+
+  #[tokio::test]
+  async fn short_writes_persist_whole_body() {
+    let url = serve_mock(|_, _| Response::new(Full::from(PACKAGE))).await;
+    let mut response = http_client()
+      .unwrap()
+      .get(format!("{url}/pkg.tar.gz"))
+      .send()
+      .await
+      .unwrap();
+    let mut writer = ShortWriter {
+      written: Vec::new(),
+    };
+    let progress = Mutex::new(Vec::new());
+
+    let total = write_response_body(&mut response, &mut writer, PARTIAL_SIZE, |written| {
+      progress.lock().unwrap().push(written)
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(writer.written, PACKAGE);
+    assert_eq!(total, PARTIAL_SIZE + PACKAGE.len());
+    assert_eq!(progress.lock().unwrap().last(), Some(&total));
+  }
 }
@@ -0,0 +1,356 @@
+use ipnet::IpNet;
+use log::{error, info, warn};
+use std::ops::DerefMut;
+use std::{
+  collections::HashMap,
+  net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+  num::NonZeroU32,
+  path::PathBuf,
+  sync::Arc,
+  time::Duration,
+};
+use tokio::sync::{Mutex, RwLock, broadcast};
+
+use crate::{
+  advertise::advertise,
+  api_servers::{ApiServersService, spawn_supervisor},
+  frontend::{init_frontend, pkg::repository::PackagesRepository},
+  listener::get_tcp_listener,
+  project_paths::ensure_project_dirs,
+  qr::print_qr_code,
+  server::serve,
+  watch::watch_dir,
+};
+
+pub use crate::{
+  error::Error,
+  frontend::UpdateTrack,
+  listener::{ListenerError, list_interfaces},
+  verify::verify,
+};
+
+mod advertise;
+mod api_servers;
+mod common;
+mod error;
+mod frontend;
+mod listener;
+mod project_paths;
+mod qr;
+mod server;
+mod verify;
+mod watch;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const DEFAULT_IPADDR: [u8; 4] = [127, 0, 0, 1];
+pub const DEFAULT_SOCKET_RETRIES: u8 = 8;
+pub const DEFAULT_IDLE_SHUTDOWN_TIMEOUT: u8 = 60;
+pub const DEFAULT_API_SERVER_RECENT_OUTPUT_LINES: usize = 500;
+pub const DEFAULT_API_SERVER_STOP_GRACE_PERIOD: u32 = 10;
+pub const DEFAULT_API_SERVER_READY_TIMEOUT: u32 = 5;
+pub const DEFAULT_FRONTEND_INIT_TIMEOUT: u32 = 60;
+pub const DEFAULT_MAX_REQUEST_BODY_SIZE: usize = 64 * 1024;
+pub const DEFAULT_STREAM_CHUNK_SIZE: usize = 256 * 1024;
+pub const DEFAULT_MIN_COMPRESSION_SIZE: u64 = 1024;
+pub const DEFAULT_RELEASES_URL: &str = "https://api.github.com/repos/sarpt/mpv-web-front/releases";
+const UNPRIVILEGED_PORT_START: u16 = 1024;
+const API_SERVICE_SHUTDOWN_TIMEOUT: u8 = 30;
+const RELOAD_CHANNEL_CAPACITY: usize = 16;
+const UPDATE_PROGRESS_CHANNEL_CAPACITY: usize = 128;
+
+#[derive(Clone, Debug)]
+pub struct ApiCredentials {
+  pub user: String,
+  pub password: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+  pub ip_address: Ipv4Addr,
+  pub port: Option<u16>,
+  pub socket_retries: u8,
+  pub port_fallback: bool,
+  pub interface: Option<String>,
+  pub pkg: Option<PathBuf>,
+  pub serve_dir: Option<PathBuf>,
+  pub watch: bool,
+  pub data_dir: Option<PathBuf>,
+  pub update: bool,
+  pub update_track: UpdateTrack,
+  pub offline: bool,
+  pub stream_install: bool,
+  pub force_outdated: bool,
+  pub idle_shutdown_timeout: Option<u32>,
+  pub open: bool,
+  pub advertise: bool,
+  pub qr: bool,
+  pub content_security_policy: Option<String>,
+  pub api_server_recent_output_lines: usize,
+  pub api_server_stop_grace_period: u32,
+  pub api_server_ready_timeout: u32,
+  pub frontend_init_timeout: u32,
+  pub max_request_body_size: usize,
+  pub stream_chunk_size: usize,
+  pub min_compression_size: u64,
+  pub mime_types: Vec<(String, String)>,
+  pub api_credentials: Option<ApiCredentials>,
+  pub api_allowlist: Vec<IpNet>,
+  pub shutdown_token: Option<String>,
+  pub rate_limit: Option<u32>,
+  pub rate_limit_burst: Option<u32>,
+  pub releases_url: String,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Config {
+      ip_address: Ipv4Addr::from(DEFAULT_IPADDR),
+      port: None,
+      socket_retries: DEFAULT_SOCKET_RETRIES,
+      port_fallback: false,
+      interface: None,
+      pkg: None,
+      serve_dir: None,
+      watch: false,
+      data_dir: None,
+      update: false,
+      update_track: UpdateTrack::default(),
+      offline: false,
+      stream_install: false,
+      force_outdated: false,
+      idle_shutdown_timeout: None,
+      open: false,
+      advertise: false,
+      qr: false,
+      content_security_policy: None,
+      api_server_recent_output_lines: DEFAULT_API_SERVER_RECENT_OUTPUT_LINES,
+      api_server_stop_grace_period: DEFAULT_API_SERVER_STOP_GRACE_PERIOD,
+      api_server_ready_timeout: DEFAULT_API_SERVER_READY_TIMEOUT,
+      frontend_init_timeout: DEFAULT_FRONTEND_INIT_TIMEOUT,
+      max_request_body_size: DEFAULT_MAX_REQUEST_BODY_SIZE,
+      stream_chunk_size: DEFAULT_STREAM_CHUNK_SIZE,
+      min_compression_size: DEFAULT_MIN_COMPRESSION_SIZE,
+      mime_types: Vec::new(),
+      api_credentials: None,
+      api_allowlist: Vec::new(),
+      shutdown_token: None,
+      rate_limit: None,
+      rate_limit_burst: None,
+      releases_url: DEFAULT_RELEASES_URL.to_owned(),
+    }
+  }
+}
+
+pub async fn run(config: Config) -> Result<(), Error> {
+  info!("version {VERSION}");
+  if let Some(port) = config.port
+    && port < UNPRIVILEGED_PORT_START
+  {
+    warn!("port {port} is privileged - binding to it may fail without elevated permissions");
+  }
+
+  let rate_limiter = rate_limiter(&config)?;
+  let project_dirs = ensure_project_dirs(config.data_dir.as_deref()).map_err(Error::ProjectDirs)?;
+  let api_service = ApiServersService::new(
+    project_dirs.logs_dir,
+    project_dirs.saved_api_servers_path,
+    config.api_server_recent_output_lines,
+    Duration::from_secs(config.api_server_stop_grace_period.into()),
+    Duration::from_secs(config.api_server_ready_timeout.into()),
+  );
+  let mut packages_repository = PackagesRepository::new(project_dirs.home_dir);
+  match &config.serve_dir {
+    Some(serve_dir) => {
+      if !serve_dir.is_dir() {
+        return Err(Error::ServeDirMissing(serve_dir.clone()));
+      }
+      warn!(
+        "serving files directly from {} - installed frontend package is not used",
+        serve_dir.to_string_lossy()
+      );
+    }
+    None => init_frontend(&config, &mut packages_repository).await?,
+  }
+  if let Some(idle_shutdown_timeout) = config.idle_shutdown_timeout {
+    warn!("server will shut down after being idle for {idle_shutdown_timeout} seconds!");
+  }
+
+  let tcp_listener = get_tcp_listener(&config).await?;
+  if config.open {
+    match tcp_listener.local_addr() {
+      Ok(addr) => open_browser(addr, |url| open::that_detached(url)),
+      Err(err) => warn!("could not open a browser - bound address unknown: {err}"),
+    }
+  }
+  if config.qr {
+    match tcp_listener.local_addr() {
+      Ok(addr) => print_qr_code(addr),
+      Err(err) => warn!("could not print a QR code - bound address unknown: {err}"),
+    }
+  }
+  let advertisement = if config.advertise {
+    match tcp_listener.local_addr() {
+      Ok(addr) => advertise(addr)
+        .inspect_err(|err| warn!("could not advertise the server over mDNS: {err}"))
+        .ok(),
+      Err(err) => {
+        warn!("could not advertise the server over mDNS - bound address unknown: {err}");
+        None
+      }
+    }
+  } else {
+    None
+  };
+  let reload_notifier = config
+    .watch
+    .then(|| broadcast::channel(RELOAD_CHANNEL_CAPACITY).0);
+  let _watcher = match (&config.serve_dir, &reload_notifier) {
+    (Some(serve_dir), Some(reload_notifier)) => watch_dir(serve_dir, reload_notifier.clone())
+      .inspect_err(|err| warn!("could not watch the served directory for changes: {err}"))
+      .ok(),
+    (None, Some(_)) => {
+      warn!("watching for changes is supported only when serving a directory");
+      None
+    }
+    _ => None,
+  };
+  let server_dependencies = server::Dependencies {
+    packages_repository: Arc::new(RwLock::new(packages_repository)),
+    api_service: Arc::new(Mutex::new(api_service)),
+    rate_limiter: rate_limiter.map(Arc::new),
+    reload_notifier,
+    update_cooldown: Default::default(),
+    update_in_progress: Default::default(),
+    metrics: Default::default(),
+    update_progress: broadcast::channel(UPDATE_PROGRESS_CHANNEL_CAPACITY).0,
+  };
+
+  let server_config = server::Config {
+    idle_shutdown_timeout: config.idle_shutdown_timeout,
+    max_request_body_size: config.max_request_body_size,
+    offline: config.offline,
+    update_track: config.update_track,
+    force_outdated: config.force_outdated,
+    stream_install: config.stream_install,
+    releases_url: config.releases_url.clone(),
+    api_authorization: config
+      .api_credentials
+      .as_ref()
+      .map(|credentials| server::basic_authorization(&credentials.user, &credentials.password)),
+    api_allowlist: config.api_allowlist.clone(),
+    shutdown_token: config.shutdown_token.clone(),
+    frontend: server::FrontendConfig {
+      content_security_policy: config.content_security_policy.clone(),
+      stream_chunk_size: config.stream_chunk_size,
+      min_compression_size: config.min_compression_size,
+      mime_types: mime_types_by_extension(&config.mime_types),
+      serve_dir: config.serve_dir.clone(),
+    },
+  };
+
+  let api_supervisor = spawn_supervisor(server_dependencies.api_service.clone());
+  let serve_result = serve(tcp_listener, server_config, &server_dependencies).await;
+  if let Some(advertisement) = advertisement {
+    advertisement.stop().await;
+  }
+  // spawned api servers must not outlive the client, even when serving failed
+  api_supervisor.abort();
+  server_dependencies
+    .api_service
+    .lock()
+    .await
+    .deref_mut()
+    .shutdown(API_SERVICE_SHUTDOWN_TIMEOUT.into())
+    .await;
+
+  serve_result.map_err(|err| {
+    let err = Error::Serve(err);
+    error!("{err}");
+    err
+  })
+}
+
+// the library config is not validated by clap, so zero rates are rejected here
+fn rate_limiter(config: &Config) -> Result<Option<server::RateLimiter>, Error> {
+  let Some(per_second) = config.rate_limit else {
+    return Ok(None);
+  };
+  let per_second = NonZeroU32::new(per_second).ok_or(Error::InvalidRateLimit)?;
+  let burst = match config.rate_limit_burst {
+    Some(burst) => NonZeroU32::new(burst).ok_or(Error::InvalidRateLimit)?,
+    None => per_second,
+  };
+
+  Ok(Some(server::RateLimiter::new(per_second, burst)))
+}
+
+fn mime_types_by_extension(mime_types: &[(String, String)]) -> HashMap<String, mime_guess::Mime> {
+  let mut by_extension = HashMap::new();
+  for (extension, mime_type) in mime_types {
+    match mime_type.parse() {
+      Ok(mime_type) => {
+        by_extension.insert(extension.trim_start_matches('.').to_lowercase(), mime_type);
+      }
+      Err(err) => warn!("ignoring invalid mime type \"{mime_type}\" for \"{extension}\": {err}"),
+    }
+  }
+
+  by_extension
+}
+
+fn open_browser(addr: SocketAddr, launch: impl FnOnce(&str) -> std::io::Result<()>) {
+  let url = served_url(addr);
+  info!("opening browser at {url}");
+  if let Err(err) = launch(&url) {
+    warn!("could not open a browser at {url}: {err}");
+  }
+}
+
+fn served_url(mut addr: SocketAddr) -> String {
+  if addr.ip().is_unspecified() {
+    addr.set_ip(match addr.ip() {
+      IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+      IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+    });
+  }
+
+  format!("http://{addr}/")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn served_url_replaces_unspecified_address_with_loopback() {
+    let cases = [
+      ("192.168.1.10:8080", "http://192.168.1.10:8080/"),
+      ("0.0.0.0:8080", "http://127.0.0.1:8080/"),
+      ("[::]:8080", "http://[::1]:8080/"),
+      ("[fd00::1]:8080", "http://[fd00::1]:8080/"),
+    ];
+
+    for (addr, expected) in cases {
+      assert_eq!(served_url(addr.parse().unwrap()), expected);
+    }
+  }
+
+  #[test]
+  fn browser_is_opened_at_served_url() {
+    let mut opened = None;
+
+    open_browser("0.0.0.0:8080".parse().unwrap(), |url| {
+      opened = Some(url.to_owned());
+      Ok(())
+    });
+
+    assert_eq!(opened.as_deref(), Some("http://127.0.0.1:8080/"));
+  }
+
+  #[test]
+  fn browser_launch_failure_is_not_fatal() {
+    open_browser("127.0.0.1:8080".parse().unwrap(), |_| {
+      Err(std::io::Error::other("no browser"))
+    });
+  }
+}
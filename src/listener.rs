@@ -0,0 +1,330 @@
+use std::{
+  collections::HashSet,
+  io::ErrorKind,
+  net::{IpAddr, Ipv6Addr, SocketAddr},
+  ops::RangeInclusive,
+};
+
+use log::{info, warn};
+use nix::{errno::Errno, ifaddrs::getifaddrs};
+use thiserror::Error;
+use tokio::net::TcpListener;
+
+use crate::Config;
+
+const PORT_RANGE: RangeInclusive<u16> = 7000..=9000;
+
+pub async fn get_tcp_listener(config: &Config) -> Result<TcpListener, ListenerError> {
+  bind_within(config, PORT_RANGE).await
+}
+
+async fn bind_within(
+  config: &Config,
+  port_range: RangeInclusive<u16>,
+) -> Result<TcpListener, ListenerError> {
+  let mut bind_attempts = 1;
+  let mut tried_ports: HashSet<u16> = HashSet::new();
+  let ip_address = decide_ip(config)?;
+  loop {
+    let port = decide_port(config, &port_range, &tried_ports);
+    tried_ports.insert(port);
+    let addr = SocketAddr::from((ip_address, port));
+
+    let listener = match TcpListener::bind(addr).await {
+      Ok(listener) => listener,
+      Err(err) => match err.kind() {
+        ErrorKind::AddrInUse => {
+          if config.port.is_some() {
+            return Err(ListenerError::AddressInUse(addr));
+          }
+
+          if bind_attempts >= config.socket_retries {
+            if config.port_fallback {
+              warn!(
+                "all {bind_attempts} attempts within ports {}-{} failed, falling back to a port assigned by the system",
+                port_range.start(),
+                port_range.end()
+              );
+              return bind_system_assigned_port(ip_address).await;
+            }
+
+            return Err(ListenerError::PortsExhausted {
+              ip: ip_address,
+              attempts: bind_attempts,
+              range: port_range,
+            });
+          }
+
+          info!(
+            "randomly selected address {addr} is in use, attempt {}/{}; retrying ...",
+            bind_attempts, config.socket_retries
+          );
+          bind_attempts += 1;
+          continue;
+        }
+        kind => {
+          return Err(ListenerError::BindFailure(addr, kind));
+        }
+      },
+    };
+
+    info!("accepting connections at {addr}");
+    return Ok(listener);
+  }
+}
+
+async fn bind_system_assigned_port(ip_address: IpAddr) -> Result<TcpListener, ListenerError> {
+  let addr = SocketAddr::from((ip_address, 0));
+  let listener = TcpListener::bind(addr)
+    .await
+    .map_err(|err| ListenerError::BindFailure(addr, err.kind()))?;
+  match listener.local_addr() {
+    Ok(addr) => info!("accepting connections at {addr}"),
+    Err(_) => info!("accepting connections at a port assigned by the system"),
+  }
+
+  Ok(listener)
+}
+
+#[derive(Debug, Error)]
+pub enum ListenerError {
+  #[error("could not probe for available interfaces - error number: {0}")]
+  InterfaceProbeFail(#[source] Errno),
+  #[error("could not resolve a bindable ip address for provided interface {0}")]
+  InterfaceAddressResolveFail(String),
+  #[error("address {0} is already in use")]
+  AddressInUse(SocketAddr),
+  #[error(
+    "all {attempts} attempts to bind to a random port within {}-{} on {ip} failed since the addresses were in use",
+    range.start(),
+    range.end()
+  )]
+  PortsExhausted {
+    ip: IpAddr,
+    attempts: u8,
+    range: RangeInclusive<u16>,
+  },
+  #[error("could not bind to address {0} - error kind: {1}")]
+  BindFailure(SocketAddr, ErrorKind),
+}
+
+fn decide_ip(config: &Config) -> Result<IpAddr, ListenerError> {
+  match config.interface {
+    Some(ref if_name) => select_interface_ip(if_name, &interface_addresses()?),
+    None => Ok(IpAddr::V4(config.ip_address)),
+  }
+}
+
+fn interface_addresses() -> Result<Vec<(String, IpAddr)>, ListenerError> {
+  let mut addresses: Vec<(String, IpAddr)> = Vec::new();
+  for ifaddr in getifaddrs().map_err(ListenerError::InterfaceProbeFail)? {
+    let Some(address) = ifaddr.address else {
+      continue;
+    };
+    if let Some(v4) = address.as_sockaddr_in() {
+      addresses.push((ifaddr.interface_name, IpAddr::V4(v4.ip())));
+    } else if let Some(v6) = address.as_sockaddr_in6() {
+      addresses.push((ifaddr.interface_name, IpAddr::V6(v6.ip())));
+    }
+  }
+
+  Ok(addresses)
+}
+
+// link-local v6 addresses cannot be bound without a scope id, so they are never selected
+fn select_interface_ip(
+  if_name: &str,
+  addresses: &[(String, IpAddr)],
+) -> Result<IpAddr, ListenerError> {
+  let mut v6_address: Option<Ipv6Addr> = None;
+  for (name, address) in addresses {
+    if name != if_name {
+      continue;
+    }
+
+    match address {
+      IpAddr::V4(_) => return Ok(*address),
+      IpAddr::V6(v6) if !v6.is_unicast_link_local() => {
+        v6_address.get_or_insert(*v6);
+      }
+      IpAddr::V6(_) => {}
+    }
+  }
+
+  v6_address
+    .map(IpAddr::V6)
+    .ok_or(ListenerError::InterfaceAddressResolveFail(
+      if_name.to_string(),
+    ))
+}
+
+// addresses are grouped by interface in the order the system reports them
+pub fn list_interfaces() -> Result<Vec<(String, Vec<String>)>, ListenerError> {
+  let mut interfaces: Vec<(String, Vec<String>)> = Vec::new();
+  for ifaddr in getifaddrs().map_err(ListenerError::InterfaceProbeFail)? {
+    let address = ifaddr.address.and_then(|addr| {
+      if let Some(v4) = addr.as_sockaddr_in() {
+        return Some(v4.ip().to_string());
+      }
+
+      addr.as_sockaddr_in6().map(|v6| v6.ip().to_string())
+    });
+
+    let idx = match interfaces
+      .iter()
+      .position(|(name, _)| *name == ifaddr.interface_name)
+    {
+      Some(idx) => idx,
+      None => {
+        interfaces.push((ifaddr.interface_name, Vec::new()));
+        interfaces.len() - 1
+      }
+    };
+
+    if let Some(address) = address {
+      interfaces[idx].1.push(address);
+    }
+  }
+
+  Ok(interfaces)
+}
+
+// ports that were already tried are skipped, so retries never collide on the same port twice
+fn decide_port(
+  config: &Config,
+  port_range: &RangeInclusive<u16>,
+  tried_ports: &HashSet<u16>,
+) -> u16 {
+  if let Some(port) = config.port {
+    return port;
+  }
+
+  loop {
+    let port = rand::random_range(port_range.clone());
+    if !tried_ports.contains(&port) {
+      return port;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const OCCUPIED_PORTS: u8 = 3;
+
+  // keeps a few consecutive loopback ports bound, so every attempt within them collides
+  fn occupy_consecutive_ports() -> (RangeInclusive<u16>, Vec<std::net::TcpListener>) {
+    let count = u16::from(OCCUPIED_PORTS);
+    for start in (20000..30000).step_by(count.into()) {
+      let listeners: Vec<std::net::TcpListener> = (start..start + count)
+        .map_while(|port| std::net::TcpListener::bind(("127.0.0.1", port)).ok())
+        .collect();
+      if listeners.len() == usize::from(count) {
+        return (start..=start + count - 1, listeners);
+      }
+    }
+
+    panic!("no consecutive free ports were found");
+  }
+
+  fn addresses(entries: &[(&str, &str)]) -> Vec<(String, IpAddr)> {
+    entries
+      .iter()
+      .map(|(name, address)| (name.to_string(), address.parse().unwrap()))
+      .collect()
+  }
+
+  #[test]
+  fn interface_v4_address_is_preferred() {
+    let addresses = addresses(&[
+      ("eth0", "2001:db8::1"),
+      ("lo", "127.0.0.1"),
+      ("eth0", "192.168.0.2"),
+    ]);
+
+    let ip = select_interface_ip("eth0", &addresses).unwrap();
+
+    assert_eq!(ip, "192.168.0.2".parse::<IpAddr>().unwrap());
+  }
+
+  #[test]
+  fn link_local_address_of_v6_only_interface_is_skipped() {
+    let addresses = addresses(&[("eth0", "fe80::1"), ("eth0", "2001:db8::1")]);
+
+    let ip = select_interface_ip("eth0", &addresses).unwrap();
+
+    assert_eq!(ip, "2001:db8::1".parse::<IpAddr>().unwrap());
+  }
+
+  #[test]
+  fn interface_with_only_link_local_address_is_not_resolved() {
+    let addresses = addresses(&[("eth0", "fe80::1"), ("lo", "::1")]);
+
+    let err = select_interface_ip("eth0", &addresses).unwrap_err();
+
+    assert!(
+      matches!(err, ListenerError::InterfaceAddressResolveFail(ref name) if name == "eth0"),
+      "{err:?}"
+    );
+  }
+
+  #[tokio::test]
+  async fn exhausted_retries_report_every_attempt_within_range() {
+    let (range, _listeners) = occupy_consecutive_ports();
+    let config = Config {
+      socket_retries: OCCUPIED_PORTS,
+      ..Config::default()
+    };
+
+    let err = bind_within(&config, range.clone()).await.unwrap_err();
+
+    let ListenerError::PortsExhausted {
+      attempts,
+      range: ref exhausted,
+      ..
+    } = err
+    else {
+      panic!("{err:?}");
+    };
+    assert_eq!(attempts, OCCUPIED_PORTS);
+    assert_eq!(*exhausted, range);
+    let msg = err.to_string();
+    assert!(
+      msg.contains(&format!("all {OCCUPIED_PORTS} attempts")),
+      "{msg}"
+    );
+    assert!(
+      msg.contains(&format!("{}-{}", range.start(), range.end())),
+      "{msg}"
+    );
+  }
+
+  #[tokio::test]
+  async fn exhausted_retries_fall_back_to_system_assigned_port() {
+    let (range, _listeners) = occupy_consecutive_ports();
+    let config = Config {
+      socket_retries: OCCUPIED_PORTS,
+      port_fallback: true,
+      ..Config::default()
+    };
+
+    let listener = bind_within(&config, range.clone()).await.unwrap();
+
+    assert!(!range.contains(&listener.local_addr().unwrap().port()));
+  }
+
+  #[test]
+  fn listed_interfaces_include_loopback() {
+    let interfaces = list_interfaces().unwrap();
+
+    let (_, addresses) = interfaces
+      .iter()
+      .find(|(name, _)| name == "lo")
+      .expect("loopback interface is missing");
+    assert!(addresses.iter().any(|address| address == "127.0.0.1"));
+    let names: std::collections::HashSet<&String> =
+      interfaces.iter().map(|(name, _)| name).collect();
+    assert_eq!(names.len(), interfaces.len());
+  }
+}
@@ -1,37 +1,35 @@
-use clap::Parser;
-use log::{error, info, warn};
-use nix::{errno::Errno, ifaddrs::getifaddrs};
-use std::ops::DerefMut;
-use std::{
-  error::Error, fmt::Display, io::ErrorKind, net::Ipv4Addr, ops::RangeInclusive, path::PathBuf,
-  sync::Arc, time::SystemTime,
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum, error::ErrorKind};
+use ipnet::IpNet;
+use log::LevelFilter;
+use mpv_web_client::{
+  ApiCredentials, DEFAULT_API_SERVER_READY_TIMEOUT, DEFAULT_API_SERVER_RECENT_OUTPUT_LINES,
+  DEFAULT_API_SERVER_STOP_GRACE_PERIOD, DEFAULT_FRONTEND_INIT_TIMEOUT,
+  DEFAULT_IDLE_SHUTDOWN_TIMEOUT, DEFAULT_IPADDR, DEFAULT_MAX_REQUEST_BODY_SIZE,
+  DEFAULT_MIN_COMPRESSION_SIZE, DEFAULT_RELEASES_URL, DEFAULT_SOCKET_RETRIES,
+  DEFAULT_STREAM_CHUNK_SIZE, ListenerError, UpdateTrack, VERSION, list_interfaces, run, verify,
 };
-use tokio::{net::TcpListener, sync::Mutex};
-
-use crate::{
-  api_servers::ApiServersService,
-  frontend::{init_frontend, pkg::repository::PackagesRepository},
-  project_paths::ensure_project_dirs,
-  server::serve,
+use serde::Deserialize;
+use std::{
+  net::Ipv4Addr,
+  path::{Path, PathBuf},
+  time::SystemTime,
 };
-use std::net::SocketAddr;
+use thiserror::Error;
 
-mod api_servers;
-mod common;
-mod frontend;
-mod project_paths;
-mod server;
+use crate::config_file::{ConfigFileErr, read_config_file};
 
-const DEFAULT_IPADDR: [u8; 4] = [127, 0, 0, 1];
-const PORT_RANGE: RangeInclusive<u16> = 7000..=9000;
-const DEFAULT_SOCKET_RETRIES: u8 = 8;
-const DEFAULT_IDLE_SHUTDOWN_TIMEOUT: u8 = 60;
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-const API_SERVICE_SHUTDOWN_TIMEOUT: u8 = 30;
+mod config_file;
 
 #[derive(Parser, Debug)]
 #[command(version = VERSION, about = "client for mpv-web-api and mpv-web-front server", long_about = None)]
 struct Args {
+  #[arg(
+    long,
+    required = false,
+    help = "Path to a TOML config file with values for any of the other options. Options provided on the command line take precedence."
+  )]
+  config: Option<PathBuf>,
+
   #[arg(
     long,
     default_value_t = Ipv4Addr::from(DEFAULT_IPADDR),
@@ -40,7 +38,12 @@ struct Args {
   )]
   ip_address: Ipv4Addr,
 
-  #[arg(long, required = false, help = "Port used for serving frontend")]
+  #[arg(
+    long,
+    required = false,
+    value_parser = clap::value_parser!(u16).range(1..),
+    help = "Port used for serving frontend"
+  )]
   port: Option<u16>,
 
   #[arg(
@@ -51,6 +54,14 @@ struct Args {
   )]
   socket_retries: u8,
 
+  #[arg(
+    action,
+    long,
+    required = false,
+    help = "Bind to a port assigned by the system when all --socket-retries attempts to bind to a random port fail. Does not apply when --port provided."
+  )]
+  port_fallback: bool,
+
   #[arg(
     long,
     required = false,
@@ -58,6 +69,22 @@ struct Args {
   )]
   interface: Option<String>,
 
+  #[arg(
+    action,
+    long,
+    required = false,
+    help = "Print available interfaces with their addresses and exit."
+  )]
+  list_interfaces: bool,
+
+  #[arg(
+    action,
+    long,
+    required = false,
+    help = "Check project directories, the installed frontend package, the mpv-web-api binary and remote releases availability, print a report and exit. Exits with an error when any critical check fails."
+  )]
+  verify: bool,
+
   #[arg(
     long,
     required = false,
@@ -65,6 +92,29 @@ struct Args {
   )]
   pkg: Option<PathBuf>,
 
+  #[arg(
+    long,
+    required = false,
+    help = "Serve frontend files directly from the provided directory instead of the installed package. Useful for frontend development."
+  )]
+  serve_dir: Option<PathBuf>,
+
+  #[arg(
+    action,
+    long,
+    required = false,
+    requires = "serve_dir",
+    help = "Watch the directory provided with --serve-dir and notify subscribers of /api/frontend/reload-events when its files change."
+  )]
+  watch: bool,
+
+  #[arg(
+    long,
+    required = false,
+    help = "Directory for the installed frontend, api server logs and saved api servers. Defaults to the MWC_HOME environment variable or ~/.mwc when not set."
+  )]
+  data_dir: Option<PathBuf>,
+
   #[arg(
     action,
     short = 'u',
@@ -74,6 +124,31 @@ struct Args {
   )]
   update: bool,
 
+  #[arg(
+    long,
+    value_enum,
+    default_value_t = UpdateTrack::Any,
+    required = false,
+    help = "Constrains updates to releases sharing the installed major version (\"major\"), major and minor versions (\"minor\") or allows any newer release (\"any\")."
+  )]
+  update_track: UpdateTrack,
+
+  #[arg(
+    action,
+    long,
+    required = false,
+    help = "Skip all network operations - checks for and downloads of remote frontend releases. Only the installed package or one provided with --pkg is served."
+  )]
+  offline: bool,
+
+  #[arg(
+    action,
+    long,
+    required = false,
+    help = "Extract a fetched frontend package while it is being downloaded, without storing the archive on disk."
+  )]
+  stream_install: bool,
+
   #[arg(
     action,
     short = 'f',
@@ -99,166 +174,453 @@ struct Args {
     help = "Enables server idle timeout mechanism which shuts server down when the server does not receive any requests in specified timeout interval."
   )]
   enable_idle_shutdown_timeout: bool,
+
+  #[arg(
+    action,
+    long,
+    required = false,
+    help = "Open the default browser at the served address once the server is listening."
+  )]
+  open: bool,
+
+  #[arg(
+    action,
+    long,
+    required = false,
+    help = "Advertise the server over mDNS as a \"_mpv-web._tcp\" service, so it can be discovered by other devices in the local network."
+  )]
+  advertise: bool,
+
+  #[arg(
+    action,
+    long,
+    required = false,
+    help = "Print the served URL with a QR code to stdout once the server is listening."
+  )]
+  qr: bool,
+
+  #[arg(
+    long,
+    required = false,
+    help = "Value of the Content-Security-Policy header sent with frontend files. The header is not sent when not provided."
+  )]
+  content_security_policy: Option<String>,
+
+  #[arg(
+    long,
+    default_value_t = DEFAULT_STREAM_CHUNK_SIZE,
+    required = false,
+    value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+    help = "Size in bytes of chunks in which frontend files are read and sent. Each served file allocates a buffer of this size."
+  )]
+  stream_chunk_size: usize,
+
+  #[arg(
+    long,
+    default_value_t = DEFAULT_MIN_COMPRESSION_SIZE,
+    required = false,
+    help = "Size in bytes below which frontend files are served plain even when a pre-compressed variant exists and is accepted. Use 0 to always prefer the compressed variant."
+  )]
+  min_compression_size: u64,
+
+  #[arg(
+    long,
+    value_name = "EXTENSION=MIME",
+    required = false,
+    value_parser = parse_mime_type,
+    help = "Content type sent for frontend files with the given extension, e.g. \"wasm=application/wasm\". Takes precedence over the built-in types. Can be repeated."
+  )]
+  mime_type: Vec<(String, String)>,
+
+  #[arg(
+    long,
+    default_value_t = DEFAULT_API_SERVER_RECENT_OUTPUT_LINES,
+    required = false,
+    help = "Number of the most recent output lines of each spawned api server kept in memory."
+  )]
+  api_server_recent_output_lines: usize,
+
+  #[arg(
+    long,
+    default_value_t = DEFAULT_API_SERVER_STOP_GRACE_PERIOD,
+    required = false,
+    help = "Time in seconds a spawned api server is given to exit after SIGTERM before it is killed with SIGKILL."
+  )]
+  api_server_stop_grace_period: u32,
+
+  #[arg(
+    long,
+    default_value_t = DEFAULT_API_SERVER_READY_TIMEOUT,
+    required = false,
+    help = "Time in seconds to wait for a spawned api server to accept connections when readiness is requested."
+  )]
+  api_server_ready_timeout: u32,
+
+  #[arg(
+    long,
+    default_value_t = DEFAULT_FRONTEND_INIT_TIMEOUT,
+    required = false,
+    help = "Time in seconds the startup check and fetch of remote frontend releases may take. After it elapses the already installed package is served."
+  )]
+  frontend_init_timeout: u32,
+
+  #[arg(
+    long,
+    default_value_t = DEFAULT_MAX_REQUEST_BODY_SIZE,
+    required = false,
+    help = "Maximum size in bytes of a request body accepted by the api."
+  )]
+  max_request_body_size: usize,
+
+  #[arg(
+    long,
+    value_enum,
+    default_value_t = LogLevel::Debug,
+    required = false,
+    help = "Minimum severity of logged records."
+  )]
+  log_level: LogLevel,
+
+  #[arg(
+    long,
+    required = false,
+    help = "Path to a file to which logs are appended instead of being written to stdout."
+  )]
+  log_file: Option<PathBuf>,
+
+  #[arg(
+    long,
+    value_enum,
+    default_value_t = LogFormat::Text,
+    required = false,
+    help = "Format of logged records - human readable \"text\" or single-line \"json\" objects."
+  )]
+  log_format: LogFormat,
+
+  #[arg(
+    long,
+    required = false,
+    requires = "api_password",
+    help = "User required with HTTP Basic auth for all /api routes. Requires --api-password."
+  )]
+  api_user: Option<String>,
+
+  #[arg(
+    long,
+    required = false,
+    requires = "api_user",
+    help = "Password required with HTTP Basic auth for all /api routes. Requires --api-user."
+  )]
+  api_password: Option<String>,
+
+  #[arg(
+    long = "api-allow",
+    value_name = "CIDR",
+    required = false,
+    help = "Network in CIDR notation allowed to use /api/servers and /api/shutdown routes. Can be repeated. All addresses are allowed when not provided."
+  )]
+  api_allow: Vec<IpNet>,
+
+  #[arg(
+    long,
+    required = false,
+    help = "Token required in the X-Shutdown-Token header of /api/shutdown requests. The /api/shutdown route is disabled when not provided."
+  )]
+  shutdown_token: Option<String>,
+
+  #[arg(
+    long,
+    required = false,
+    value_parser = clap::value_parser!(u32).range(1..),
+    help = "Number of requests per second allowed from a single address. Requests over the limit are rejected with 429. Rate limiting is disabled when not provided."
+  )]
+  rate_limit: Option<u32>,
+
+  #[arg(
+    long,
+    required = false,
+    requires = "rate_limit",
+    value_parser = clap::value_parser!(u32).range(1..),
+    help = "Number of requests a single address can make in a burst before --rate-limit applies. Defaults to the value of --rate-limit."
+  )]
+  rate_limit_burst: Option<u32>,
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+enum LogFormat {
+  Text,
+  Json,
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+enum LogLevel {
+  Trace,
+  Debug,
+  Info,
+  Warn,
+  Error,
+}
+
+impl From<LogLevel> for LevelFilter {
+  fn from(level: LogLevel) -> Self {
+    match level {
+      LogLevel::Trace => LevelFilter::Trace,
+      LogLevel::Debug => LevelFilter::Debug,
+      LogLevel::Info => LevelFilter::Info,
+      LogLevel::Warn => LevelFilter::Warn,
+      LogLevel::Error => LevelFilter::Error,
+    }
+  }
 }
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<(), Box<dyn Error>> {
-  let args = Args::parse();
-
-  init_logging()?;
-  info!("version {VERSION}");
-
-  let project_dirs = ensure_project_dirs()?;
-  let api_service = ApiServersService::new(project_dirs.logs_dir);
-  let mut packages_repository = PackagesRepository::new();
-  init_frontend(
-    args.pkg.clone(),
-    args.update,
-    args.force_outdated,
-    &mut packages_repository,
-  )
-  .await
-  .map_err(|err_msg| *Box::new(err_msg))?;
-  let idle_shutdown_interval = if args.enable_idle_shutdown_timeout {
-    warn!(
-      "server will shut down after being idle for {} seconds!",
-      &args.idle_shutdown_timeout
-    );
-    Some(args.idle_shutdown_timeout)
-  } else {
-    None
-  };
-
-  let tcp_listener = get_tcp_listener(&args)
-    .await
-    .map_err(|err| *Box::new(err))?;
-  let server_dependencies = server::Dependencies {
-    packages_repository: Arc::new(Mutex::new(packages_repository)),
-    api_service: Arc::new(Mutex::new(api_service)),
-  };
-
-  if let Err(err) = serve(tcp_listener, idle_shutdown_interval, &server_dependencies).await {
-    error!("error encountered while serving frontend: {err}");
-    return Err(err);
+async fn main() -> Result<(), MainError> {
+  let matches = Args::command().get_matches();
+  let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+  if let Some(config_path) = args.config.clone() {
+    read_config_file(&config_path)?.merge_into(&mut args, &matches);
+  }
+
+  if args.api_user.is_some() != args.api_password.is_some() {
+    Args::command()
+      .error(
+        ErrorKind::MissingRequiredArgument,
+        "--api-user and --api-password have to be provided together",
+      )
+      .exit();
   }
 
-  server_dependencies
-    .api_service
-    .lock()
-    .await
-    .deref_mut()
-    .shutdown(API_SERVICE_SHUTDOWN_TIMEOUT.into())
-    .await;
+  if args.list_interfaces {
+    for (name, addresses) in list_interfaces()? {
+      println!("{name}: {}", addresses.join(", "));
+    }
+    return Ok(());
+  }
+
+  if args.verify {
+    if !verify(&args.into_config()).await {
+      return Err(MainError::Verify);
+    }
+    return Ok(());
+  }
+
+  init_logging(args.log_level, args.log_format, args.log_file.as_deref())?;
+  run(args.into_config()).await?;
 
   Ok(())
 }
 
-async fn get_tcp_listener(args: &Args) -> Result<TcpListener, ListenerError> {
-  let mut bind_attempts = 1;
-  let ip_address = decide_ip(args)?;
-  loop {
-    let port = decide_port(args);
-    let addr = SocketAddr::from((ip_address, port));
-
-    let listener = match TcpListener::bind(addr).await {
-      Ok(listener) => listener,
-      Err(err) => match err.kind() {
-        ErrorKind::AddrInUse => {
-          if args.port.is_some() || bind_attempts >= args.socket_retries {
-            return Err(ListenerError::AddressInUse(addr));
-          }
-
-          info!(
-            "randomly selected address {addr} is in use, attempt {}/{}; retrying ...",
-            bind_attempts, args.socket_retries
-          );
-          bind_attempts += 1;
-          continue;
-        }
-        kind => {
-          return Err(ListenerError::BindFailure(addr, kind));
-        }
-      },
-    };
-
-    info!("accepting connections at {addr}");
-    return Ok(listener);
+impl Args {
+  fn into_config(self) -> mpv_web_client::Config {
+    mpv_web_client::Config {
+      ip_address: self.ip_address,
+      port: self.port,
+      socket_retries: self.socket_retries,
+      port_fallback: self.port_fallback,
+      interface: self.interface,
+      pkg: self.pkg,
+      serve_dir: self.serve_dir,
+      watch: self.watch,
+      data_dir: self.data_dir,
+      update: self.update,
+      update_track: self.update_track,
+      offline: self.offline,
+      stream_install: self.stream_install,
+      force_outdated: self.force_outdated,
+      idle_shutdown_timeout: self
+        .enable_idle_shutdown_timeout
+        .then_some(self.idle_shutdown_timeout),
+      open: self.open,
+      advertise: self.advertise,
+      qr: self.qr,
+      content_security_policy: self.content_security_policy,
+      api_server_recent_output_lines: self.api_server_recent_output_lines,
+      api_server_stop_grace_period: self.api_server_stop_grace_period,
+      api_server_ready_timeout: self.api_server_ready_timeout,
+      frontend_init_timeout: self.frontend_init_timeout,
+      max_request_body_size: self.max_request_body_size,
+      stream_chunk_size: self.stream_chunk_size,
+      min_compression_size: self.min_compression_size,
+      mime_types: self.mime_type,
+      api_credentials: self
+        .api_user
+        .zip(self.api_password)
+        .map(|(user, password)| ApiCredentials { user, password }),
+      api_allowlist: self.api_allow,
+      shutdown_token: self.shutdown_token,
+      rate_limit: self.rate_limit,
+      rate_limit_burst: self.rate_limit_burst,
+      releases_url: DEFAULT_RELEASES_URL.to_owned(),
+    }
   }
 }
 
-#[derive(Clone)]
-enum ListenerError {
-  InterfaceProbeFail(Errno),
-  InterfaceAddressResolveFail(String),
-  AddressInUse(SocketAddr),
-  BindFailure(SocketAddr, ErrorKind),
+fn parse_mime_type(value: &str) -> Result<(String, String), String> {
+  let (extension, mime_type) = value
+    .split_once('=')
+    .ok_or_else(|| "expected a value in the EXTENSION=MIME format".to_owned())?;
+  validate_mime_type(extension, mime_type)?;
+
+  Ok((extension.to_owned(), mime_type.to_owned()))
 }
 
-impl Display for ListenerError {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    match &self {
-      ListenerError::AddressInUse(addr) => write!(f, "address {addr} is already in use"),
-      ListenerError::BindFailure(addr, kind) => {
-        write!(f, "could not bind to address {addr} - error kind: {kind}")
-      }
-      ListenerError::InterfaceProbeFail(errno) => write!(
-        f,
-        "could not probe for available interfaces - error number: {errno}"
-      ),
-      ListenerError::InterfaceAddressResolveFail(if_name) => write!(
-        f,
-        "could not resolve ip address for provided interface {if_name}"
-      ),
-    }
+fn validate_mime_type(extension: &str, mime_type: &str) -> Result<(), String> {
+  if extension.trim_start_matches('.').is_empty() {
+    return Err("extension cannot be empty".to_owned());
   }
+
+  mime_type
+    .parse::<mime_guess::Mime>()
+    .map(|_| ())
+    .map_err(|err| format!("\"{mime_type}\" is not a valid mime type: {err}"))
+}
+
+#[derive(Error)]
+enum MainError {
+  #[error(transparent)]
+  Config(#[from] ConfigFileErr),
+  #[error("could not initialize logging: {0}")]
+  Logging(#[from] fern::InitError),
+  #[error(transparent)]
+  Listener(#[from] ListenerError),
+  #[error(transparent)]
+  Run(#[from] mpv_web_client::Error),
+  #[error("installation verification failed")]
+  Verify,
 }
 
-impl std::fmt::Debug for ListenerError {
+// main prints the returned error with Debug, so it shows the same message as Display
+impl std::fmt::Debug for MainError {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     write!(f, "{}", &self)
   }
 }
 
-impl Error for ListenerError {}
-
-fn decide_ip(args: &Args) -> Result<Ipv4Addr, ListenerError> {
-  let if_name = match args.interface {
-    Some(ref name) => name,
-    None => return Ok(args.ip_address),
-  };
-
-  let mut ifaddrs_iter = getifaddrs().map_err(ListenerError::InterfaceProbeFail)?;
+fn init_logging(
+  level: LogLevel,
+  format: LogFormat,
+  log_file: Option<&Path>,
+) -> Result<(), fern::InitError> {
+  let dispatch = log_dispatch(level, format);
+  match log_file {
+    Some(path) => dispatch.chain(fern::log_file(path)?),
+    None => dispatch.chain(std::io::stdout()),
+  }
+  .apply()?;
+  Ok(())
+}
 
-  ifaddrs_iter
-    .find_map(|ifadrr| {
-      if ifadrr.interface_name != *if_name {
-        return None;
+fn log_dispatch(level: LogLevel, format: LogFormat) -> fern::Dispatch {
+  fern::Dispatch::new()
+    .format(move |out, message, record| {
+      let timestamp = humantime::format_rfc3339_seconds(SystemTime::now());
+      match format {
+        LogFormat::Text => out.finish(format_args!(
+          "{} {} {} # {}",
+          timestamp,
+          record.level(),
+          record.target(),
+          message
+        )),
+        LogFormat::Json => out.finish(format_args!(
+          "{}",
+          json_log_line(&timestamp.to_string(), message, record)
+        )),
       }
-
-      Some(ifadrr.address?.as_sockaddr_in()?.ip())
     })
-    .ok_or(ListenerError::InterfaceAddressResolveFail(
-      if_name.to_string(),
-    ))
+    .level(level.into())
 }
 
-fn decide_port(args: &Args) -> u16 {
-  args.port.unwrap_or(rand::random_range(PORT_RANGE))
+fn json_log_line(timestamp: &str, message: &std::fmt::Arguments, record: &log::Record) -> String {
+  serde_json::json!({
+    "timestamp": timestamp,
+    "level": record.level().as_str(),
+    "target": record.target(),
+    "message": message.to_string(),
+  })
+  .to_string()
 }
 
-fn init_logging() -> Result<(), fern::InitError> {
-  fern::Dispatch::new()
-    .format(|out, message, record| {
-      out.finish(format_args!(
-        "{} {} {} # {}",
-        humantime::format_rfc3339_seconds(SystemTime::now()),
-        record.level(),
-        record.target(),
-        message
-      ))
-    })
-    .level(log::LevelFilter::Debug)
-    .chain(std::io::stdout())
-    .apply()?;
-  Ok(())
+#[cfg(test)]
+mod tests {
+  use std::sync::mpsc::{Receiver, channel};
+
+  use log::{Level, Log, Record};
+
+  use super::*;
+
+  fn capture_logs(level: LogLevel, format: LogFormat) -> (Box<dyn Log>, Receiver<String>) {
+    let (sender, receiver) = channel();
+    let (_, logger) = log_dispatch(level, format).chain(sender).into_log();
+    (logger, receiver)
+  }
+
+  fn log_message(logger: &dyn Log, level: Level, message: &str) {
+    logger.log(
+      &Record::builder()
+        .level(level)
+        .target("mwc::fixture")
+        .args(format_args!("{message}"))
+        .build(),
+    );
+  }
+
+  #[test]
+  fn level_filters_lower_severity_records() {
+    let (logger, logs) = capture_logs(LogLevel::Warn, LogFormat::Text);
+
+    log_message(logger.as_ref(), Level::Debug, "debug message");
+    log_message(logger.as_ref(), Level::Info, "info message");
+    log_message(logger.as_ref(), Level::Warn, "warn message");
+    log_message(logger.as_ref(), Level::Error, "error message");
+    drop(logger);
+
+    let lines: Vec<String> = logs.iter().collect();
+    assert_eq!(lines.len(), 2, "{lines:?}");
+    assert!(
+      lines[0]
+        .trim_end()
+        .ends_with("WARN mwc::fixture # warn message")
+    );
+    assert!(
+      lines[1]
+        .trim_end()
+        .ends_with("ERROR mwc::fixture # error message")
+    );
+  }
+
+  #[test]
+  fn port_zero_is_rejected() {
+    let err = Args::try_parse_from(["mpv-web-client", "--port", "0"]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ValueValidation);
+
+    for port in ["1", "8080", "65535"] {
+      let args = Args::try_parse_from(["mpv-web-client", "--port", port]).unwrap();
+      assert_eq!(args.port, Some(port.parse().unwrap()));
+    }
+    let args = Args::try_parse_from(["mpv-web-client"]).unwrap();
+    assert_eq!(args.port, None);
+  }
+
+  #[test]
+  fn json_line_holds_record_fields() {
+    let timestamp = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+    let message = format_args!("served {} bytes", 42);
+    let record = Record::builder()
+      .level(Level::Info)
+      .target("mwc::fixture")
+      .args(message)
+      .build();
+
+    let line = json_log_line(&timestamp, &message, &record);
+
+    let parsed: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(parsed["timestamp"], timestamp);
+    assert!(humantime::parse_rfc3339(parsed["timestamp"].as_str().unwrap()).is_ok());
+    assert_eq!(parsed["level"], "INFO");
+    assert_eq!(parsed["target"], "mwc::fixture");
+    assert_eq!(parsed["message"], "served 42 bytes");
+  }
 }
@@ -1,17 +1,38 @@
 use std::{
   env::{self},
+  ffi::OsString,
   fs::create_dir_all,
-  path::PathBuf,
+  path::{Path, PathBuf},
 };
 
 const PROJECT_SUBDIR: &str = ".mwc";
-pub fn get_project_home_dir() -> Result<PathBuf, std::io::Error> {
-  let mut src_path = match env::home_dir() {
+const DATA_DIR_ENV: &str = "MWC_HOME";
+// the provided data dir takes precedence over MWC_HOME and the home directory
+pub fn get_project_home_dir(data_dir: Option<&Path>) -> Result<PathBuf, std::io::Error> {
+  resolve_project_home_dir(data_dir, env::var_os(DATA_DIR_ENV), env::home_dir())
+}
+
+fn resolve_project_home_dir(
+  data_dir: Option<&Path>,
+  data_dir_env: Option<OsString>,
+  home_dir: Option<PathBuf>,
+) -> Result<PathBuf, std::io::Error> {
+  if let Some(path) = data_dir {
+    return Ok(path.to_path_buf());
+  }
+
+  if let Some(path) = data_dir_env.filter(|path| !path.is_empty()) {
+    return Ok(PathBuf::from(path));
+  }
+
+  let mut src_path = match home_dir {
     Some(path) => path,
     None => {
       return Err(std::io::Error::new(
         std::io::ErrorKind::NotFound,
-        "could not resolve home path",
+        format!(
+          "could not resolve home directory - provide a data directory with --data-dir or the {DATA_DIR_ENV} environment variable"
+        ),
       ));
     }
   };
@@ -21,53 +42,137 @@ pub fn get_project_home_dir() -> Result<PathBuf, std::io::Error> {
 }
 
 const FRONTEND_DIR: &str = "frontend";
-pub fn get_frontend_dir() -> Result<PathBuf, std::io::Error> {
-  let mut home_dir = get_project_home_dir()?;
-  home_dir.push(FRONTEND_DIR);
-
-  Ok(home_dir)
+pub fn get_frontend_dir(home_dir: &Path) -> PathBuf {
+  home_dir.join(FRONTEND_DIR)
 }
 
 const LOGS_DIR: &str = "logs";
-pub fn get_logs_dir() -> Result<PathBuf, std::io::Error> {
-  let mut home_dir = get_project_home_dir()?;
-  home_dir.push(LOGS_DIR);
-
-  Ok(home_dir)
+pub fn get_logs_dir(home_dir: &Path) -> PathBuf {
+  home_dir.join(LOGS_DIR)
 }
 
-pub fn get_temp_dir() -> PathBuf {
-  let mut path = env::temp_dir();
-  path.push(PROJECT_SUBDIR);
+const SAVED_API_SERVERS_FILE: &str = "api_servers.json";
+pub fn get_saved_api_servers_path(home_dir: &Path) -> PathBuf {
+  home_dir.join(SAVED_API_SERVERS_FILE)
+}
 
-  path
+const TEMP_DIR: &str = "tmp";
+// kept under the project home, so clients with different data dirs never share an extraction dir
+pub fn get_temp_dir(home_dir: &Path) -> PathBuf {
+  home_dir.join(TEMP_DIR)
 }
 
 pub struct ProjectDirs {
+  pub home_dir: PathBuf,
   pub logs_dir: PathBuf,
-  pub temp_dir: PathBuf,
-  pub project_dir: PathBuf,
+  pub saved_api_servers_path: PathBuf,
 }
 
-pub fn ensure_project_dirs() -> Result<ProjectDirs, std::io::Error> {
-  let temp_dir = get_temp_dir();
-  create_dir_all(&temp_dir)?;
+pub fn ensure_project_dirs(data_dir: Option<&Path>) -> Result<ProjectDirs, std::io::Error> {
+  let home_dir = get_project_home_dir(data_dir)?;
+  create_project_dir(&home_dir)?;
+  create_project_dir(&get_temp_dir(&home_dir))?;
+  create_project_dir(&get_frontend_dir(&home_dir))?;
 
-  let project_dir = get_project_home_dir()?;
-  create_dir_all(&project_dir)?;
-
-  let logs_dir = get_logs_dir()?;
-  create_dir_all(&logs_dir)?;
+  let logs_dir = get_logs_dir(&home_dir);
+  create_project_dir(&logs_dir)?;
 
   Ok(ProjectDirs {
-    temp_dir,
-    project_dir,
+    saved_api_servers_path: get_saved_api_servers_path(&home_dir),
+    home_dir,
     logs_dir,
   })
 }
 
-pub fn get_frontend_temp_dir() -> PathBuf {
-  let mut dir = get_temp_dir();
-  dir.push(FRONTEND_DIR);
-  dir
+fn create_project_dir(path: &Path) -> Result<(), std::io::Error> {
+  create_dir_all(path).map_err(|err| {
+    std::io::Error::new(
+      err.kind(),
+      format!(
+        "could not create directory {}: {err}",
+        path.to_string_lossy()
+      ),
+    )
+  })
+}
+
+pub fn get_frontend_temp_dir(home_dir: &Path) -> PathBuf {
+  get_temp_dir(home_dir).join(FRONTEND_DIR)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{ffi::OsString, path::PathBuf};
+
+  use super::{ensure_project_dirs, get_frontend_temp_dir, resolve_project_home_dir};
+
+  #[test]
+  fn missing_home_points_at_data_dir_options() {
+    let err = resolve_project_home_dir(None, None, None).unwrap_err();
+
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    assert!(err.to_string().contains("--data-dir"), "{err}");
+    assert!(err.to_string().contains("MWC_HOME"), "{err}");
+  }
+
+  #[test]
+  fn data_dir_takes_precedence() {
+    let data_dir = PathBuf::from("/srv/mwc");
+
+    let resolved = resolve_project_home_dir(
+      Some(&data_dir),
+      Some(OsString::from("/env/mwc")),
+      Some(PathBuf::from("/home/user")),
+    );
+
+    assert_eq!(resolved.unwrap(), data_dir);
+  }
+
+  #[test]
+  fn empty_env_falls_back_to_home() {
+    let resolved = resolve_project_home_dir(
+      None,
+      Some(OsString::new()),
+      Some(PathBuf::from("/home/user")),
+    );
+
+    assert_eq!(resolved.unwrap(), PathBuf::from("/home/user/.mwc"));
+  }
+
+  #[test]
+  fn temp_dirs_are_kept_under_project_home() {
+    let first = get_frontend_temp_dir(&PathBuf::from("/srv/first"));
+    let second = get_frontend_temp_dir(&PathBuf::from("/srv/second"));
+
+    assert!(first.starts_with("/srv/first"), "{}", first.display());
+    assert!(second.starts_with("/srv/second"), "{}", second.display());
+  }
+
+  #[test]
+  fn every_project_dir_is_created() {
+    let home_dir = std::env::temp_dir()
+      .join(format!("mwc-project-dirs-{}", std::process::id()))
+      .join("nested");
+    let _ = std::fs::remove_dir_all(&home_dir);
+
+    let dirs = ensure_project_dirs(Some(&home_dir)).unwrap();
+
+    assert_eq!(dirs.home_dir, home_dir);
+    assert_eq!(dirs.logs_dir, home_dir.join("logs"));
+    assert_eq!(
+      dirs.saved_api_servers_path,
+      home_dir.join("api_servers.json")
+    );
+    for dir in [
+      home_dir.clone(),
+      home_dir.join("tmp"),
+      home_dir.join("frontend"),
+      home_dir.join("logs"),
+    ] {
+      assert!(dir.is_dir(), "{}", dir.display());
+    }
+    assert!(!dirs.saved_api_servers_path.exists());
+    // already existing dirs are not an error
+    ensure_project_dirs(Some(&home_dir)).unwrap();
+  }
 }
@@ -0,0 +1,83 @@
+use std::net::{IpAddr, SocketAddr};
+
+use log::warn;
+use nix::ifaddrs::getifaddrs;
+use qrcode::{QrCode, render::unicode::Dense1x2, types::QrError};
+
+use crate::served_url;
+
+pub fn print_qr_code(addr: SocketAddr) {
+  let url = qr_code_url(addr);
+  match render_qr_code(&url) {
+    Ok(rendered) => println!("{url}\n{rendered}"),
+    Err(err) => warn!("could not encode {url} as a QR code: {err}"),
+  }
+}
+
+fn qr_code_url(mut addr: SocketAddr) -> String {
+  // a code pointing at every interface is only useful with an address reachable from other devices
+  if addr.ip().is_unspecified()
+    && let Some(ip) = first_lan_address()
+  {
+    addr.set_ip(ip);
+  }
+  if addr.ip().is_loopback() || addr.ip().is_unspecified() {
+    warn!("address {addr} encoded in the QR code is not reachable from other devices");
+  }
+
+  served_url(addr)
+}
+
+fn render_qr_code(url: &str) -> Result<String, QrError> {
+  let rendered = QrCode::new(url)?
+    .render::<Dense1x2>()
+    .dark_color(Dense1x2::Light)
+    .light_color(Dense1x2::Dark)
+    .quiet_zone(true)
+    .build();
+
+  Ok(rendered)
+}
+
+fn first_lan_address() -> Option<IpAddr> {
+  getifaddrs()
+    .ok()?
+    .filter_map(|ifaddr| ifaddr.address?.as_sockaddr_in().map(|v4| v4.ip()))
+    .find(|ip| !ip.is_loopback() && !ip.is_link_local())
+    .map(IpAddr::V4)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::{Ipv4Addr, TcpListener};
+
+  use super::*;
+
+  #[test]
+  fn url_points_at_bound_address() {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let url = qr_code_url(addr);
+
+    assert_eq!(url, format!("http://127.0.0.1:{}/", addr.port()));
+  }
+
+  #[test]
+  fn url_for_every_interface_keeps_bound_port() {
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let url = qr_code_url(addr);
+
+    assert!(url.ends_with(&format!(":{}/", addr.port())), "{url}");
+    assert!(!url.contains("0.0.0.0"), "{url}");
+  }
+
+  #[test]
+  fn rendered_code_spans_multiple_lines() {
+    let rendered = render_qr_code("http://127.0.0.1:8080/").unwrap();
+
+    assert!(rendered.lines().count() > 1);
+  }
+}
@@ -1,65 +1,152 @@
 use std::error::Error;
+use std::future::pending;
+use std::net::SocketAddr;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 use std::time::Duration;
 
+use hyper::header::{ALLOW, HeaderValue, RETRY_AFTER};
 use hyper::service::service_fn;
-use hyper::{Request, Response, StatusCode};
+use hyper::{Request, StatusCode};
 use hyper_util::rt::{TokioExecutor, TokioIo};
 use hyper_util::server::conn::auto;
 use hyper_util::server::graceful;
-use log::{debug, info};
+use ipnet::IpNet;
+use log::{debug, info, warn};
 use tokio::net::TcpListener;
 use tokio::select;
-use tokio::sync::{Mutex, Notify};
+use tokio::signal::unix::{Signal, SignalKind, signal};
+use tokio::sync::{Mutex, Notify, RwLock, broadcast};
 use tokio::time::sleep;
 
 use crate::api_servers::ApiServersService;
 use crate::frontend::pkg::repository::PackagesRepository;
+use crate::frontend::{UpdateTrack, reload_frontend};
 use crate::server::api::api_servers::{
-  get_all_instances, get_logs_request, spawn_local_server, stop_local_server,
+  get_all_instances, get_logs_archive, get_logs_request, get_logs_tail, register_remote_server,
+  respawn_saved_servers, restart_local_server, spawn_local_server, stop_all_local_servers,
+  stop_local_server, unregister_remote_server,
 };
-use crate::server::api::frontend::{check_latest_frontend_release, update_frontend_package};
-use crate::server::api::management::trigger_shutdown;
-use crate::server::common::{ServiceResponse, empty_body, full_body};
+use crate::server::api::frontend::{
+  UpdateCooldown, UpdateInProgress, UpdateProgress, check_latest_frontend_release,
+  frontend_reload_events, frontend_update_progress_events, get_version, list_frontend_releases,
+  remote_releases_offline_response, update_frontend_package,
+};
+use crate::server::api::management::{
+  get_idle_status, get_metrics, pause_idle_shutdown, resume_idle_shutdown, trigger_shutdown,
+};
+pub use crate::server::auth::basic_authorization;
+use crate::server::auth::{
+  forbidden_response, is_authorized, is_peer_allowed, unauthorized_response,
+};
+use crate::server::common::{ServiceResponse, error_json_response_with_status};
+pub use crate::server::frontend::FrontendConfig;
 use crate::server::frontend::serve_frontend;
+use crate::server::idle::IdleTracker;
+use crate::server::metrics::Metrics;
+use crate::server::proxy::{proxy_http, proxy_websocket};
+pub use crate::server::rate_limit::RateLimiter;
 use crate::server::router::get_route;
 
 mod api;
+mod auth;
 mod common;
 mod frontend;
+mod idle;
+mod metrics;
+mod proxy;
+mod rate_limit;
 mod router;
 
 const GRACEFUL_SHUTDOWN_TIMEOUT_SEC: u8 = 30;
+const API_PATH_PREFIX: &str = "/api/";
+const PROXY_PATH_PREFIX: &str = "/proxy/";
+const API_SERVERS_PATH: &str = "/api/servers";
+const API_SHUTDOWN_PATH: &str = "/api/shutdown";
+
+#[derive(Clone, Default)]
+pub struct Config {
+  pub idle_shutdown_timeout: Option<u32>,
+  pub max_request_body_size: usize,
+  pub offline: bool,
+  pub update_track: UpdateTrack,
+  pub force_outdated: bool,
+  pub stream_install: bool,
+  pub releases_url: String,
+  pub api_authorization: Option<String>,
+  pub api_allowlist: Vec<IpNet>,
+  pub shutdown_token: Option<String>,
+  pub frontend: FrontendConfig,
+}
+
 #[derive(Clone)]
 pub struct Dependencies {
-  pub packages_repository: Arc<Mutex<PackagesRepository>>,
+  pub packages_repository: Arc<RwLock<PackagesRepository>>,
   pub api_service: Arc<Mutex<ApiServersService>>,
+  pub rate_limiter: Option<Arc<RateLimiter>>,
+  pub reload_notifier: Option<broadcast::Sender<()>>,
+  pub update_cooldown: Arc<UpdateCooldown>,
+  pub update_in_progress: Arc<UpdateInProgress>,
+  pub update_progress: broadcast::Sender<UpdateProgress>,
+  pub metrics: Arc<Metrics>,
 }
 
 pub async fn serve(
   listener: TcpListener,
-  idle_shutdown_timeout: Option<u32>,
+  config: Config,
   dependencies: &Dependencies,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), Box<dyn Error + Send + Sync>> {
   let graceful = graceful::GracefulShutdown::new();
   let main_service_shutdown_notifier = Arc::new(Notify::new());
+  let idle_tracker = Arc::new(IdleTracker::new(config.idle_shutdown_timeout));
+  let config = Arc::new(config);
+  // listening starts once, so signals received while a connection is being accepted are not lost
+  let mut hangup = listen_for_signal(SignalKind::hangup());
+  let shutdown_condition =
+    wait_for_shutdown_condition(main_service_shutdown_notifier.clone(), &idle_tracker);
+  tokio::pin!(shutdown_condition);
 
   loop {
     let shutdown_notifier = main_service_shutdown_notifier.clone();
+    let idle = idle_tracker.clone();
 
     select! {
       Ok((stream, incoming_addr)) = listener.accept() => {
         debug!("accepted connection from {incoming_addr}");
 
         let deps = dependencies.clone();
+        let cfg = config.clone();
         tokio::task::spawn(async move {
+          let _connection = deps.metrics.connection_opened();
           let io = TokioIo::new(stream);
           let runner = auto::Builder::new(TokioExecutor::new());
-          _ = runner.serve_connection(io, service_fn(|req| { service(req, shutdown_notifier.clone(), deps.clone()) })).await;
+          _ = runner.serve_connection_with_upgrades(io, service_fn(|req| { service(req, incoming_addr, shutdown_notifier.clone(), idle.clone(), cfg.clone(), deps.clone()) })).await;
         });
       }
-      _ = wait_for_shutdown_condition(shutdown_notifier.clone(), idle_shutdown_timeout) => {
+      _ = recv_signal(&mut hangup) => {
+        // a reload shares the temp dir with updates requested over the api
+        let Some(running_update) = dependencies.update_in_progress.begin() else {
+          warn!("ignoring SIGHUP signal - a frontend update is already in progress");
+          continue;
+        };
+        info!("reloading frontend package due to SIGHUP signal");
+
+        let deps = dependencies.clone();
+        let cfg = config.clone();
+        tokio::task::spawn(async move {
+          let _running_update = running_update;
+          reload_frontend(
+            cfg.update_track,
+            cfg.force_outdated,
+            cfg.offline,
+            cfg.stream_install,
+            &cfg.releases_url,
+            &deps.packages_repository,
+          )
+          .await;
+        });
+      }
+      _ = &mut shutdown_condition => {
         drop(listener);
         break;
       }
@@ -78,12 +165,11 @@ pub async fn serve(
   Ok(())
 }
 
-async fn wait_for_shutdown_condition<T>(
-  service_shutdown_notify: T,
-  idle_shutdown_timeout: Option<u32>,
-) where
+async fn wait_for_shutdown_condition<T>(service_shutdown_notify: T, idle_tracker: &IdleTracker)
+where
   T: Deref<Target = Notify>,
 {
+  let mut terminate = listen_for_signal(SignalKind::terminate());
   select! {
     _ = service_shutdown_notify.notified() => {
       info!("triggering shutdown due to shutdown request")
@@ -91,49 +177,205 @@ async fn wait_for_shutdown_condition<T>(
     _ = tokio::signal::ctrl_c() => {
       info!("triggering shutdown due to SIGINT signal")
     }
-    _ = sleep(Duration::from_secs(idle_shutdown_timeout.unwrap_or_default().into())), if idle_shutdown_timeout.is_some() => {
-      info!("triggering shutdown since no request has been received for {} seconds", idle_shutdown_timeout.unwrap_or_default())
+    _ = recv_signal(&mut terminate) => {
+      info!("triggering shutdown due to SIGTERM signal")
+    }
+    _ = idle_tracker.wait_until_idle() => {
+      info!("triggering shutdown since no request has been received for {} seconds", idle_tracker.timeout().unwrap_or_default().as_secs())
+    }
+  }
+}
+
+fn listen_for_signal(kind: SignalKind) -> Option<Signal> {
+  signal(kind)
+    .inspect_err(|err| warn!("could not listen for signal {}: {err}", kind.as_raw_value()))
+    .ok()
+}
+
+async fn recv_signal(stream: &mut Option<Signal>) {
+  match stream {
+    Some(stream) => {
+      stream.recv().await;
     }
+    None => pending::<()>().await,
   }
 }
 
+fn is_process_control_path(path: &str) -> bool {
+  path == API_SHUTDOWN_PATH
+    || path
+      .strip_prefix(API_SERVERS_PATH)
+      .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
 async fn service<T>(
   req: Request<hyper::body::Incoming>,
+  peer_addr: SocketAddr,
   shutdown_notifier: T,
+  idle_tracker: Arc<IdleTracker>,
+  config: Arc<Config>,
   dependencies: Dependencies,
 ) -> ServiceResponse
 where
   T: Deref<Target = Notify>,
 {
-  let route = get_route(req).await;
+  let metrics = dependencies.metrics.clone();
+  metrics.record_request();
+  let response = handle_request(
+    req,
+    peer_addr,
+    shutdown_notifier,
+    idle_tracker,
+    config,
+    dependencies,
+  )
+  .await?;
+  Ok(metrics.record_response(response))
+}
+
+async fn handle_request<T>(
+  req: Request<hyper::body::Incoming>,
+  peer_addr: SocketAddr,
+  shutdown_notifier: T,
+  idle_tracker: Arc<IdleTracker>,
+  config: Arc<Config>,
+  dependencies: Dependencies,
+) -> ServiceResponse
+where
+  T: Deref<Target = Notify>,
+{
+  if let Some(rate_limiter) = &dependencies.rate_limiter
+    && let Err(retry_after) = rate_limiter.acquire(peer_addr.ip())
+  {
+    debug!("rate limiting request from {peer_addr}");
+    let mut response =
+      error_json_response_with_status("too many requests", StatusCode::TOO_MANY_REQUESTS)?;
+    response.headers_mut().append(
+      RETRY_AFTER,
+      HeaderValue::from(retry_after.as_secs_f64().ceil() as u64),
+    );
+    return Ok(response);
+  }
+
+  if !config.api_allowlist.is_empty()
+    && is_process_control_path(req.uri().path())
+    && !is_peer_allowed(peer_addr.ip(), &config.api_allowlist)
+  {
+    debug!(
+      "rejecting request to \"{}\" from {peer_addr} not on the api allowlist",
+      req.uri().path()
+    );
+    return forbidden_response();
+  }
+
+  if (req.uri().path().starts_with(API_PATH_PREFIX)
+    || req.uri().path().starts_with(PROXY_PATH_PREFIX))
+    && let Some(expected_authorization) = &config.api_authorization
+    && !is_authorized(&req, expected_authorization)
+  {
+    return unauthorized_response();
+  }
+
+  let route = get_route(req, config.max_request_body_size).await;
+  // polling the idle status must not keep the server alive
+  if !matches!(route, Ok(router::Routes::Api(router::ApiRoutes::Idle))) {
+    idle_tracker.touch();
+  }
+
   match route {
     Ok(r) => match r {
-      router::Routes::Frontend(name, encodings) => {
+      router::Routes::Frontend(name, encodings, range, if_range) => {
         serve_frontend(
           name.as_deref(),
           encodings,
-          dependencies.packages_repository.lock().await.deref_mut(),
+          range.as_deref(),
+          if_range.as_deref(),
+          dependencies.packages_repository.read().await.deref(),
+          &config.frontend,
+        )
+        .await
+      }
+      router::Routes::Proxy(name, path, req) => {
+        proxy_http(
+          &name,
+          &path,
+          *req,
+          peer_addr,
+          config.api_authorization.is_some(),
+          &dependencies.api_service,
         )
         .await
       }
       router::Routes::Api(api_route) => match api_route {
+        router::ApiRoutes::FrontendLatest
+        | router::ApiRoutes::FrontendReleases
+        | router::ApiRoutes::FrontendUpdate(..)
+          if config.offline =>
+        {
+          remote_releases_offline_response()
+        }
         router::ApiRoutes::FrontendLatest => {
-          check_latest_frontend_release(dependencies.packages_repository.lock().await.deref()).await
+          check_latest_frontend_release(
+            &config.releases_url,
+            dependencies.packages_repository.read().await.deref(),
+          )
+          .await
+        }
+        router::ApiRoutes::FrontendReleases => list_frontend_releases(&config.releases_url).await,
+        router::ApiRoutes::FrontendUpdateProgress => {
+          frontend_update_progress_events(&dependencies.update_progress)
+        }
+        router::ApiRoutes::FrontendReloadEvents => {
+          frontend_reload_events(dependencies.reload_notifier.as_ref())
         }
-        router::ApiRoutes::FrontendUpdate(req_body) => {
+        router::ApiRoutes::FrontendUpdate(req_body, req_query) => {
           update_frontend_package(
             req_body,
-            dependencies.packages_repository.lock().await.deref_mut(),
+            req_query,
+            &config.releases_url,
+            &dependencies.packages_repository,
+            &dependencies.update_cooldown,
+            &dependencies.update_in_progress,
+            &dependencies.update_progress,
           )
           .await
         }
-        router::ApiRoutes::Shutdown => trigger_shutdown(shutdown_notifier).await,
+        router::ApiRoutes::Shutdown(token) => {
+          trigger_shutdown(
+            shutdown_notifier,
+            token.as_deref(),
+            config.shutdown_token.as_deref(),
+          )
+          .await
+        }
+        router::ApiRoutes::Idle => get_idle_status(&idle_tracker),
+        router::ApiRoutes::Version => {
+          get_version(dependencies.packages_repository.read().await.deref())
+        }
+        router::ApiRoutes::Metrics => get_metrics(&dependencies.metrics),
+        router::ApiRoutes::IdlePause => pause_idle_shutdown(&idle_tracker),
+        router::ApiRoutes::IdleResume => resume_idle_shutdown(&idle_tracker),
         router::ApiRoutes::ApiServers(api_servers_path) => match api_servers_path {
           router::ApiServersRoutes::Spawn(req_body) => {
-            spawn_local_server(req_body, dependencies.api_service.lock().await.deref_mut()).await
+            spawn_local_server(req_body, &dependencies.api_service).await
+          }
+          router::ApiServersRoutes::Register(req_body) => {
+            register_remote_server(req_body, dependencies.api_service.lock().await.deref_mut())
+          }
+          router::ApiServersRoutes::Unregister(req_body) => {
+            unregister_remote_server(req_body, dependencies.api_service.lock().await.deref_mut())
           }
           router::ApiServersRoutes::Stop(req_body) => {
-            stop_local_server(req_body, dependencies.api_service.lock().await.deref_mut()).await
+            stop_local_server(req_body, &dependencies.api_service).await
+          }
+          router::ApiServersRoutes::StopAll => {
+            stop_all_local_servers(&dependencies.api_service).await
+          }
+          router::ApiServersRoutes::Restart(req_body) => {
+            restart_local_server(req_body, &dependencies.api_service).await
+          }
+          router::ApiServersRoutes::RespawnSaved => {
+            respawn_saved_servers(dependencies.api_service.lock().await.deref_mut()).await
           }
           router::ApiServersRoutes::All => {
             get_all_instances(dependencies.api_service.lock().await.deref_mut())
@@ -141,24 +383,35 @@ where
           router::ApiServersRoutes::Logs(req_body) => {
             get_logs_request(req_body, dependencies.api_service.lock().await.deref_mut()).await
           }
+          router::ApiServersRoutes::LogsArchive(req_query) => {
+            get_logs_archive(req_query, dependencies.api_service.lock().await.deref()).await
+          }
+          router::ApiServersRoutes::LogsTail(req_query) => {
+            get_logs_tail(req_query, dependencies.api_service.lock().await.deref())
+          }
+          router::ApiServersRoutes::WebSocket(name, path, req) => {
+            proxy_websocket(&name, &path, *req, &dependencies.api_service).await
+          }
         },
       },
     },
-    Err(err) => {
-      let mut response = Response::new(empty_body());
-      match err {
-        router::RoutingErr::Unmatched => {
-          *response.status_mut() = StatusCode::NOT_FOUND;
-        }
-        router::RoutingErr::InvalidMethod => {
-          *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
-        }
-        router::RoutingErr::InvalidRequestBody(e) => {
-          *response.status_mut() = StatusCode::BAD_REQUEST;
-          *response.body_mut() = full_body(format!("request invalid: {e}"));
-        }
-      };
-      Ok(response)
-    }
+    Err(err) => match err {
+      router::RoutingErr::Unmatched => {
+        error_json_response_with_status("no api route matches the request", StatusCode::NOT_FOUND)
+      }
+      router::RoutingErr::InvalidMethod(allowed) => {
+        let mut response = error_json_response_with_status(
+          "method not allowed for the requested route",
+          StatusCode::METHOD_NOT_ALLOWED,
+        )?;
+        response
+          .headers_mut()
+          .append(ALLOW, HeaderValue::from_str(allowed.as_str())?);
+        Ok(response)
+      }
+      router::RoutingErr::InvalidRequestBody(e) => {
+        error_json_response_with_status(format!("request invalid: {e}"), StatusCode::BAD_REQUEST)
+      }
+    },
   }
 }
@@ -1,13 +1,34 @@
-use futures::StreamExt;
+use std::{
+  collections::{HashMap, VecDeque},
+  time::UNIX_EPOCH,
+};
+
+use futures::{Stream, StreamExt, stream::select};
 use http_body_util::{StreamBody, combinators::BoxBody};
-use hyper::{Response, body::Frame, header::HeaderValue};
+use hyper::{
+  Response, StatusCode,
+  body::{Bytes, Frame},
+  header::HeaderValue,
+};
+use log::warn;
 use serde::{Deserialize, Serialize};
+use tokio::{
+  fs::{File, canonicalize},
+  io::{AsyncBufReadExt, BufReader},
+  sync::Mutex,
+};
 use tokio_util::io::ReaderStream;
 use uuid::Uuid;
 
 use crate::{
-  api_servers::{ApiServersService, ServerArguments},
-  server::common::{ServiceResponse, empty_body, error_json_response, json_response},
+  api_servers::{
+    ApiServersErr, ApiServersService, ServerArguments, SpawnOptions, recent_output::OutputLine,
+    wait_for_stops, wait_until_ready,
+  },
+  server::common::{
+    ServiceError, ServiceResponse, empty_response, error_json_response,
+    error_json_response_with_status, full_body, json_response,
+  },
 };
 
 #[derive(Deserialize)]
@@ -16,38 +37,182 @@ pub struct LocalApiServerSpawnRequest {
   port: Option<u16>,
   dir: Vec<String>,
   watch_dir: Option<bool>,
+  restart: Option<bool>,
+  wait_ready: Option<bool>,
+  env: Option<HashMap<String, String>>,
+  clear_env: Option<bool>,
+  extra_args: Option<Vec<String>>,
 }
 
 #[derive(Serialize)]
 pub struct LocalApiServerSpawnResponse<'a> {
   uuid: &'a Uuid,
+  name: &'a str,
+  address: &'a str,
 }
 
-const DEFAULT_LOCAL_SERVER_PORT: u16 = 3001;
-
 pub async fn spawn_local_server(
   req: LocalApiServerSpawnRequest,
-  servers_service: &mut ApiServersService,
+  servers_service: &Mutex<ApiServersService>,
 ) -> ServiceResponse {
   if req.dir.is_empty() {
-    let response = error_json_response("at least one dir entry is required")?;
+    let response = error_json_response_with_status(
+      "at least one dir entry is required",
+      StatusCode::BAD_REQUEST,
+    )?;
     return Ok(response);
   }
 
+  let dir = match canonicalize_dirs(&req.dir).await {
+    Ok(dir) => dir,
+    Err(invalid_dirs) => {
+      let response = error_json_response_with_status(
+        format!(
+          "provided dir entries are not existing directories: {}",
+          invalid_dirs.join(", ")
+        ),
+        StatusCode::BAD_REQUEST,
+      )?;
+      return Ok(response);
+    }
+  };
+
   let server_args = ServerArguments {
-    port: req.port.unwrap_or(DEFAULT_LOCAL_SERVER_PORT),
-    dir: &req.dir,
+    port: req.port,
+    dir,
     watch_dir: req.watch_dir.unwrap_or(false),
+    env: req.env.unwrap_or_default(),
+    clear_env: req.clear_env.unwrap_or(false),
+    extra_args: req.extra_args.unwrap_or_default(),
+  };
+  let spawn_options = SpawnOptions {
+    restart: req.restart.unwrap_or(false),
   };
 
-  match servers_service.spawn(req.name, &server_args).await {
+  let (uuid, address, ready_timeout) = {
+    let mut servers_service = servers_service.lock().await;
+    let uuid = match servers_service
+      .spawn(req.name.clone(), server_args, spawn_options)
+      .await
+    {
+      Ok(uuid) => uuid,
+      Err(err) => return spawn_failed_response(&err),
+    };
+    let Some(instance) = servers_service.get(&uuid) else {
+      let response = error_json_response(format!(
+        "spawned api instance with uuid {uuid} could not be found"
+      ))?;
+      return Ok(response);
+    };
+    (
+      uuid,
+      instance.address.clone(),
+      servers_service.ready_timeout(),
+    )
+  };
+
+  if req.wait_ready.unwrap_or(false)
+    && let Err(err) = wait_until_ready(&address, ready_timeout).await
+  {
+    if let Err(stop_err) = stop_instance(&uuid, servers_service).await {
+      warn!("could not stop api instance with uuid {uuid} that never became ready: {stop_err}");
+    }
+    return spawn_failed_response(&err);
+  }
+
+  let body = serde_json::to_string(&LocalApiServerSpawnResponse {
+    uuid: &uuid,
+    name: &req.name,
+    address: &address,
+  })?;
+  let response = json_response(body);
+  Ok(response)
+}
+
+fn spawn_failed_response(err: &ApiServersErr) -> ServiceResponse {
+  error_json_response_with_status(
+    format!("could not spawn a new api instance: {err}"),
+    error_status(err),
+  )
+}
+
+fn error_status(err: &ApiServersErr) -> StatusCode {
+  match err {
+    ApiServersErr::InstanceNotFound(_) | ApiServersErr::NameNotFound(_) => StatusCode::NOT_FOUND,
+    ApiServersErr::RemoteInstance(_)
+    | ApiServersErr::LocalInstance(_)
+    | ApiServersErr::InvalidAddress(_) => StatusCode::BAD_REQUEST,
+    ApiServersErr::NameTaken { .. } | ApiServersErr::AlreadyRunning { .. } => StatusCode::CONFLICT,
+    _ => StatusCode::INTERNAL_SERVER_ERROR,
+  }
+}
+
+async fn canonicalize_dirs(dirs: &[String]) -> Result<Vec<String>, Vec<String>> {
+  let mut canonicalized: Vec<String> = Vec::with_capacity(dirs.len());
+  let mut invalid: Vec<String> = Vec::new();
+  for dir in dirs {
+    match canonicalize(dir).await {
+      Ok(path) if path.is_dir() => canonicalized.push(path.to_string_lossy().into_owned()),
+      _ => invalid.push(format!("\"{dir}\"")),
+    }
+  }
+
+  if !invalid.is_empty() {
+    return Err(invalid);
+  }
+
+  Ok(canonicalized)
+}
+
+#[derive(Deserialize)]
+pub struct RemoteApiServerRegisterRequest {
+  name: String,
+  address: String,
+}
+
+pub fn register_remote_server(
+  req: RemoteApiServerRegisterRequest,
+  servers_service: &mut ApiServersService,
+) -> ServiceResponse {
+  match servers_service.register(req.name.clone(), req.address.clone()) {
     Ok(uuid) => {
-      let body = serde_json::to_string(&LocalApiServerSpawnResponse { uuid: &uuid })?;
+      let body = serde_json::to_string(&LocalApiServerSpawnResponse {
+        uuid: &uuid,
+        name: &req.name,
+        address: &req.address,
+      })?;
       let response = json_response(body);
       Ok(response)
     }
     Err(err) => {
-      let response = error_json_response(format!("could not spawn a new api instance: {err}"))?;
+      let response = error_json_response_with_status(
+        format!("could not register an api instance: {err}"),
+        error_status(&err),
+      )?;
+      Ok(response)
+    }
+  }
+}
+
+#[derive(Deserialize)]
+pub struct RemoteApiServerUnregisterRequest {
+  uuid: Uuid,
+}
+
+pub fn unregister_remote_server(
+  req: RemoteApiServerUnregisterRequest,
+  servers_service: &mut ApiServersService,
+) -> ServiceResponse {
+  match servers_service.unregister(&req.uuid) {
+    Ok(()) => {
+      let response = empty_response();
+      Ok(response)
+    }
+    Err(err) => {
+      let response = error_json_response_with_status(
+        format!("could not unregister api instance: {err}"),
+        error_status(&err),
+      )?;
       Ok(response)
     }
   }
@@ -58,59 +223,316 @@ pub struct LocalApiServerStopRequest {
   uuid: Uuid,
 }
 
+// the service is only locked to take the instance out and to record its logs - waiting for the
+// process to exit happens in between, so other routes are not blocked for the grace period
+async fn stop_instance(
+  uuid: &Uuid,
+  servers_service: &Mutex<ApiServersService>,
+) -> Result<(), ApiServersErr> {
+  let pending = servers_service.lock().await.begin_stop(uuid).await?;
+  let stopped = pending.wait().await?;
+  servers_service.lock().await.finish_stop(stopped);
+
+  Ok(())
+}
+
 pub async fn stop_local_server(
   req: LocalApiServerStopRequest,
-  servers_service: &mut ApiServersService,
+  servers_service: &Mutex<ApiServersService>,
 ) -> ServiceResponse {
-  match servers_service.stop(&req.uuid).await {
+  match stop_instance(&req.uuid, servers_service).await {
     Ok(()) => {
-      let response = Response::new(empty_body());
+      let response = empty_response();
       Ok(response)
     }
     Err(err) => {
-      let response = error_json_response(format!("could not stop api instance: {err}"))?;
+      let response = error_json_response_with_status(
+        format!("could not stop api instance: {err}"),
+        error_status(&err),
+      )?;
       Ok(response)
     }
   }
 }
 
 #[derive(Deserialize)]
+pub struct LocalApiServerRestartRequest {
+  name: String,
+}
+
+async fn restart_instance(
+  name: &str,
+  servers_service: &Mutex<ApiServersService>,
+) -> Result<Uuid, ApiServersErr> {
+  let pending = servers_service.lock().await.begin_restart(name)?;
+  let stopped = pending.wait().await?;
+  servers_service.lock().await.finish_restart(stopped).await
+}
+
+pub async fn restart_local_server(
+  req: LocalApiServerRestartRequest,
+  servers_service: &Mutex<ApiServersService>,
+) -> ServiceResponse {
+  match restart_instance(&req.name, servers_service).await {
+    Ok(uuid) => {
+      let servers_service = servers_service.lock().await;
+      let Some(instance) = servers_service.get(&uuid) else {
+        let response = error_json_response(format!(
+          "restarted api instance with uuid {uuid} could not be found"
+        ))?;
+        return Ok(response);
+      };
+      let body = serde_json::to_string(&LocalApiServerSpawnResponse {
+        uuid: &uuid,
+        name: &instance.name,
+        address: &instance.address,
+      })?;
+      let response = json_response(body);
+      Ok(response)
+    }
+    Err(err) => {
+      let response = error_json_response_with_status(
+        format!("could not restart api instance: {err}"),
+        error_status(&err),
+      )?;
+      Ok(response)
+    }
+  }
+}
+
+#[derive(Serialize)]
+pub struct RespawnedApiServer<'a> {
+  name: &'a str,
+  uuid: &'a Uuid,
+}
+
+#[derive(Serialize)]
+pub struct RespawnFailure<'a> {
+  name: &'a str,
+  err_msg: String,
+}
+
+#[derive(Serialize)]
+pub struct RespawnSavedResponse<'a> {
+  respawned: Vec<RespawnedApiServer<'a>>,
+  failed: Vec<RespawnFailure<'a>>,
+}
+
+pub async fn respawn_saved_servers(servers_service: &mut ApiServersService) -> ServiceResponse {
+  let outcomes = servers_service.respawn_saved().await;
+
+  let mut response_body = RespawnSavedResponse {
+    respawned: Vec::new(),
+    failed: Vec::new(),
+  };
+  for (name, outcome) in &outcomes {
+    match outcome {
+      Ok(uuid) => response_body
+        .respawned
+        .push(RespawnedApiServer { name, uuid }),
+      Err(err) => response_body.failed.push(RespawnFailure {
+        name,
+        err_msg: err.to_string(),
+      }),
+    }
+  }
+
+  let body = serde_json::to_string(&response_body)?;
+  Ok(json_response(body))
+}
+
+pub async fn stop_all_local_servers(servers_service: &Mutex<ApiServersService>) -> ServiceResponse {
+  let (pending, mut errs) = servers_service.lock().await.begin_stop_all().await;
+  let (stopped, wait_errs) = wait_for_stops(pending).await;
+  errs.extend(wait_errs);
+  {
+    let mut servers_service = servers_service.lock().await;
+    for stopped in stopped {
+      servers_service.finish_stop(stopped);
+    }
+  }
+
+  if errs.is_empty() {
+    return Ok(empty_response());
+  }
+  let response = error_json_response(format!(
+    "could not stop all api instances: {}",
+    errs.join("; ")
+  ))?;
+  Ok(response)
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
 enum LogVariant {
   Stdout,
   Stderr,
+  #[default]
+  Both,
 }
 
+// instance can be identified either by uuid or by name
 #[derive(Deserialize)]
 pub struct LocalApiServerLogsRequest {
-  uuid: Uuid,
-  variant: LogVariant,
+  uuid: Option<Uuid>,
+  name: Option<String>,
+  #[serde(default)]
+  stream: LogVariant,
+  tail: Option<usize>,
 }
 
 pub async fn get_logs_request(
   req: LocalApiServerLogsRequest,
   servers_service: &mut ApiServersService,
 ) -> ServiceResponse {
-  match servers_service.get_logs_readers(&req.uuid).await {
+  let uuid = match (req.uuid, &req.name) {
+    (Some(uuid), _) => uuid,
+    (None, Some(name)) => match servers_service.find_by_name(name) {
+      Some((uuid, _)) => *uuid,
+      None => {
+        let err = ApiServersErr::NameNotFound(name.clone());
+        let response = error_json_response_with_status(
+          format!("could not get logs: {err}"),
+          error_status(&err),
+        )?;
+        return Ok(response);
+      }
+    },
+    (None, None) => {
+      let response = error_json_response_with_status(
+        "either uuid or name of the instance is required",
+        StatusCode::BAD_REQUEST,
+      )?;
+      return Ok(response);
+    }
+  };
+
+  match servers_service.get_logs_readers(&uuid).await {
     Ok((stdout, stderr)) => {
-      let reader = match req.variant {
-        LogVariant::Stdout => stdout,
-        LogVariant::Stderr => stderr,
+      let body = match (req.tail, req.stream) {
+        (Some(tail), LogVariant::Stdout) => full_body(tail_lines(stdout, tail).await?),
+        (Some(tail), LogVariant::Stderr) => full_body(tail_lines(stderr, tail).await?),
+        (Some(tail), LogVariant::Both) => {
+          let mut lines = tail_lines(stdout, tail).await?;
+          lines.push_str(&tail_lines(stderr, tail).await?);
+          full_body(lines)
+        }
+        (None, LogVariant::Stdout) => BoxBody::new(StreamBody::new(file_frames(stdout))),
+        (None, LogVariant::Stderr) => BoxBody::new(StreamBody::new(file_frames(stderr))),
+        (None, LogVariant::Both) => BoxBody::new(StreamBody::new(select(
+          file_frames(stdout),
+          file_frames(stderr),
+        ))),
       };
 
-      let reader_stream = ReaderStream::new(reader).map(|chunk| match chunk {
-        Ok(bytes) => Ok(Frame::data(bytes)),
-        Err(err) => Err(Box::new(err).into()),
-      });
+      let mut response = Response::new(body);
+      response
+        .headers_mut()
+        .append("Content-Type", HeaderValue::from_static("text/plain"));
 
-      let mut response = Response::new(BoxBody::new(StreamBody::new(reader_stream)));
+      Ok(response)
+    }
+    Err(err) => {
+      let response =
+        error_json_response_with_status(format!("could not get logs: {err}"), error_status(&err))?;
+      Ok(response)
+    }
+  }
+}
+
+async fn tail_lines(reader: BufReader<File>, count: usize) -> Result<String, std::io::Error> {
+  let mut tail: VecDeque<String> = VecDeque::with_capacity(count.min(1024));
+  let mut lines = reader.lines();
+  while let Some(line) = lines.next_line().await? {
+    if count == 0 {
+      continue;
+    }
+
+    if tail.len() == count {
+      tail.pop_front();
+    }
+    tail.push_back(line);
+  }
+
+  Ok(tail.into_iter().map(|line| line + "\n").collect())
+}
+
+fn file_frames(reader: BufReader<File>) -> impl Stream<Item = Result<Frame<Bytes>, ServiceError>> {
+  ReaderStream::new(reader).map(|chunk| match chunk {
+    Ok(bytes) => Ok(Frame::data(bytes)),
+    Err(err) => Err(Box::new(err).into()),
+  })
+}
+
+#[derive(Deserialize)]
+pub struct LocalApiServerLogsArchiveRequest {
+  name: String,
+}
+
+pub async fn get_logs_archive(
+  req: LocalApiServerLogsArchiveRequest,
+  servers_service: &ApiServersService,
+) -> ServiceResponse {
+  match servers_service.get_logs_archive_reader(&req.name).await {
+    Ok(Some((filename, reader))) => {
+      let mut response = Response::new(BoxBody::new(StreamBody::new(file_frames(reader))));
       response
         .headers_mut()
-        .append("Content-Type", HeaderValue::from_str("text/plain").unwrap());
+        .append("Content-Type", HeaderValue::from_static("application/gzip"));
+      response.headers_mut().append(
+        "Content-Disposition",
+        HeaderValue::from_str(&format!("attachment; filename=\"{filename}\""))?,
+      );
 
       Ok(response)
     }
+    Ok(None) => {
+      let response = error_json_response_with_status(
+        format!(
+          "no logs archive exists for instance with name \"{}\"",
+          req.name
+        ),
+        StatusCode::NOT_FOUND,
+      )?;
+      Ok(response)
+    }
+    Err(err) => {
+      let response = error_json_response(format!("could not get logs archive: {err}"))?;
+      Ok(response)
+    }
+  }
+}
+
+#[derive(Deserialize)]
+pub struct LocalApiServerLogsTailRequest {
+  name: String,
+  lines: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct LocalApiServerLogsTailResponse {
+  lines: Vec<OutputLine>,
+}
+
+pub fn get_logs_tail(
+  req: LocalApiServerLogsTailRequest,
+  servers_service: &ApiServersService,
+) -> ServiceResponse {
+  let lines = match servers_service.find_by_name(&req.name) {
+    Some((uuid, _)) => servers_service.get_recent_output(uuid, req.lines.unwrap_or(usize::MAX)),
+    None => Err(ApiServersErr::NameNotFound(req.name)),
+  };
+
+  match lines {
+    Ok(lines) => {
+      let body = serde_json::to_string(&LocalApiServerLogsTailResponse { lines })?;
+      Ok(json_response(body))
+    }
     Err(err) => {
-      let response = error_json_response(format!("could not get logs: {err}"))?;
+      let response = error_json_response_with_status(
+        format!("could not get recent logs: {err}"),
+        error_status(&err),
+      )?;
       Ok(response)
     }
   }
@@ -122,6 +544,9 @@ pub struct ApiServerInstance<'a> {
   pub address: &'a str,
   pub name: &'a str,
   pub uuid: &'a Uuid,
+  pub pid: Option<u32>,
+  pub started_at: u64,
+  pub uptime_secs: u64,
 }
 
 #[derive(Serialize)]
@@ -133,10 +558,17 @@ pub fn get_all_instances(servers_service: &mut ApiServersService) -> ServiceResp
   let instances: Vec<ApiServerInstance> = servers_service
     .server_instances()
     .map(|(uuid, inst)| ApiServerInstance {
-      local: inst.local,
+      local: inst.is_local(),
       address: &inst.address,
       name: &inst.name,
       uuid,
+      pid: inst.pid(),
+      started_at: inst
+        .started_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs(),
+      uptime_secs: inst.started_at.elapsed().unwrap_or_default().as_secs(),
     })
     .collect();
   let body = serde_json::to_string(&ApiInstancesResponse {
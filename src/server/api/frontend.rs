@@ -1,30 +1,69 @@
-use hyper::Response;
+use std::{
+  sync::{
+    Arc, PoisonError,
+    atomic::{AtomicBool, AtomicU8, Ordering},
+  },
+  time::{Duration, SystemTime},
+};
+
+use futures::stream::unfold;
+use http_body_util::{StreamBody, combinators::BoxBody};
+use hyper::{
+  Response, StatusCode,
+  body::{Bytes, Frame},
+  header::{CACHE_CONTROL, CONTENT_TYPE, HeaderValue, RETRY_AFTER},
+};
+use log::error;
 use serde::{Deserialize, Serialize};
+use tokio::sync::{
+  RwLock,
+  broadcast::{self, error::RecvError},
+};
 
 use crate::{
   common::semver::Semver,
   frontend::{
-    pkg::repository::PackagesRepository,
-    releases::{Release, Version, fetch_remote_frontend_package_release, get_remote_release},
+    pkg::{manifest::VersionInfo, repository::PackagesRepository},
+    releases::{
+      Release, ReleaseFetchErr, Version, fetch_remote_frontend_package_release_with_progress,
+      get_remote_release, get_remote_releases,
+    },
+    remove_fetched_package,
+  },
+  server::common::{
+    ServiceError, ServiceResponse, empty_response, error_json_response,
+    error_json_response_with_status, json_response,
   },
-  server::common::{ServiceResponse, empty_body, error_json_response, json_response},
 };
 
+pub fn remote_releases_offline_response() -> ServiceResponse {
+  let response = error_json_response_with_status(
+    "client is running in offline mode - remote frontend releases are unavailable",
+    StatusCode::SERVICE_UNAVAILABLE,
+  )?;
+  Ok(response)
+}
+
 #[derive(Serialize)]
 pub struct CheckLatestResponseBody {
   latest_release: Release,
+  changelog: String,
   local_version: Option<Semver>,
   should_update: bool,
 }
 
-pub async fn check_latest_frontend_release(pkgs_repo: &PackagesRepository) -> ServiceResponse {
-  let response = match get_remote_release(Version::Latest).await {
+pub async fn check_latest_frontend_release(
+  releases_url: &str,
+  pkgs_repo: &PackagesRepository,
+) -> ServiceResponse {
+  let response = match get_remote_release(releases_url, Version::Latest).await {
     Ok(latest_release) => {
       let local_version = pkgs_repo.get_installed().map_or(None, |installed| {
         Some(installed.manifest.version_info.version)
       });
       let response_body = CheckLatestResponseBody {
         should_update: local_version.is_none_or(|local| local < latest_release.version),
+        changelog: latest_release.description.clone(),
         latest_release,
         local_version,
       };
@@ -37,49 +76,406 @@ pub async fn check_latest_frontend_release(pkgs_repo: &PackagesRepository) -> Se
   Ok(response)
 }
 
+#[derive(Serialize)]
+pub struct VersionResponseBody<'a> {
+  client_version: &'static str,
+  frontend: Option<&'a VersionInfo>,
+}
+
+pub fn get_version(pkgs_repo: &PackagesRepository) -> ServiceResponse {
+  let response_body = VersionResponseBody {
+    client_version: env!("CARGO_PKG_VERSION"),
+    frontend: pkgs_repo
+      .get_installed()
+      .ok()
+      .map(|installed| &installed.manifest.version_info),
+  };
+  let body = serde_json::to_string(&response_body).map_err(Box::new)?;
+  Ok(json_response(body))
+}
+
+#[derive(Serialize)]
+pub struct ReleasesResponseBody {
+  releases: Vec<Release>,
+}
+
+pub async fn list_frontend_releases(releases_url: &str) -> ServiceResponse {
+  let response = match get_remote_releases(releases_url).await {
+    Ok(releases) => {
+      let body = serde_json::to_string(&ReleasesResponseBody { releases }).map_err(Box::new)?;
+      json_response(body)
+    }
+    Err(err) => error_json_response(format!("could not fetch releases: {err}"))?,
+  };
+
+  Ok(response)
+}
+
 #[derive(Deserialize)]
 pub struct FrontendUpdateRequest {
   version: Semver,
 }
 
+#[derive(Deserialize)]
+pub struct FrontendUpdateQuery {
+  force: Option<bool>,
+}
+
+const DEFAULT_UPDATE_COOLDOWN_SECS: u64 = 60;
+// updates are refused until the remote rate limit resets, so that retries do not prolong it
+#[derive(Default)]
+pub struct UpdateCooldown {
+  until: std::sync::Mutex<Option<SystemTime>>,
+}
+
+impl UpdateCooldown {
+  fn remaining(&self) -> Option<Duration> {
+    let until = (*self.until.lock().unwrap_or_else(PoisonError::into_inner))?;
+    until
+      .duration_since(SystemTime::now())
+      .ok()
+      .filter(|remaining| !remaining.is_zero())
+  }
+
+  fn start(&self, resets_at: Option<SystemTime>) -> Duration {
+    let until = resets_at
+      .unwrap_or_else(|| SystemTime::now() + Duration::from_secs(DEFAULT_UPDATE_COOLDOWN_SECS));
+    *self.until.lock().unwrap_or_else(PoisonError::into_inner) = Some(until);
+    until.duration_since(SystemTime::now()).unwrap_or_default()
+  }
+}
+
+fn update_cooldown_response(retry_after: Duration) -> ServiceResponse {
+  let retry_after_secs = retry_after.as_secs_f64().ceil() as u64;
+  let mut response = error_json_response_with_status(
+    format!("remote rate limit exceeded - updates are paused for {retry_after_secs} seconds"),
+    StatusCode::SERVICE_UNAVAILABLE,
+  )?;
+  response
+    .headers_mut()
+    .append(RETRY_AFTER, HeaderValue::from(retry_after_secs));
+  Ok(response)
+}
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum UpdateProgress {
+  Downloading { percent: u8 },
+  Extracting,
+  Installing,
+  Done,
+  Error { err_msg: String },
+}
+
+// updates run one at a time - the flag is cleared when the running update finishes
+#[derive(Default)]
+pub struct UpdateInProgress {
+  running: AtomicBool,
+}
+
+impl UpdateInProgress {
+  pub fn begin(self: &Arc<Self>) -> Option<RunningUpdate> {
+    self
+      .running
+      .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+      .ok()
+      .map(|_| RunningUpdate(self.clone()))
+  }
+}
+
+pub struct RunningUpdate(Arc<UpdateInProgress>);
+
+impl Drop for RunningUpdate {
+  fn drop(&mut self) {
+    self.0.running.store(false, Ordering::SeqCst);
+  }
+}
+
+fn update_in_progress_response() -> ServiceResponse {
+  error_json_response_with_status(
+    "a frontend update is already in progress - follow its progress events",
+    StatusCode::CONFLICT,
+  )
+}
+
+// the release is looked up right away, so that rate limits and refused downgrades are reported in
+// the response - the download and install run in the background and report over progress events
 pub async fn update_frontend_package(
   req: FrontendUpdateRequest,
-  pkgs_repo: &mut PackagesRepository,
+  query: FrontendUpdateQuery,
+  releases_url: &str,
+  pkgs_repo: &Arc<RwLock<PackagesRepository>>,
+  cooldown: &Arc<UpdateCooldown>,
+  in_progress: &Arc<UpdateInProgress>,
+  progress: &broadcast::Sender<UpdateProgress>,
 ) -> ServiceResponse {
-  let release = match get_remote_release(Version::Semver(req.version)).await {
+  if let Some(remaining) = cooldown.remaining() {
+    report_update_failure(progress, "remote rate limit exceeded");
+    return update_cooldown_response(remaining);
+  }
+
+  let Some(running_update) = in_progress.begin() else {
+    return update_in_progress_response();
+  };
+
+  let release = match get_remote_release(releases_url, Version::Semver(req.version)).await {
     Ok(release) => release,
+    Err(ReleaseFetchErr::RateLimited(_, resets_at)) => {
+      report_update_failure(progress, "remote rate limit exceeded");
+      return update_cooldown_response(cooldown.start(resets_at));
+    }
     Err(err) => {
-      let response = error_json_response(format!(
-        "could not fetch release info for version {}: {err}",
-        req.version
-      ))?;
-      return Ok(response);
+      return update_failed_response(
+        progress,
+        format!(
+          "could not fetch release info for version {}: {err}",
+          req.version
+        ),
+      );
     }
   };
 
-  let path = match fetch_remote_frontend_package_release(&release).await {
+  // outdated packages are installed unless the frontend explicitly asks otherwise
+  let force_outdated = query.force.unwrap_or(true);
+  let installed_version = pkgs_repo
+    .read()
+    .await
+    .get_installed()
+    .ok()
+    .map(|installed| installed.manifest.version_info.version);
+  if !force_outdated
+    && let Some(installed_version) = installed_version
+    && release.version < installed_version
+  {
+    report_update_failure(
+      progress,
+      format!(
+        "version \"{}\" is older than the installed version",
+        release.version
+      ),
+    );
+    return outdated_package_response(&release.version.to_string(), &installed_version.to_string());
+  }
+
+  let pkgs_repo = pkgs_repo.clone();
+  let cooldown = cooldown.clone();
+  let progress = progress.clone();
+  tokio::spawn(async move {
+    let _running_update = running_update;
+    run_frontend_update(release, force_outdated, &pkgs_repo, &cooldown, &progress).await;
+  });
+
+  let mut response = empty_response();
+  *response.status_mut() = StatusCode::ACCEPTED;
+  Ok(response)
+}
+
+async fn run_frontend_update(
+  release: Release,
+  force_outdated: bool,
+  pkgs_repo: &RwLock<PackagesRepository>,
+  cooldown: &UpdateCooldown,
+  progress: &broadcast::Sender<UpdateProgress>,
+) {
+  let last_percent = AtomicU8::new(0);
+  let temp_dir = pkgs_repo.read().await.temp_dir();
+  let _ = progress.send(UpdateProgress::Downloading { percent: 0 });
+  let fetch_result =
+    fetch_remote_frontend_package_release_with_progress(&release, temp_dir, |written, total| {
+      let percent = (written * 100).checked_div(total).unwrap_or(100).min(100) as u8;
+      // only whole percent changes are worth an event
+      if last_percent.swap(percent, Ordering::Relaxed) != percent {
+        let _ = progress.send(UpdateProgress::Downloading { percent });
+      }
+    })
+    .await;
+  let path = match fetch_result {
     Ok(path) => path,
+    Err(ReleaseFetchErr::RateLimited(_, resets_at)) => {
+      cooldown.start(resets_at);
+      report_update_failure(progress, "remote rate limit exceeded");
+      return;
+    }
     Err(err) => {
-      let response = error_json_response(format!(
-        "could not fetch the \"{}\" release: {err}",
-        req.version
-      ))?;
-      return Ok(response);
+      report_update_failure(
+        progress,
+        format!("could not fetch the \"{}\" release: {err}", release.version),
+      );
+      return;
     }
   };
 
-  const FORCE_OUTDATED: bool = true; // TODO: this should be provided from frontend. atm always force outdated pkg
-  match pkgs_repo.install_package(path, FORCE_OUTDATED).await {
+  // the repository is locked for writing only for the install, so files are served during the fetch
+  let mut pkgs_repo = pkgs_repo.write().await;
+  let _ = progress.send(UpdateProgress::Extracting);
+  if let Err(err) = pkgs_repo.unpack_package(path.clone()).await {
+    remove_fetched_package(&path).await;
+    report_update_failure(
+      progress,
+      format!(
+        "could not unpack the \"{}\" release: {err}",
+        release.version
+      ),
+    );
+    return;
+  }
+
+  let _ = progress.send(UpdateProgress::Installing);
+  let install_result = pkgs_repo.install_extracted_package(force_outdated).await;
+  remove_fetched_package(&path).await;
+  match install_result {
     Ok(()) => {
-      let response = Response::new(empty_body());
-      Ok(response)
+      let _ = progress.send(UpdateProgress::Done);
     }
     Err(err) => {
-      let response = error_json_response(format!(
-        "could not fetch the \"{}\" release: {err}",
-        req.version
-      ))?;
-      Ok(response)
+      error!("frontend update failed: {err}");
+      report_update_failure(
+        progress,
+        format!(
+          "could not install the \"{}\" release: {err}",
+          release.version
+        ),
+      );
     }
   }
 }
+
+fn report_update_failure<T>(progress: &broadcast::Sender<UpdateProgress>, msg: T)
+where
+  T: Into<String>,
+{
+  let _ = progress.send(UpdateProgress::Error {
+    err_msg: msg.into(),
+  });
+}
+
+fn update_failed_response(
+  progress: &broadcast::Sender<UpdateProgress>,
+  msg: String,
+) -> ServiceResponse {
+  report_update_failure(progress, msg.as_str());
+  error_json_response(msg)
+}
+
+const UPDATE_PROGRESS_EVENT_NAME: &str = "progress";
+pub fn frontend_update_progress_events(
+  progress: &broadcast::Sender<UpdateProgress>,
+) -> ServiceResponse {
+  let events = unfold(
+    (Some(UPDATE_PROGRESS_PREAMBLE), progress.subscribe()),
+    |(preamble, mut receiver)| async move {
+      if let Some(preamble) = preamble {
+        return Some((Ok(Frame::data(Bytes::from(preamble))), (None, receiver)));
+      }
+
+      loop {
+        match receiver.recv().await {
+          Ok(event) => {
+            let data = match serde_json::to_string(&event) {
+              Ok(data) => data,
+              Err(err) => return Some((Err(Box::new(err).into()), (None, receiver))),
+            };
+            let frame = Frame::data(Bytes::from(format!(
+              "event: {UPDATE_PROGRESS_EVENT_NAME}\ndata: {data}\n\n"
+            )));
+            return Some((Ok(frame), (None, receiver)));
+          }
+          // intermediate download percentages are not worth failing the stream over
+          Err(RecvError::Lagged(_)) => continue,
+          Err(RecvError::Closed) => return None,
+        }
+      }
+    },
+  );
+
+  Ok(event_stream_response(BoxBody::new(StreamBody::new(events))))
+}
+
+#[derive(Serialize)]
+pub struct OutdatedPackageResponseBody<'a> {
+  err_msg: String,
+  provided_version: &'a str,
+  installed_version: &'a str,
+}
+
+// lets the frontend ask the user to confirm the downgrade and retry with force
+fn outdated_package_response(provided_version: &str, installed_version: &str) -> ServiceResponse {
+  let body = serde_json::to_string(&OutdatedPackageResponseBody {
+    err_msg: format!(
+      "version \"{provided_version}\" is older than the installed version \"{installed_version}\" - retry with force to downgrade"
+    ),
+    provided_version,
+    installed_version,
+  })?;
+  let mut response = json_response(body);
+  *response.status_mut() = StatusCode::CONFLICT;
+  Ok(response)
+}
+
+const RELOAD_EVENT: &str = "event: reload\ndata: {}\n\n";
+// comment sent right away, so clients know the subscription is active
+const RELOAD_EVENTS_PREAMBLE: &str = ": subscribed to frontend reload events\n\n";
+const UPDATE_PROGRESS_PREAMBLE: &str = ": subscribed to frontend update progress\n\n";
+pub fn frontend_reload_events(reload_notifier: Option<&broadcast::Sender<()>>) -> ServiceResponse {
+  let Some(reload_notifier) = reload_notifier else {
+    return error_json_response_with_status(
+      "reload events are available only when serving a watched directory",
+      StatusCode::NOT_FOUND,
+    );
+  };
+
+  let events = unfold(
+    (Some(RELOAD_EVENTS_PREAMBLE), reload_notifier.subscribe()),
+    |(preamble, mut receiver)| async move {
+      if let Some(preamble) = preamble {
+        return Some((Ok(Frame::data(Bytes::from(preamble))), (None, receiver)));
+      }
+
+      match receiver.recv().await {
+        Ok(()) | Err(RecvError::Lagged(_)) => {
+          Some((Ok(Frame::data(Bytes::from(RELOAD_EVENT))), (None, receiver)))
+        }
+        Err(RecvError::Closed) => None,
+      }
+    },
+  );
+
+  Ok(event_stream_response(BoxBody::new(StreamBody::new(events))))
+}
+
+fn event_stream_response(
+  body: BoxBody<Bytes, ServiceError>,
+) -> Response<BoxBody<Bytes, ServiceError>> {
+  let mut response = Response::new(body);
+  response
+    .headers_mut()
+    .append(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+  response
+    .headers_mut()
+    .append(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+  response
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn empty_changelog_is_still_serialized() {
+    let response_body = CheckLatestResponseBody {
+      latest_release: Release {
+        name: "pkg.tar.gz".to_owned(),
+        version: "1.0.0".parse().unwrap(),
+        description: String::new(),
+        download: None,
+      },
+      changelog: String::new(),
+      local_version: None,
+      should_update: true,
+    };
+
+    let body: serde_json::Value = serde_json::to_value(&response_body).unwrap();
+
+    assert_eq!(body["changelog"], serde_json::Value::String(String::new()));
+  }
+}
@@ -1,15 +1,89 @@
 use std::ops::Deref;
 
-use hyper::Response;
+use hyper::{
+  StatusCode,
+  header::{CONTENT_LENGTH, CONTENT_TYPE, HeaderValue},
+};
+use serde::Serialize;
 use tokio::sync::Notify;
 
-use crate::server::common::{ServiceResponse, empty_body};
+use crate::server::{
+  auth::constant_time_eq,
+  common::{
+    ServiceResponse, empty_response, error_json_response_with_status, full_body, json_response,
+  },
+  idle::IdleTracker,
+  metrics::Metrics,
+};
 
-pub async fn trigger_shutdown<T>(notifier: T) -> ServiceResponse
+pub async fn trigger_shutdown<T>(
+  notifier: T,
+  token: Option<&str>,
+  expected_token: Option<&str>,
+) -> ServiceResponse
 where
   T: Deref<Target = Notify>,
 {
+  let Some(expected_token) = expected_token else {
+    return error_json_response_with_status(
+      "shutdown route is disabled - provide a shutdown token to enable it",
+      StatusCode::FORBIDDEN,
+    );
+  };
+  if !token.is_some_and(|token| constant_time_eq(token.as_bytes(), expected_token.as_bytes())) {
+    return error_json_response_with_status(
+      "missing or invalid shutdown token",
+      StatusCode::FORBIDDEN,
+    );
+  }
+
   notifier.notify_waiters();
-  let response = Response::new(empty_body());
+  let response = empty_response();
+  Ok(response)
+}
+
+#[derive(Serialize)]
+pub struct IdleStatusResponse {
+  enabled: bool,
+  paused: bool,
+  timeout_secs: Option<u64>,
+  remaining_secs: Option<u64>,
+}
+
+pub fn get_idle_status(idle_tracker: &IdleTracker) -> ServiceResponse {
+  let response_body = IdleStatusResponse {
+    enabled: idle_tracker.timeout().is_some(),
+    paused: idle_tracker.is_paused(),
+    timeout_secs: idle_tracker.timeout().map(|timeout| timeout.as_secs()),
+    remaining_secs: idle_tracker
+      .remaining()
+      .map(|remaining| remaining.as_secs()),
+  };
+  let body = serde_json::to_string(&response_body)?;
+  Ok(json_response(body))
+}
+
+pub fn pause_idle_shutdown(idle_tracker: &IdleTracker) -> ServiceResponse {
+  idle_tracker.pause();
+  let response = empty_response();
+  Ok(response)
+}
+
+pub fn resume_idle_shutdown(idle_tracker: &IdleTracker) -> ServiceResponse {
+  idle_tracker.resume();
+  let response = empty_response();
+  Ok(response)
+}
+
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+pub fn get_metrics(metrics: &Metrics) -> ServiceResponse {
+  let body = metrics.render();
+  let mut response = hyper::Response::new(full_body(body.clone()));
+  response
+    .headers_mut()
+    .append(CONTENT_LENGTH, HeaderValue::from(body.len()));
+  response
+    .headers_mut()
+    .append(CONTENT_TYPE, HeaderValue::from_static(METRICS_CONTENT_TYPE));
   Ok(response)
 }
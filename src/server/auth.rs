@@ -0,0 +1,57 @@
+use std::net::IpAddr;
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use hyper::{
+  Request, StatusCode,
+  header::{AUTHORIZATION, HeaderValue, WWW_AUTHENTICATE},
+};
+use ipnet::IpNet;
+
+use crate::server::common::{ServiceResponse, error_json_response_with_status};
+
+const BASIC_AUTH_REALM: &str = "Basic realm=\"mpv-web-client api\", charset=\"UTF-8\"";
+
+pub fn basic_authorization(user: &str, password: &str) -> String {
+  format!(
+    "Basic {}",
+    BASE64_STANDARD.encode(format!("{user}:{password}"))
+  )
+}
+
+pub fn is_authorized<T>(req: &Request<T>, expected_authorization: &str) -> bool {
+  req
+    .headers()
+    .get(AUTHORIZATION)
+    .is_some_and(|value| constant_time_eq(value.as_bytes(), expected_authorization.as_bytes()))
+}
+
+// comparison time does not depend on how many leading bytes of the credentials match
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  if a.len() != b.len() {
+    return false;
+  }
+
+  a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub fn is_peer_allowed(peer: IpAddr, allowlist: &[IpNet]) -> bool {
+  // peers connecting over IPv4 to a dual-stack listener are reported as IPv4-mapped IPv6 addresses
+  let peer = peer.to_canonical();
+  allowlist.iter().any(|net| net.contains(&peer))
+}
+
+pub fn forbidden_response() -> ServiceResponse {
+  error_json_response_with_status(
+    "requests from this address are not allowed for the route",
+    StatusCode::FORBIDDEN,
+  )
+}
+
+pub fn unauthorized_response() -> ServiceResponse {
+  let mut response =
+    error_json_response_with_status("missing or invalid credentials", StatusCode::UNAUTHORIZED)?;
+  response
+    .headers_mut()
+    .append(WWW_AUTHENTICATE, HeaderValue::from_static(BASIC_AUTH_REALM));
+  Ok(response)
+}
@@ -1,7 +1,11 @@
 use std::error::Error;
 
 use http_body_util::{BodyExt, Empty, Full, combinators::BoxBody};
-use hyper::{Response, StatusCode, body::Bytes, header::HeaderValue};
+use hyper::{
+  Response, StatusCode,
+  body::Bytes,
+  header::{CONTENT_LENGTH, HeaderValue},
+};
 
 use crate::server::api::ApiErr;
 
@@ -23,12 +27,25 @@ where
     .boxed()
 }
 
+pub fn empty_response() -> Response<BoxBody<Bytes, ServiceError>> {
+  let mut response = Response::new(empty_body());
+  response
+    .headers_mut()
+    .append(CONTENT_LENGTH, HeaderValue::from(0));
+
+  response
+}
+
 pub fn json_response<T>(msg: T) -> Response<BoxBody<Bytes, ServiceError>>
 where
   T: Into<Bytes>,
 {
-  let body = full_body(msg);
-  let mut response = Response::new(body);
+  let msg: Bytes = msg.into();
+  let content_length = msg.len();
+  let mut response = Response::new(full_body(msg));
+  response
+    .headers_mut()
+    .append(CONTENT_LENGTH, HeaderValue::from(content_length));
 
   response.headers_mut().append(
     "Content-Type",
@@ -39,6 +56,13 @@ where
 }
 
 pub fn error_json_response<T>(msg: T) -> ServiceResponse
+where
+  T: AsRef<str>,
+{
+  error_json_response_with_status(msg, StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+pub fn error_json_response_with_status<T>(msg: T, status: StatusCode) -> ServiceResponse
 where
   T: AsRef<str>,
 {
@@ -46,6 +70,6 @@ where
     err_msg: msg.as_ref(),
   })?;
   let mut response = json_response(body);
-  *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+  *response.status_mut() = status;
   Ok(response)
 }
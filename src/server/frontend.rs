@@ -1,65 +1,282 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::io::SeekFrom;
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
 use http_body_util::StreamBody;
 use http_body_util::combinators::BoxBody;
-use hyper::Response;
-use hyper::body::Frame;
-use hyper::header::HeaderValue;
-use log::debug;
+use httpdate::{fmt_http_date, parse_http_date};
+use hyper::body::{Bytes, Frame};
+use hyper::header::{
+  ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_SECURITY_POLICY, ETAG, HeaderValue,
+  LAST_MODIFIED, REFERRER_POLICY, VARY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use hyper::{Response, StatusCode};
+use log::{debug, warn};
 use mime_guess::Mime;
 use tokio::fs::File;
-use tokio::io::BufReader;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio_util::io::ReaderStream;
 
 use crate::frontend::DEFAULT_ENTRYPOINT_FILE_NAME;
-use crate::frontend::pkg::repository::PackagesRepository;
-use crate::server::common::{ServiceError, ServiceResponse};
+use crate::frontend::pkg::repository::{PackagesRepository, is_contained_path, open_file_in_dir};
+use crate::server::common::{ServiceError, ServiceResponse, empty_response, full_body};
 
-const STREAM_CHUNK_SIZE: usize = 1024 * 1024 * 64;
+#[derive(Clone, Default)]
+pub struct FrontendConfig {
+  pub content_security_policy: Option<String>,
+  pub stream_chunk_size: usize,
+  pub min_compression_size: u64,
+  pub mime_types: HashMap<String, Mime>,
+  pub serve_dir: Option<PathBuf>,
+}
+
+const NOT_FOUND_BODY: &str = "<!DOCTYPE html><html><body><h1>404 Not Found</h1></body></html>";
 pub async fn serve_frontend(
   name: Option<&str>,
   encodings: Vec<String>,
+  range: Option<&str>,
+  if_range: Option<&str>,
   pkgs_repo: &PackagesRepository,
+  config: &FrontendConfig,
 ) -> ServiceResponse {
-  let file_to_serve = match decide_file_to_serve(name, &encodings, pkgs_repo).await {
+  // a path escaping the served directory must not fall back to the entrypoint either
+  if let Some(name) = name
+    && !is_contained_path(Path::new(name))
+  {
+    debug!("rejecting path \"{name}\" pointing outside of the served directory");
+    return Ok(not_found_response(config));
+  }
+
+  let mut file_to_serve = match decide_file_to_serve(name, &encodings, pkgs_repo, config).await {
     Some(served_file_info) => served_file_info,
     None => {
-      return Err(*Box::<ServiceError>::new(
-        "unable to serve any of the expected files for request"
-          .to_owned()
-          .into(),
-      ));
+      debug!("unable to serve any of the expected files for request");
+      return Ok(not_found_response(config));
     }
   };
 
   debug!("serving path \"{}\"", file_to_serve.path.to_string_lossy());
-  let reader = BufReader::with_capacity(STREAM_CHUNK_SIZE, file_to_serve.file);
-  let reader_stream = ReaderStream::new(reader).map(|chunk| match chunk {
-    Ok(bytes) => Ok(Frame::data(bytes)),
-    Err(err) => Err(Box::new(err).into()),
-  });
+  let file_metadata = file_to_serve.file.metadata().await?;
+  let file_size = file_metadata.len();
+  let validators = Validators::new(file_size, file_metadata.modified().ok());
+  // a stale validator means the client would resume a different file - it gets the whole one instead
+  let range = range.filter(|_| if_range.is_none_or(|if_range| validators.matches(if_range)));
+  let byte_range = match range {
+    Some(range) => match parse_byte_range(range, file_size) {
+      Some(byte_range) => Some(byte_range),
+      None => {
+        debug!("range \"{range}\" cannot be satisfied for a file of {file_size} bytes");
+        let mut response = empty_response();
+        *response.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+        response.headers_mut().append(
+          CONTENT_RANGE,
+          HeaderValue::from_str(&format!("bytes */{file_size}"))?,
+        );
+        append_security_headers(&mut response, config);
+        return Ok(response);
+      }
+    },
+    None => None,
+  };
+  let (start, end) = byte_range.unwrap_or((0, file_size.saturating_sub(1)));
+  let content_length = if file_size == 0 { 0 } else { end - start + 1 };
+  if start > 0 {
+    file_to_serve.file.seek(SeekFrom::Start(start)).await?;
+  }
+
+  let reader_stream = file_frames(file_to_serve.file, content_length, config.stream_chunk_size);
 
   let mut response = Response::new(BoxBody::new(StreamBody::new(reader_stream)));
-  response.headers_mut().append(
-    "Content-Type",
-    HeaderValue::from_str(file_to_serve.meta.mime.as_ref()).unwrap(),
+  let content_type = content_type_header(
+    &content_type_with_charset(&file_to_serve.meta.mime),
+    &file_to_serve.meta.file_name,
   );
+  response.headers_mut().append("Content-Type", content_type);
 
-  if let Some(encoding) = file_to_serve.meta.encoding {
+  response
+    .headers_mut()
+    .append(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+  response
+    .headers_mut()
+    .append(CONTENT_LENGTH, HeaderValue::from(content_length));
+  if byte_range.is_some() {
+    *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+    response.headers_mut().append(
+      CONTENT_RANGE,
+      HeaderValue::from_str(&format!("bytes {start}-{end}/{file_size}"))?,
+    );
+  }
+  response
+    .headers_mut()
+    .append(VARY, HeaderValue::from_static("Accept-Encoding"));
+  response
+    .headers_mut()
+    .append(ETAG, HeaderValue::from_str(&validators.etag)?);
+  if let Some(last_modified) = &validators.last_modified {
     response
       .headers_mut()
-      .append("Content-Encoding", HeaderValue::from_str(encoding).unwrap());
+      .append(LAST_MODIFIED, HeaderValue::from_str(last_modified)?);
+  }
+
+  if let Some(encoding) = file_to_serve.meta.encoding {
+    match HeaderValue::from_str(encoding) {
+      Ok(value) => {
+        response.headers_mut().append("Content-Encoding", value);
+      }
+      Err(err) => warn!("could not use encoding \"{encoding}\" as a header: {err}"),
+    }
   }
 
+  append_security_headers(&mut response, config);
+
   Ok(response)
 }
 
+fn not_found_response(config: &FrontendConfig) -> Response<BoxBody<Bytes, ServiceError>> {
+  let mut response = Response::new(full_body(NOT_FOUND_BODY));
+  *response.status_mut() = StatusCode::NOT_FOUND;
+  response
+    .headers_mut()
+    .append("Content-Type", HeaderValue::from_static("text/html"));
+  append_security_headers(&mut response, config);
+  response
+}
+
+struct Validators {
+  etag: String,
+  last_modified: Option<String>,
+}
+
+impl Validators {
+  fn new(file_size: u64, modified: Option<SystemTime>) -> Self {
+    let modified_secs = modified
+      .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+      .map(|since_epoch| since_epoch.as_secs())
+      .unwrap_or_default();
+
+    Validators {
+      etag: format!("\"{modified_secs:x}-{file_size:x}\""),
+      last_modified: modified.map(fmt_http_date),
+    }
+  }
+
+  // weak entity tags never match, as required for If-Range
+  fn matches(&self, if_range: &str) -> bool {
+    let if_range = if_range.trim();
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+      return if_range == self.etag;
+    }
+
+    let if_range_date = parse_http_date(if_range).ok().map(fmt_http_date);
+    if_range_date.is_some() && if_range_date == self.last_modified
+  }
+}
+
+const BYTES_RANGE_PREFIX: &str = "bytes=";
+// returns inclusive bounds of a single satisfiable range - malformed, unsatisfiable and multiple
+// ranges are all rejected
+fn parse_byte_range(range: &str, file_size: u64) -> Option<(u64, u64)> {
+  let spec = range.trim().strip_prefix(BYTES_RANGE_PREFIX)?;
+  if spec.contains(',') {
+    return None;
+  }
+
+  let (start, end) = spec.split_once('-')?;
+  let (start, end) = (start.trim(), end.trim());
+  let last_byte = file_size.checked_sub(1)?;
+  match (start.is_empty(), end.is_empty()) {
+    (true, true) => None,
+    (true, false) => {
+      let suffix_length: u64 = end.parse().ok()?;
+      if suffix_length == 0 {
+        return None;
+      }
+
+      Some((file_size.saturating_sub(suffix_length), last_byte))
+    }
+    (false, _) => {
+      let start: u64 = start.parse().ok()?;
+      let end: u64 = if end.is_empty() {
+        last_byte
+      } else {
+        end.parse().ok()?
+      };
+      if start > end || start > last_byte {
+        return None;
+      }
+
+      Some((start, end.min(last_byte)))
+    }
+  }
+}
+
+// each frame holds at most chunk_size bytes, so large files are never buffered whole
+fn file_frames(
+  file: File,
+  content_length: u64,
+  chunk_size: usize,
+) -> impl Stream<Item = Result<Frame<Bytes>, ServiceError>> {
+  ReaderStream::with_capacity(file.take(content_length), chunk_size).map(|chunk| match chunk {
+    Ok(bytes) => Ok(Frame::data(bytes)),
+    Err(err) => Err(Box::new(err).into()),
+  })
+}
+
+const FALLBACK_CONTENT_TYPE: &str = "application/octet-stream";
+const TEXT_CHARSET_PARAM: &str = "charset=utf-8";
+fn content_type_header(content_type: &str, file_name: &str) -> HeaderValue {
+  match HeaderValue::from_str(content_type) {
+    Ok(value) => value,
+    Err(err) => {
+      warn!(
+        "could not use mime \"{content_type}\" of \"{file_name}\" as a content type, falling back to \"{FALLBACK_CONTENT_TYPE}\": {err}"
+      );
+      HeaderValue::from_static(FALLBACK_CONTENT_TYPE)
+    }
+  }
+}
+
+// frontend text files are expected to be utf-8 encoded - binary types are left untouched
+fn content_type_with_charset(mime_type: &Mime) -> String {
+  let is_text = mime_type.type_() == mime_guess::mime::TEXT
+    || (mime_type.type_() == mime_guess::mime::APPLICATION
+      && (mime_type.subtype() == mime_guess::mime::JAVASCRIPT
+        || mime_type.subtype() == mime_guess::mime::JSON));
+  if is_text && mime_type.get_param(mime_guess::mime::CHARSET).is_none() {
+    format!("{mime_type}; {TEXT_CHARSET_PARAM}")
+  } else {
+    mime_type.to_string()
+  }
+}
+
+const REFERRER_POLICY_VALUE: &str = "same-origin";
+fn append_security_headers<T>(response: &mut Response<T>, config: &FrontendConfig) {
+  let headers = response.headers_mut();
+  headers.append(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+  headers.append(X_FRAME_OPTIONS, HeaderValue::from_static("SAMEORIGIN"));
+  headers.append(
+    REFERRER_POLICY,
+    HeaderValue::from_static(REFERRER_POLICY_VALUE),
+  );
+
+  if let Some(csp) = &config.content_security_policy {
+    match HeaderValue::from_str(csp) {
+      Ok(value) => {
+        headers.append(CONTENT_SECURITY_POLICY, value);
+      }
+      Err(err) => warn!("could not use provided content security policy as a header: {err}"),
+    }
+  }
+}
+
 struct ServedFileMeta {
   mime: Mime,
   file_name: String,
   encoding: Option<&'static str>,
+  // name of the plain file, when this is its pre-compressed variant
+  compressed_from: Option<String>,
 }
 
 struct ServedFile {
@@ -72,60 +289,63 @@ async fn decide_file_to_serve(
   name: Option<&str>,
   encodings: &[String],
   pkgs_repo: &PackagesRepository,
+  config: &FrontendConfig,
 ) -> Option<ServedFile> {
+  let mime_types = &config.mime_types;
   let mut file_candidates: VecDeque<ServedFileMeta> = VecDeque::new();
   // fallback to entrypoint on unmatched paths, with additional fallback to default index name
   // required for BrowserRouter in mpv-web-frontend
-  let entrypoint_fallback_name = match pkgs_repo.get_installed() {
-    Ok(pkg) => pkg
+  let installed_pkg = match config.serve_dir {
+    Some(_) => None,
+    None => pkgs_repo.get_installed().ok(),
+  };
+  let entrypoint_fallback_name = match installed_pkg {
+    Some(pkg) => pkg
       .manifest
       .version_info
       .entrypoint
       .as_deref()
       .unwrap_or(DEFAULT_ENTRYPOINT_FILE_NAME),
-    Err(_) => DEFAULT_ENTRYPOINT_FILE_NAME,
+    None => DEFAULT_ENTRYPOINT_FILE_NAME,
   };
-  let (entrypoint_mime_type, entrypoint_encoding) =
-    file_mime_and_encoding(entrypoint_fallback_name);
-  file_candidates.push_back(ServedFileMeta {
-    file_name: entrypoint_fallback_name.to_owned(),
-    mime: entrypoint_mime_type.clone(),
-    encoding: entrypoint_encoding,
-  });
-  if entrypoint_encoding.is_none()
-    && should_file_be_encoded(&entrypoint_mime_type)
-    && let Some((ext, encoding)) = decide_encoding_extension(encodings)
-  {
-    file_candidates.push_front(ServedFileMeta {
-      file_name: format!("{entrypoint_fallback_name}.{ext}"),
-      mime: entrypoint_mime_type,
-      encoding: Some(encoding),
-    });
-  }
+  push_front_candidates(
+    &mut file_candidates,
+    entrypoint_fallback_name,
+    encodings,
+    mime_types,
+  );
 
   if let Some(name) = name {
-    let (file_mime_type, file_encoding) = file_mime_and_encoding(name);
-    file_candidates.push_front(ServedFileMeta {
-      mime: file_mime_type.clone(),
-      file_name: name.to_owned(),
-      encoding: file_encoding,
-    });
-    if file_encoding.is_none()
-      && should_file_be_encoded(&file_mime_type)
-      && let Some((ext, encoding)) = decide_encoding_extension(encodings)
-    {
-      file_candidates.push_front(ServedFileMeta {
-        mime: file_mime_type,
-        file_name: format!("{name}.{ext}"),
-        encoding: Some(encoding),
-      });
+    // nested directories can have their own index, preferred over the top-level entrypoint
+    let dir_name = name.trim_end_matches('/');
+    if !dir_name.is_empty() {
+      push_front_candidates(
+        &mut file_candidates,
+        &format!("{dir_name}/{DEFAULT_ENTRYPOINT_FILE_NAME}"),
+        encodings,
+        mime_types,
+      );
+    }
+    if !name.ends_with('/') {
+      push_front_candidates(&mut file_candidates, name, encodings, mime_types);
     }
   };
 
   let mut src_file_opt: Option<ServedFile> = None;
   for file_candidate in file_candidates {
     let src_file_name = &file_candidate.file_name;
-    match pkgs_repo.get_installed_file(src_file_name).await {
+    if let Some(plain_name) = &file_candidate.compressed_from
+      && is_below_compression_size(plain_name, pkgs_repo, config).await
+    {
+      debug!("serving \"{plain_name}\" plain since it is below the compression size threshold");
+      continue;
+    }
+
+    let open_result = match &config.serve_dir {
+      Some(serve_dir) => open_file_in_dir(serve_dir, src_file_name).await,
+      None => pkgs_repo.get_installed_file(src_file_name).await,
+    };
+    match open_result {
       Ok((file, path)) => {
         src_file_opt = Some(ServedFile {
           file,
@@ -143,6 +363,56 @@ async fn decide_file_to_serve(
   src_file_opt
 }
 
+// encoded variant, when applicable, ends up in front of the plain file
+fn push_front_candidates(
+  file_candidates: &mut VecDeque<ServedFileMeta>,
+  name: &str,
+  encodings: &[String],
+  mime_types: &HashMap<String, Mime>,
+) {
+  let (file_mime_type, file_encoding) = file_mime_and_encoding(name, mime_types);
+  file_candidates.push_front(ServedFileMeta {
+    mime: file_mime_type.clone(),
+    file_name: name.to_owned(),
+    encoding: file_encoding,
+    compressed_from: None,
+  });
+  if file_encoding.is_none()
+    && should_file_be_encoded(&file_mime_type)
+    && let Some((ext, encoding)) = decide_encoding_extension(encodings)
+  {
+    file_candidates.push_front(ServedFileMeta {
+      mime: file_mime_type,
+      file_name: format!("{name}.{ext}"),
+      encoding: Some(encoding),
+      compressed_from: Some(name.to_owned()),
+    });
+  }
+}
+
+// compression overhead is not worth it for small files - a missing plain file is not small
+async fn is_below_compression_size(
+  plain_name: &str,
+  pkgs_repo: &PackagesRepository,
+  config: &FrontendConfig,
+) -> bool {
+  if config.min_compression_size == 0 {
+    return false;
+  }
+
+  let open_result = match &config.serve_dir {
+    Some(serve_dir) => open_file_in_dir(serve_dir, plain_name).await,
+    None => pkgs_repo.get_installed_file(plain_name).await,
+  };
+  let Ok((file, _)) = open_result else {
+    return false;
+  };
+  file
+    .metadata()
+    .await
+    .is_ok_and(|metadata| metadata.len() < config.min_compression_size)
+}
+
 const ENCODABLE_MIMES: [Mime; 6] = [
   mime_guess::mime::APPLICATION_JAVASCRIPT,
   mime_guess::mime::APPLICATION_JAVASCRIPT_UTF_8,
@@ -171,28 +441,46 @@ fn decide_encoding_extension(encodings: &[String]) -> Option<(&'static str, &'st
   }
 }
 
-fn file_mime_and_encoding<T>(name: T) -> (Mime, Option<&'static str>)
+// types missing from (or guessed differently by) older mime_guess tables
+const BUILTIN_MIME_TYPES: [(&str, &str); 3] = [
+  ("wasm", "application/wasm"),
+  ("webmanifest", "application/manifest+json"),
+  ("avif", "image/avif"),
+];
+fn file_mime_and_encoding<T>(
+  name: T,
+  mime_types: &HashMap<String, Mime>,
+) -> (Mime, Option<&'static str>)
 where
   T: AsRef<Path>,
 {
   let encoding = encoding_for_name(&name);
-  let file_type = match encoding {
-    Some(_) => {
-      let name_without_ext = name.as_ref().file_stem();
-      match name_without_ext {
-        Some(name) => mime_guess::from_path(name),
-        None => mime_guess::from_path(name),
-      }
-    }
-    None => mime_guess::from_path(name),
+  let name = name.as_ref();
+  let name = match encoding {
+    Some(_) => name.file_stem().map(Path::new).unwrap_or(name),
+    None => name,
   };
-  let mime_type = file_type
-    .first()
-    .unwrap_or(mime_guess::mime::APPLICATION_OCTET_STREAM);
+  let mime_type = overridden_mime_type(name, mime_types).unwrap_or_else(|| {
+    mime_guess::from_path(name)
+      .first()
+      .unwrap_or(mime_guess::mime::APPLICATION_OCTET_STREAM)
+  });
 
   (mime_type, encoding)
 }
 
+fn overridden_mime_type(name: &Path, mime_types: &HashMap<String, Mime>) -> Option<Mime> {
+  let extension = name.extension()?.to_str()?.to_lowercase();
+  if let Some(mime_type) = mime_types.get(&extension) {
+    return Some(mime_type.clone());
+  }
+
+  BUILTIN_MIME_TYPES
+    .iter()
+    .find(|(builtin_extension, _)| *builtin_extension == extension)
+    .and_then(|(_, mime_type)| mime_type.parse().ok())
+}
+
 fn encoding_for_name<T>(name: T) -> Option<&'static str>
 where
   T: AsRef<Path>,
@@ -204,3 +492,107 @@ where
     None
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use futures::StreamExt;
+
+  use super::{FALLBACK_CONTENT_TYPE, content_type_header, file_frames, parse_byte_range};
+
+  const FILE_SIZE: u64 = 100;
+
+  #[test]
+  fn parses_bounded_range() {
+    assert_eq!(parse_byte_range("bytes=0-9", FILE_SIZE), Some((0, 9)));
+    assert_eq!(
+      parse_byte_range(" bytes=10 - 20 ", FILE_SIZE),
+      Some((10, 20))
+    );
+  }
+
+  #[test]
+  fn parses_open_ended_range() {
+    assert_eq!(parse_byte_range("bytes=90-", FILE_SIZE), Some((90, 99)));
+  }
+
+  #[test]
+  fn clamps_end_past_file_size() {
+    assert_eq!(parse_byte_range("bytes=90-500", FILE_SIZE), Some((90, 99)));
+  }
+
+  #[test]
+  fn parses_suffix_range() {
+    assert_eq!(parse_byte_range("bytes=-10", FILE_SIZE), Some((90, 99)));
+    assert_eq!(parse_byte_range("bytes=-500", FILE_SIZE), Some((0, 99)));
+  }
+
+  #[test]
+  fn rejects_malformed_ranges() {
+    for range in [
+      "0-9",
+      "items=0-9",
+      "bytes=",
+      "bytes=-",
+      "bytes=5",
+      "bytes=a-9",
+      "bytes=0-b",
+      "bytes=-x",
+    ] {
+      assert_eq!(parse_byte_range(range, FILE_SIZE), None, "{range}");
+    }
+  }
+
+  #[test]
+  fn rejects_unsatisfiable_ranges() {
+    for range in ["bytes=100-", "bytes=150-200", "bytes=20-10", "bytes=-0"] {
+      assert_eq!(parse_byte_range(range, FILE_SIZE), None, "{range}");
+    }
+    assert_eq!(parse_byte_range("bytes=0-", 0), None);
+  }
+
+  #[test]
+  fn rejects_multiple_ranges() {
+    assert_eq!(parse_byte_range("bytes=0-9,20-29", FILE_SIZE), None);
+  }
+
+  #[test]
+  fn invalid_content_type_falls_back_to_octet_stream() {
+    assert_eq!(
+      content_type_header("text/plain\n; charset=utf-8", "notes.txt"),
+      FALLBACK_CONTENT_TYPE
+    );
+    assert_eq!(
+      content_type_header("text/css; charset=utf-8", "style.css"),
+      "text/css; charset=utf-8"
+    );
+  }
+
+  #[tokio::test]
+  async fn file_is_streamed_in_chunks_of_configured_size() {
+    let content: Vec<u8> = (0..=u8::MAX).cycle().take(1000).collect();
+    let path = std::env::temp_dir().join(format!("mwc-frontend-chunks-{}", std::process::id()));
+    std::fs::write(&path, &content).unwrap();
+    let chunk_size = 64;
+
+    let file = tokio::fs::File::open(&path).await.unwrap();
+    let frames: Vec<_> = file_frames(file, content.len() as u64, chunk_size)
+      .collect()
+      .await;
+
+    let mut streamed = Vec::new();
+    for frame in frames {
+      let data = frame.unwrap().into_data().unwrap();
+      assert!(data.len() <= chunk_size, "{}", data.len());
+      streamed.extend_from_slice(&data);
+    }
+    assert_eq!(streamed, content);
+
+    let file = tokio::fs::File::open(&path).await.unwrap();
+    let frames: Vec<_> = file_frames(file, 100, chunk_size).collect().await;
+    let lengths: Vec<usize> = frames
+      .into_iter()
+      .map(|frame| frame.unwrap().into_data().unwrap().len())
+      .collect();
+    assert_eq!(lengths, [64, 36]);
+  }
+}
@@ -0,0 +1,155 @@
+use std::{
+  sync::{
+    Mutex, PoisonError,
+    atomic::{AtomicBool, Ordering},
+  },
+  time::{Duration, Instant},
+};
+
+use tokio::{sync::Notify, time::sleep_until};
+
+pub struct IdleTracker {
+  timeout: Option<Duration>,
+  last_activity: Mutex<Instant>,
+  paused: AtomicBool,
+  resumed: Notify,
+}
+
+impl IdleTracker {
+  pub fn new(timeout: Option<u32>) -> Self {
+    IdleTracker {
+      timeout: timeout.map(|secs| Duration::from_secs(secs.into())),
+      last_activity: Mutex::new(Instant::now()),
+      paused: AtomicBool::new(false),
+      resumed: Notify::new(),
+    }
+  }
+
+  pub fn timeout(&self) -> Option<Duration> {
+    self.timeout
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.paused.load(Ordering::SeqCst)
+  }
+
+  pub fn pause(&self) {
+    self.paused.store(true, Ordering::SeqCst);
+  }
+
+  // counting starts over from the moment of resuming
+  pub fn resume(&self) {
+    self.touch();
+    self.paused.store(false, Ordering::SeqCst);
+    self.resumed.notify_waiters();
+  }
+
+  pub fn touch(&self) {
+    *self
+      .last_activity
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+  }
+
+  pub fn remaining(&self) -> Option<Duration> {
+    let timeout = self.timeout?;
+    if self.is_paused() {
+      return Some(timeout);
+    }
+
+    let idle_for = self
+      .last_activity
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner)
+      .elapsed();
+    Some(timeout.saturating_sub(idle_for))
+  }
+
+  // resolves once no activity has been recorded for the whole timeout; never resolves when disabled
+  pub async fn wait_until_idle(&self) {
+    loop {
+      let resumed = self.resumed.notified();
+      if self.is_paused() {
+        resumed.await;
+        continue;
+      }
+
+      match self.remaining() {
+        Some(remaining) if remaining.is_zero() => return,
+        Some(remaining) => sleep_until((Instant::now() + remaining).into()).await,
+        None => std::future::pending::<()>().await,
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use tokio::time::{sleep, timeout};
+
+  use super::IdleTracker;
+
+  const TIMEOUT: Duration = Duration::from_secs(1);
+
+  #[tokio::test]
+  async fn disabled_tracker_never_idles() {
+    let tracker = IdleTracker::new(None);
+
+    assert_eq!(tracker.remaining(), None);
+    assert!(
+      timeout(Duration::from_millis(100), tracker.wait_until_idle())
+        .await
+        .is_err()
+    );
+  }
+
+  #[tokio::test]
+  async fn activity_resets_remaining_time() {
+    let tracker = IdleTracker::new(Some(1));
+    sleep(Duration::from_millis(200)).await;
+    let remaining = tracker.remaining().unwrap();
+    assert!(remaining < TIMEOUT - Duration::from_millis(100));
+
+    tracker.touch();
+    assert!(tracker.remaining().unwrap() > remaining);
+  }
+
+  #[tokio::test]
+  async fn paused_tracker_idles_only_after_resume() {
+    let tracker = IdleTracker::new(Some(1));
+    tracker.pause();
+
+    assert_eq!(tracker.remaining(), Some(TIMEOUT));
+    assert!(
+      timeout(
+        TIMEOUT + Duration::from_millis(200),
+        tracker.wait_until_idle()
+      )
+      .await
+      .is_err()
+    );
+
+    tracker.resume();
+    assert!(!tracker.is_paused());
+    assert!(tracker.remaining().unwrap() > TIMEOUT - Duration::from_millis(100));
+    assert!(
+      timeout(TIMEOUT * 2, tracker.wait_until_idle())
+        .await
+        .is_ok()
+    );
+  }
+
+  #[tokio::test]
+  async fn resuming_wakes_pending_waiter() {
+    let tracker = IdleTracker::new(Some(1));
+    tracker.pause();
+
+    let (idle, ()) = tokio::join!(timeout(TIMEOUT * 3, tracker.wait_until_idle()), async {
+      sleep(Duration::from_millis(100)).await;
+      tracker.resume();
+    });
+    assert!(idle.is_ok());
+  }
+}
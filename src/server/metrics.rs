@@ -0,0 +1,122 @@
+use std::{
+  fmt::Write,
+  sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+  },
+};
+
+use http_body_util::{BodyExt, combinators::BoxBody};
+use hyper::{Response, StatusCode, body::Bytes};
+
+use crate::server::common::ServiceError;
+
+const METRICS_PREFIX: &str = "mpv_web_client";
+const STATUS_CLASSES: [&str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+
+#[derive(Default)]
+pub struct Metrics {
+  requests: AtomicU64,
+  responses_by_class: [AtomicU64; STATUS_CLASSES.len()],
+  served_bytes: AtomicU64,
+  not_modified: AtomicU64,
+  active_connections: AtomicU64,
+}
+
+impl Metrics {
+  pub fn record_request(&self) {
+    self.requests.fetch_add(1, Ordering::Relaxed);
+  }
+
+  // bytes are counted as the body is sent, so streamed responses are accounted for as well
+  pub fn record_response(
+    self: &Arc<Self>,
+    response: Response<BoxBody<Bytes, ServiceError>>,
+  ) -> Response<BoxBody<Bytes, ServiceError>> {
+    let status = response.status();
+    if let Some(counter) = (status.as_u16() / 100)
+      .checked_sub(1)
+      .and_then(|idx| self.responses_by_class.get(usize::from(idx)))
+    {
+      counter.fetch_add(1, Ordering::Relaxed);
+    }
+    if status == StatusCode::NOT_MODIFIED {
+      self.not_modified.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let metrics = self.clone();
+    response.map(|body| {
+      body
+        .map_frame(move |frame| {
+          if let Some(data) = frame.data_ref() {
+            metrics
+              .served_bytes
+              .fetch_add(data.len() as u64, Ordering::Relaxed);
+          }
+          frame
+        })
+        .boxed()
+    })
+  }
+
+  pub fn connection_opened(self: &Arc<Self>) -> ConnectionGuard {
+    self.active_connections.fetch_add(1, Ordering::Relaxed);
+    ConnectionGuard(self.clone())
+  }
+
+  pub fn render(&self) -> String {
+    let mut out = String::new();
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+    let _ = write!(
+      out,
+      "# HELP {METRICS_PREFIX}_requests_total Received requests.\n\
+       # TYPE {METRICS_PREFIX}_requests_total counter\n\
+       {METRICS_PREFIX}_requests_total {}\n",
+      load(&self.requests)
+    );
+    let _ = write!(
+      out,
+      "# HELP {METRICS_PREFIX}_responses_total Sent responses by status class.\n\
+       # TYPE {METRICS_PREFIX}_responses_total counter\n"
+    );
+    for (class, counter) in STATUS_CLASSES.iter().zip(&self.responses_by_class) {
+      let _ = writeln!(
+        out,
+        "{METRICS_PREFIX}_responses_total{{class=\"{class}\"}} {}",
+        load(counter)
+      );
+    }
+    let _ = write!(
+      out,
+      "# HELP {METRICS_PREFIX}_served_bytes_total Bytes sent in response bodies.\n\
+       # TYPE {METRICS_PREFIX}_served_bytes_total counter\n\
+       {METRICS_PREFIX}_served_bytes_total {}\n",
+      load(&self.served_bytes)
+    );
+    let _ = write!(
+      out,
+      "# HELP {METRICS_PREFIX}_not_modified_total Responses answered with 304 Not Modified.\n\
+       # TYPE {METRICS_PREFIX}_not_modified_total counter\n\
+       {METRICS_PREFIX}_not_modified_total {}\n",
+      load(&self.not_modified)
+    );
+    let _ = write!(
+      out,
+      "# HELP {METRICS_PREFIX}_active_connections Currently open connections.\n\
+       # TYPE {METRICS_PREFIX}_active_connections gauge\n\
+       {METRICS_PREFIX}_active_connections {}\n",
+      load(&self.active_connections)
+    );
+
+    out
+  }
+}
+
+pub struct ConnectionGuard(Arc<Metrics>);
+
+impl Drop for ConnectionGuard {
+  fn drop(&mut self) {
+    self.0.active_connections.fetch_sub(1, Ordering::Relaxed);
+  }
+}
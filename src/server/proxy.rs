@@ -0,0 +1,243 @@
+use std::{net::SocketAddr, sync::LazyLock};
+
+use futures::StreamExt;
+use http_body_util::BodyExt;
+use hyper::{
+  Request, Response, StatusCode, Uri, Version,
+  body::Incoming,
+  header::{
+    AUTHORIZATION, CONNECTION, HOST, HeaderMap, HeaderName, HeaderValue, PROXY_AUTHENTICATE,
+    PROXY_AUTHORIZATION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION, TE,
+    TRAILER, TRANSFER_ENCODING, UPGRADE,
+  },
+};
+use hyper_util::{
+  client::legacy::{Client, connect::HttpConnector},
+  rt::{TokioExecutor, TokioIo},
+};
+use log::{debug, warn};
+use tokio::{select, sync::Mutex};
+use tokio_tungstenite::{
+  WebSocketStream, connect_async,
+  tungstenite::{handshake::derive_accept_key, protocol::Role},
+};
+
+use crate::{
+  api_servers::ApiServersService,
+  server::common::{ServiceResponse, empty_body, error_json_response_with_status},
+};
+
+const WEBSOCKET_PROTOCOL: &str = "websocket";
+const WEBSOCKET_VERSION: &str = "13";
+const KEEP_ALIVE_HEADER: &str = "keep-alive";
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+const FORWARDED_HOST_HEADER: &str = "x-forwarded-host";
+const FORWARDED_PROTO_HEADER: &str = "x-forwarded-proto";
+
+static HTTP_CLIENT: LazyLock<Client<HttpConnector, Incoming>> =
+  LazyLock::new(|| Client::builder(TokioExecutor::new()).build_http());
+
+pub async fn proxy_http(
+  name: &str,
+  path: &str,
+  req: Request<Incoming>,
+  peer_addr: SocketAddr,
+  strip_authorization: bool,
+  servers_service: &Mutex<ApiServersService>,
+) -> ServiceResponse {
+  let Some(address) = find_instance_address(name, servers_service).await else {
+    return unknown_instance_response(name);
+  };
+
+  let (mut parts, body) = req.into_parts();
+  let upstream_uri = match parts.uri.query() {
+    Some(query) => format!("http://{address}/{path}?{query}"),
+    None => format!("http://{address}/{path}"),
+  };
+  parts.uri = match upstream_uri.parse::<Uri>() {
+    Ok(uri) => uri,
+    Err(err) => {
+      return error_json_response_with_status(
+        format!("address of api server instance \"{name}\" is not a valid upstream: {err}"),
+        StatusCode::BAD_GATEWAY,
+      );
+    }
+  };
+  parts.version = Version::HTTP_11;
+
+  strip_hop_by_hop_headers(&mut parts.headers);
+  // credentials of the client api must not leak to instances, which can be any registered host
+  if strip_authorization {
+    parts.headers.remove(AUTHORIZATION);
+  }
+  // the client sets the host of the instance instead
+  if let Some(host) = parts.headers.remove(HOST) {
+    parts.headers.insert(FORWARDED_HOST_HEADER, host);
+  }
+  let forwarded_for = match parts.headers.get(FORWARDED_FOR_HEADER) {
+    Some(previous) => format!(
+      "{}, {}",
+      previous.to_str().unwrap_or_default(),
+      peer_addr.ip()
+    ),
+    None => peer_addr.ip().to_string(),
+  };
+  parts
+    .headers
+    .insert(FORWARDED_FOR_HEADER, HeaderValue::from_str(&forwarded_for)?);
+  parts
+    .headers
+    .insert(FORWARDED_PROTO_HEADER, HeaderValue::from_static("http"));
+
+  let mut response = match HTTP_CLIENT.request(Request::from_parts(parts, body)).await {
+    Ok(response) => response,
+    Err(err) => {
+      debug!("could not proxy request to instance \"{name}\" at {upstream_uri}: {err}");
+      return error_json_response_with_status(
+        format!("could not reach api server instance \"{name}\": {err}"),
+        StatusCode::BAD_GATEWAY,
+      );
+    }
+  };
+
+  strip_hop_by_hop_headers(response.headers_mut());
+  Ok(response.map(|body| body.map_err(|err| err.into()).boxed()))
+}
+
+pub async fn proxy_websocket(
+  name: &str,
+  path: &str,
+  mut req: Request<Incoming>,
+  servers_service: &Mutex<ApiServersService>,
+) -> ServiceResponse {
+  let Some(address) = find_instance_address(name, servers_service).await else {
+    return unknown_instance_response(name);
+  };
+
+  let Some(key) = websocket_upgrade_key(&req) else {
+    return error_json_response_with_status(
+      "websocket upgrade request expected",
+      StatusCode::BAD_REQUEST,
+    );
+  };
+
+  let upstream_url = match req.uri().query() {
+    Some(query) => format!("ws://{address}/{path}?{query}"),
+    None => format!("ws://{address}/{path}"),
+  };
+  // connecting before accepting the upgrade lets clients know the instance is unreachable
+  let upstream = match connect_async(&upstream_url).await {
+    Ok((upstream, _)) => upstream,
+    Err(err) => {
+      debug!("could not connect to websocket of instance \"{name}\" at {upstream_url}: {err}");
+      return error_json_response_with_status(
+        format!("could not connect to api server instance \"{name}\": {err}"),
+        StatusCode::BAD_GATEWAY,
+      );
+    }
+  };
+
+  let name = name.to_owned();
+  tokio::spawn(async move {
+    let upgraded = match hyper::upgrade::on(&mut req).await {
+      Ok(upgraded) => upgraded,
+      Err(err) => {
+        warn!("could not upgrade websocket connection to instance \"{name}\": {err}");
+        return;
+      }
+    };
+    let client = WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+
+    let (client_sink, client_stream) = client.split();
+    let (upstream_sink, upstream_stream) = upstream.split();
+    select! {
+      result = client_stream.forward(upstream_sink) => {
+        if let Err(err) = result {
+          debug!("websocket proxy to instance \"{name}\" closed on client side: {err}");
+        }
+      }
+      result = upstream_stream.forward(client_sink) => {
+        if let Err(err) = result {
+          debug!("websocket proxy to instance \"{name}\" closed on instance side: {err}");
+        }
+      }
+    }
+  });
+
+  let mut response = Response::new(empty_body());
+  *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+  response
+    .headers_mut()
+    .append(CONNECTION, HeaderValue::from_static("upgrade"));
+  response
+    .headers_mut()
+    .append(UPGRADE, HeaderValue::from_static(WEBSOCKET_PROTOCOL));
+  response.headers_mut().append(
+    SEC_WEBSOCKET_ACCEPT,
+    HeaderValue::from_str(&derive_accept_key(key.as_bytes()))?,
+  );
+  Ok(response)
+}
+
+async fn find_instance_address(
+  name: &str,
+  servers_service: &Mutex<ApiServersService>,
+) -> Option<String> {
+  servers_service
+    .lock()
+    .await
+    .find_by_name(name)
+    .map(|(_, instance)| instance.address.clone())
+}
+
+fn unknown_instance_response(name: &str) -> ServiceResponse {
+  error_json_response_with_status(
+    format!("no api server instance with name \"{name}\" exists"),
+    StatusCode::NOT_FOUND,
+  )
+}
+
+fn websocket_upgrade_key(req: &Request<Incoming>) -> Option<String> {
+  let headers = req.headers();
+  let is_upgrade = headers
+    .get(UPGRADE)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| value.eq_ignore_ascii_case(WEBSOCKET_PROTOCOL));
+  let is_supported_version = headers
+    .get(SEC_WEBSOCKET_VERSION)
+    .is_some_and(|value| value == WEBSOCKET_VERSION);
+  if !is_upgrade || !is_supported_version {
+    return None;
+  }
+
+  headers
+    .get(SEC_WEBSOCKET_KEY)
+    .and_then(|value| value.to_str().ok())
+    .map(|value| value.to_owned())
+}
+
+fn strip_hop_by_hop_headers(headers: &mut HeaderMap) {
+  let connection_headers: Vec<HeaderName> = headers
+    .get_all(CONNECTION)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+    .collect();
+  for name in connection_headers {
+    headers.remove(name);
+  }
+
+  for name in [
+    CONNECTION,
+    PROXY_AUTHENTICATE,
+    PROXY_AUTHORIZATION,
+    TE,
+    TRAILER,
+    TRANSFER_ENCODING,
+    UPGRADE,
+  ] {
+    headers.remove(name);
+  }
+  headers.remove(KEEP_ALIVE_HEADER);
+}
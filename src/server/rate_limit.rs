@@ -0,0 +1,112 @@
+use std::{
+  collections::HashMap,
+  net::IpAddr,
+  num::NonZeroU32,
+  sync::{Mutex, PoisonError},
+  time::{Duration, Instant},
+};
+
+const PRUNE_THRESHOLD: usize = 1024;
+
+struct Bucket {
+  tokens: f64,
+  refilled_at: Instant,
+}
+
+pub struct RateLimiter {
+  per_second: f64,
+  burst: f64,
+  buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+  pub fn new(per_second: NonZeroU32, burst: NonZeroU32) -> Self {
+    RateLimiter {
+      per_second: per_second.get().into(),
+      burst: burst.get().into(),
+      buckets: Mutex::new(HashMap::new()),
+    }
+  }
+
+  // takes a token from the peer's bucket, or returns the time after which one becomes available.
+  pub fn acquire(&self, peer: IpAddr) -> Result<(), Duration> {
+    let now = Instant::now();
+    let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+    if buckets.len() > PRUNE_THRESHOLD {
+      buckets.retain(|_, bucket| self.refilled_tokens(bucket, now) < self.burst);
+    }
+
+    let bucket = buckets.entry(peer).or_insert(Bucket {
+      tokens: self.burst,
+      refilled_at: now,
+    });
+    bucket.tokens = self.refilled_tokens(bucket, now);
+    bucket.refilled_at = now;
+
+    if bucket.tokens >= 1.0 {
+      bucket.tokens -= 1.0;
+      return Ok(());
+    }
+
+    Err(Duration::from_secs_f64(
+      (1.0 - bucket.tokens) / self.per_second,
+    ))
+  }
+
+  fn refilled_tokens(&self, bucket: &Bucket, now: Instant) -> f64 {
+    let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+    (bucket.tokens + elapsed * self.per_second).min(self.burst)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    net::{IpAddr, Ipv4Addr},
+    num::NonZeroU32,
+    thread::sleep,
+    time::Duration,
+  };
+
+  use super::RateLimiter;
+
+  const PEER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 2));
+  const OTHER_PEER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 3));
+
+  fn limiter(per_second: u32, burst: u32) -> RateLimiter {
+    RateLimiter::new(
+      NonZeroU32::new(per_second).unwrap(),
+      NonZeroU32::new(burst).unwrap(),
+    )
+  }
+
+  #[test]
+  fn allows_burst_then_rejects() {
+    let limiter = limiter(1, 3);
+    for _ in 0..3 {
+      assert!(limiter.acquire(PEER).is_ok());
+    }
+
+    let retry_after = limiter.acquire(PEER).unwrap_err();
+    assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(1));
+  }
+
+  #[test]
+  fn tracks_peers_separately() {
+    let limiter = limiter(1, 1);
+    assert!(limiter.acquire(PEER).is_ok());
+    assert!(limiter.acquire(PEER).is_err());
+
+    assert!(limiter.acquire(OTHER_PEER).is_ok());
+  }
+
+  #[test]
+  fn refills_tokens_over_time() {
+    let limiter = limiter(50, 1);
+    assert!(limiter.acquire(PEER).is_ok());
+    let retry_after = limiter.acquire(PEER).unwrap_err();
+
+    sleep(retry_after + Duration::from_millis(10));
+    assert!(limiter.acquire(PEER).is_ok());
+  }
+}
@@ -1,85 +1,192 @@
-use http_body_util::BodyExt;
-use hyper::{Method, Request, body::Incoming};
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use hyper::{
+  Method, Request,
+  body::Incoming,
+  header::{CONTENT_LENGTH, HeaderName, IF_RANGE, RANGE},
+};
 use route_recognizer::Router;
 use serde::Deserialize;
 
 use crate::server::api::{
-  api_servers::{LocalApiServerLogsRequest, LocalApiServerSpawnRequest, LocalApiServerStopRequest},
-  frontend::FrontendUpdateRequest,
+  api_servers::{
+    LocalApiServerLogsArchiveRequest, LocalApiServerLogsRequest, LocalApiServerLogsTailRequest,
+    LocalApiServerRestartRequest, LocalApiServerSpawnRequest, LocalApiServerStopRequest,
+    RemoteApiServerRegisterRequest, RemoteApiServerUnregisterRequest,
+  },
+  frontend::{FrontendUpdateQuery, FrontendUpdateRequest},
 };
 
 enum PathRoutes {
   Frontend,
   Api(ApiPathRoutes),
+  UnknownApi,
+  Proxy,
 }
 
 enum ApiPathRoutes {
   FrontendLatest,
+  FrontendReleases,
   FrontendUpdate,
+  FrontendReloadEvents,
+  FrontendUpdateProgress,
   Shutdown,
+  Idle,
+  IdlePause,
+  IdleResume,
+  Version,
+  Metrics,
   ApiServers(ApiServersPathRoutes),
 }
 
 enum ApiServersPathRoutes {
   Spawn,
+  Register,
+  Unregister,
   All,
   Stop,
+  StopAll,
+  Restart,
+  RespawnSaved,
   Logs,
+  LogsArchive,
+  LogsTail,
+  WebSocket,
 }
 
 pub enum Routes {
-  Frontend(Option<String>, Vec<String>),
+  Frontend(Option<String>, Vec<String>, Option<String>, Option<String>),
   Api(ApiRoutes),
+  Proxy(String, String, Box<Request<Incoming>>),
 }
 
 pub enum ApiRoutes {
   FrontendLatest,
-  FrontendUpdate(FrontendUpdateRequest),
-  Shutdown,
+  FrontendReleases,
+  FrontendUpdate(FrontendUpdateRequest, FrontendUpdateQuery),
+  FrontendReloadEvents,
+  FrontendUpdateProgress,
+  Shutdown(Option<String>),
+  Idle,
+  IdlePause,
+  IdleResume,
+  Version,
+  Metrics,
   ApiServers(ApiServersRoutes),
 }
 
 pub enum ApiServersRoutes {
   Spawn(LocalApiServerSpawnRequest),
+  Register(RemoteApiServerRegisterRequest),
+  Unregister(RemoteApiServerUnregisterRequest),
   All,
   Stop(LocalApiServerStopRequest),
+  StopAll,
+  Restart(LocalApiServerRestartRequest),
+  RespawnSaved,
   Logs(LocalApiServerLogsRequest),
+  LogsArchive(LocalApiServerLogsArchiveRequest),
+  LogsTail(LocalApiServerLogsTailRequest),
+  WebSocket(String, String, Box<Request<Incoming>>),
 }
 
 pub enum RoutingErr {
   Unmatched,
-  InvalidMethod,
+  InvalidMethod(Method),
   InvalidRequestBody(String),
 }
 
-pub async fn get_route(req: Request<hyper::body::Incoming>) -> Result<Routes, RoutingErr> {
+pub async fn get_route(
+  req: Request<hyper::body::Incoming>,
+  max_body_size: usize,
+) -> Result<Routes, RoutingErr> {
   let mut router = Router::new();
 
   router.add(
     "/api/frontend/latest",
     PathRoutes::Api(ApiPathRoutes::FrontendLatest),
   );
+  router.add(
+    "/api/frontend/releases",
+    PathRoutes::Api(ApiPathRoutes::FrontendReleases),
+  );
   router.add(
     "/api/frontend/update",
     PathRoutes::Api(ApiPathRoutes::FrontendUpdate),
   );
+  router.add(
+    "/api/frontend/update/progress",
+    PathRoutes::Api(ApiPathRoutes::FrontendUpdateProgress),
+  );
+  router.add(
+    "/api/frontend/reload-events",
+    PathRoutes::Api(ApiPathRoutes::FrontendReloadEvents),
+  );
   router.add(
     "/api/servers/logs",
     PathRoutes::Api(ApiPathRoutes::ApiServers(ApiServersPathRoutes::Logs)),
   );
+  router.add(
+    "/api/servers/logs/archive",
+    PathRoutes::Api(ApiPathRoutes::ApiServers(ApiServersPathRoutes::LogsArchive)),
+  );
+  router.add(
+    "/api/servers/logs/tail",
+    PathRoutes::Api(ApiPathRoutes::ApiServers(ApiServersPathRoutes::LogsTail)),
+  );
   router.add(
     "/api/servers/spawn",
     PathRoutes::Api(ApiPathRoutes::ApiServers(ApiServersPathRoutes::Spawn)),
   );
+  router.add(
+    "/api/servers/register",
+    PathRoutes::Api(ApiPathRoutes::ApiServers(ApiServersPathRoutes::Register)),
+  );
+  router.add(
+    "/api/servers/unregister",
+    PathRoutes::Api(ApiPathRoutes::ApiServers(ApiServersPathRoutes::Unregister)),
+  );
   router.add(
     "/api/servers/stop",
     PathRoutes::Api(ApiPathRoutes::ApiServers(ApiServersPathRoutes::Stop)),
   );
+  router.add(
+    "/api/servers/restart",
+    PathRoutes::Api(ApiPathRoutes::ApiServers(ApiServersPathRoutes::Restart)),
+  );
+  router.add(
+    "/api/servers/respawn-saved",
+    PathRoutes::Api(ApiPathRoutes::ApiServers(
+      ApiServersPathRoutes::RespawnSaved,
+    )),
+  );
+  router.add(
+    "/api/servers/stop-all",
+    PathRoutes::Api(ApiPathRoutes::ApiServers(ApiServersPathRoutes::StopAll)),
+  );
   router.add(
     "/api/servers",
     PathRoutes::Api(ApiPathRoutes::ApiServers(ApiServersPathRoutes::All)),
   );
+  router.add(
+    "/api/servers/:name/ws",
+    PathRoutes::Api(ApiPathRoutes::ApiServers(ApiServersPathRoutes::WebSocket)),
+  );
+  router.add(
+    "/api/servers/:name/ws/*path",
+    PathRoutes::Api(ApiPathRoutes::ApiServers(ApiServersPathRoutes::WebSocket)),
+  );
   router.add("/api/shutdown", PathRoutes::Api(ApiPathRoutes::Shutdown));
+  router.add("/api/idle", PathRoutes::Api(ApiPathRoutes::Idle));
+  router.add("/api/version", PathRoutes::Api(ApiPathRoutes::Version));
+  router.add("/api/metrics", PathRoutes::Api(ApiPathRoutes::Metrics));
+  router.add("/api/idle/pause", PathRoutes::Api(ApiPathRoutes::IdlePause));
+  router.add(
+    "/api/idle/resume",
+    PathRoutes::Api(ApiPathRoutes::IdleResume),
+  );
+  router.add("/api/*path", PathRoutes::UnknownApi);
+  router.add("/proxy/:name", PathRoutes::Proxy);
+  router.add("/proxy/:name/*path", PathRoutes::Proxy);
   router.add("/*path", PathRoutes::Frontend);
   router.add("/", PathRoutes::Frontend);
 
@@ -91,67 +198,233 @@ pub async fn get_route(req: Request<hyper::body::Incoming>) -> Result<Routes, Ro
   };
 
   match routes.handler() {
-    PathRoutes::Frontend => Ok(Routes::Frontend(
-      routes.params().find("path").map(|val| val.to_owned()),
-      parse_accepted_encodings(req),
-    )),
+    PathRoutes::Frontend => {
+      let range = header_value(&req, RANGE);
+      let if_range = header_value(&req, IF_RANGE);
+      Ok(Routes::Frontend(
+        routes.params().find("path").map(|val| val.to_owned()),
+        parse_accepted_encodings(req),
+        range,
+        if_range,
+      ))
+    }
+    PathRoutes::UnknownApi => Err(RoutingErr::Unmatched),
+    PathRoutes::Proxy => {
+      let name = routes.params().find("name").unwrap_or_default().to_owned();
+      let path = routes.params().find("path").unwrap_or_default().to_owned();
+
+      Ok(Routes::Proxy(name, path, Box::new(req)))
+    }
     PathRoutes::Api(api_path) => match api_path {
       ApiPathRoutes::ApiServers(api_servers_path) => match api_servers_path {
         ApiServersPathRoutes::Spawn => {
-          if req.method() != Method::POST {
-            return Err(RoutingErr::InvalidMethod);
-          }
+          ensure_method(&req, Method::POST)?;
 
-          let req_body = parse_request_body::<LocalApiServerSpawnRequest>(req).await?;
+          let req_body =
+            parse_request_body::<LocalApiServerSpawnRequest>(req, max_body_size).await?;
           Ok(Routes::Api(ApiRoutes::ApiServers(ApiServersRoutes::Spawn(
             req_body,
           ))))
         }
+        ApiServersPathRoutes::Register => {
+          ensure_method(&req, Method::POST)?;
+
+          let req_body =
+            parse_request_body::<RemoteApiServerRegisterRequest>(req, max_body_size).await?;
+          Ok(Routes::Api(ApiRoutes::ApiServers(
+            ApiServersRoutes::Register(req_body),
+          )))
+        }
+        ApiServersPathRoutes::Unregister => {
+          ensure_method(&req, Method::POST)?;
+
+          let req_body =
+            parse_request_body::<RemoteApiServerUnregisterRequest>(req, max_body_size).await?;
+          Ok(Routes::Api(ApiRoutes::ApiServers(
+            ApiServersRoutes::Unregister(req_body),
+          )))
+        }
         ApiServersPathRoutes::Stop => {
-          if req.method() != Method::POST {
-            return Err(RoutingErr::InvalidMethod);
-          }
+          ensure_method(&req, Method::POST)?;
 
-          let req_body = parse_request_body::<LocalApiServerStopRequest>(req).await?;
+          let req_body =
+            parse_request_body::<LocalApiServerStopRequest>(req, max_body_size).await?;
           Ok(Routes::Api(ApiRoutes::ApiServers(ApiServersRoutes::Stop(
             req_body,
           ))))
         }
-        ApiServersPathRoutes::All => Ok(Routes::Api(ApiRoutes::ApiServers(ApiServersRoutes::All))),
+        ApiServersPathRoutes::StopAll => {
+          ensure_method(&req, Method::POST)?;
+
+          Ok(Routes::Api(ApiRoutes::ApiServers(
+            ApiServersRoutes::StopAll,
+          )))
+        }
+        ApiServersPathRoutes::Restart => {
+          ensure_method(&req, Method::POST)?;
+
+          let req_body =
+            parse_request_body::<LocalApiServerRestartRequest>(req, max_body_size).await?;
+          Ok(Routes::Api(ApiRoutes::ApiServers(
+            ApiServersRoutes::Restart(req_body),
+          )))
+        }
+        ApiServersPathRoutes::RespawnSaved => {
+          ensure_method(&req, Method::POST)?;
+
+          Ok(Routes::Api(ApiRoutes::ApiServers(
+            ApiServersRoutes::RespawnSaved,
+          )))
+        }
+        ApiServersPathRoutes::All => {
+          ensure_method(&req, Method::GET)?;
+
+          Ok(Routes::Api(ApiRoutes::ApiServers(ApiServersRoutes::All)))
+        }
         ApiServersPathRoutes::Logs => {
-          if req.method() != Method::GET {
-            return Err(RoutingErr::InvalidMethod);
-          }
+          ensure_method(&req, Method::GET)?;
 
-          let req_body = parse_request_body::<LocalApiServerLogsRequest>(req).await?;
+          let req_query = parse_request_query::<LocalApiServerLogsRequest>(&req)?;
           Ok(Routes::Api(ApiRoutes::ApiServers(ApiServersRoutes::Logs(
-            req_body,
+            req_query,
           ))))
         }
+        ApiServersPathRoutes::LogsArchive => {
+          ensure_method(&req, Method::GET)?;
+
+          let req_query = parse_request_query::<LocalApiServerLogsArchiveRequest>(&req)?;
+          Ok(Routes::Api(ApiRoutes::ApiServers(
+            ApiServersRoutes::LogsArchive(req_query),
+          )))
+        }
+        ApiServersPathRoutes::LogsTail => {
+          ensure_method(&req, Method::GET)?;
+
+          let req_query = parse_request_query::<LocalApiServerLogsTailRequest>(&req)?;
+          Ok(Routes::Api(ApiRoutes::ApiServers(
+            ApiServersRoutes::LogsTail(req_query),
+          )))
+        }
+        ApiServersPathRoutes::WebSocket => {
+          ensure_method(&req, Method::GET)?;
+          let name = routes.params().find("name").unwrap_or_default().to_owned();
+          let path = routes.params().find("path").unwrap_or_default().to_owned();
+
+          Ok(Routes::Api(ApiRoutes::ApiServers(
+            ApiServersRoutes::WebSocket(name, path, Box::new(req)),
+          )))
+        }
       },
-      ApiPathRoutes::Shutdown => Ok(Routes::Api(ApiRoutes::Shutdown)),
-      ApiPathRoutes::FrontendLatest => Ok(Routes::Api(ApiRoutes::FrontendLatest)),
+      ApiPathRoutes::Shutdown => {
+        ensure_method(&req, Method::POST)?;
+
+        let token = req
+          .headers()
+          .get(SHUTDOWN_TOKEN_HEADER)
+          .and_then(|value| value.to_str().ok())
+          .map(|value| value.to_owned());
+
+        Ok(Routes::Api(ApiRoutes::Shutdown(token)))
+      }
+      ApiPathRoutes::Idle => {
+        ensure_method(&req, Method::GET)?;
+
+        Ok(Routes::Api(ApiRoutes::Idle))
+      }
+      ApiPathRoutes::Version => {
+        ensure_method(&req, Method::GET)?;
+
+        Ok(Routes::Api(ApiRoutes::Version))
+      }
+      ApiPathRoutes::Metrics => {
+        ensure_method(&req, Method::GET)?;
+
+        Ok(Routes::Api(ApiRoutes::Metrics))
+      }
+      ApiPathRoutes::IdlePause => {
+        ensure_method(&req, Method::POST)?;
+
+        Ok(Routes::Api(ApiRoutes::IdlePause))
+      }
+      ApiPathRoutes::IdleResume => {
+        ensure_method(&req, Method::POST)?;
+
+        Ok(Routes::Api(ApiRoutes::IdleResume))
+      }
+      ApiPathRoutes::FrontendLatest => {
+        ensure_method(&req, Method::GET)?;
+
+        Ok(Routes::Api(ApiRoutes::FrontendLatest))
+      }
+      ApiPathRoutes::FrontendReleases => {
+        ensure_method(&req, Method::GET)?;
+
+        Ok(Routes::Api(ApiRoutes::FrontendReleases))
+      }
       ApiPathRoutes::FrontendUpdate => {
-        if req.method() != Method::POST {
-          return Err(RoutingErr::InvalidMethod);
-        }
+        ensure_method(&req, Method::POST)?;
+
+        let req_query = parse_request_query::<FrontendUpdateQuery>(&req)?;
+        let req_body = parse_request_body::<FrontendUpdateRequest>(req, max_body_size).await?;
+        Ok(Routes::Api(ApiRoutes::FrontendUpdate(req_body, req_query)))
+      }
+      ApiPathRoutes::FrontendReloadEvents => {
+        ensure_method(&req, Method::GET)?;
+
+        Ok(Routes::Api(ApiRoutes::FrontendReloadEvents))
+      }
+      ApiPathRoutes::FrontendUpdateProgress => {
+        ensure_method(&req, Method::GET)?;
 
-        let req_body = parse_request_body::<FrontendUpdateRequest>(req).await?;
-        Ok(Routes::Api(ApiRoutes::FrontendUpdate(req_body)))
+        Ok(Routes::Api(ApiRoutes::FrontendUpdateProgress))
       }
     },
   }
 }
 
-async fn parse_request_body<T>(req: Request<Incoming>) -> Result<T, RoutingErr>
+fn header_value(req: &Request<Incoming>, name: HeaderName) -> Option<String> {
+  req
+    .headers()
+    .get(name)
+    .and_then(|value| value.to_str().ok())
+    .map(|value| value.to_owned())
+}
+
+fn ensure_method(req: &Request<Incoming>, allowed: Method) -> Result<(), RoutingErr> {
+  if *req.method() != allowed {
+    return Err(RoutingErr::InvalidMethod(allowed));
+  }
+
+  Ok(())
+}
+
+async fn parse_request_body<T>(
+  req: Request<Incoming>,
+  max_body_size: usize,
+) -> Result<T, RoutingErr>
 where
   T: for<'a> Deserialize<'a>,
 {
-  let body_bytes = req
-    .into_body()
+  let content_length = req
+    .headers()
+    .get(CONTENT_LENGTH)
+    .and_then(|val| val.to_str().ok())
+    .and_then(|val| val.parse::<usize>().ok());
+  if content_length.is_some_and(|len| len > max_body_size) {
+    return Err(RoutingErr::InvalidRequestBody(format!(
+      "body too large: exceeds the limit of {max_body_size} bytes"
+    )));
+  }
+
+  let body_bytes = Limited::new(req.into_body(), max_body_size)
     .collect()
     .await
-    .map_err(|err| RoutingErr::InvalidRequestBody(format!("cannot collect request body: {err}")))?
+    .map_err(|err| match err.downcast_ref::<LengthLimitError>() {
+      Some(_) => RoutingErr::InvalidRequestBody(format!(
+        "body too large: exceeds the limit of {max_body_size} bytes"
+      )),
+      None => RoutingErr::InvalidRequestBody(format!("cannot collect request body: {err}")),
+    })?
     .to_bytes();
   let request_string = String::from_utf8(body_bytes.into()).map_err(|err| {
     RoutingErr::InvalidRequestBody(format!("cannot convert body to string: {err}"))
@@ -163,8 +436,19 @@ where
   Ok(request)
 }
 
+fn parse_request_query<T>(req: &Request<Incoming>) -> Result<T, RoutingErr>
+where
+  T: for<'a> Deserialize<'a>,
+{
+  let query = req.uri().query().unwrap_or_default();
+  serde_urlencoded::from_str(query).map_err(|err| {
+    RoutingErr::InvalidRequestBody(format!("incorrect request query provided: {err}"))
+  })
+}
+
 const ENCODINGS_SEPARATOR: &str = ",";
 const ACCEPT_ANY_ENCODING: &str = "*";
+const SHUTDOWN_TOKEN_HEADER: &str = "X-Shutdown-Token";
 fn parse_accepted_encodings(req: Request<hyper::body::Incoming>) -> Vec<String> {
   let mut encodings = req
     .headers()
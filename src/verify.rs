@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+  Config,
+  api_servers::find_local_server_binary,
+  frontend::{
+    check_api_compatibility, check_frontend_pkg,
+    pkg::repository::PackagesRepository,
+    releases::{Version, get_remote_release},
+  },
+  project_paths::{get_frontend_dir, get_logs_dir, get_project_home_dir, get_temp_dir},
+};
+
+enum CheckResult {
+  Ok(String),
+  Warn(String),
+  Fail(String),
+}
+
+// prints a report of the installation state and returns whether all critical checks passed
+pub async fn verify(config: &Config) -> bool {
+  let mut results: Vec<(&str, CheckResult)> = Vec::new();
+  let home_dir = match get_project_home_dir(config.data_dir.as_deref()) {
+    Ok(home_dir) => home_dir,
+    Err(err) => {
+      println!("[fail] project home: {err}");
+      return false;
+    }
+  };
+  for (name, dir) in [
+    ("project home", home_dir.clone()),
+    ("frontend dir", get_frontend_dir(&home_dir)),
+    ("logs dir", get_logs_dir(&home_dir)),
+    ("temp dir", get_temp_dir(&home_dir)),
+  ] {
+    results.push((name, check_dir(dir)));
+  }
+
+  let mut packages_repository = PackagesRepository::new(home_dir);
+  let manifest_result = match packages_repository.check_installed().await {
+    Ok(pkg) => CheckResult::Ok(format!(
+      "installed version {}",
+      pkg.manifest.version_info.version
+    )),
+    Err(err) => CheckResult::Fail(err.to_string()),
+  };
+  let manifest_ok = matches!(manifest_result, CheckResult::Ok(_));
+  results.push(("manifest", manifest_result));
+  if manifest_ok {
+    results.push((
+      "entrypoint",
+      match check_frontend_pkg(&packages_repository).await {
+        Ok(()) => CheckResult::Ok("present".to_owned()),
+        Err(err) => CheckResult::Fail(err.to_string()),
+      },
+    ));
+    results.push((
+      "api version",
+      match check_api_compatibility(&packages_repository).await {
+        Ok(()) => {
+          CheckResult::Ok("no incompatibility with the installed frontend detected".to_owned())
+        }
+        Err(err) => CheckResult::Warn(err.to_string()),
+      },
+    ));
+  }
+
+  results.push((
+    "mpv-web-api",
+    match find_local_server_binary() {
+      Some(path) => CheckResult::Ok(format!("found at {}", path.to_string_lossy())),
+      None => CheckResult::Warn(
+        "binary not found on PATH - local api servers cannot be spawned".to_owned(),
+      ),
+    },
+  ));
+
+  results.push((
+    "remote release",
+    if config.offline {
+      CheckResult::Warn("skipped in offline mode".to_owned())
+    } else {
+      match get_remote_release(&config.releases_url, Version::Latest).await {
+        Ok(release) => CheckResult::Ok(format!("latest version {}", release.version)),
+        Err(err) => CheckResult::Warn(err.to_string()),
+      }
+    },
+  ));
+
+  let mut healthy = true;
+  for (name, result) in results {
+    match result {
+      CheckResult::Ok(msg) => println!("[ok]   {name}: {msg}"),
+      CheckResult::Warn(msg) => println!("[warn] {name}: {msg}"),
+      CheckResult::Fail(msg) => {
+        healthy = false;
+        println!("[fail] {name}: {msg}");
+      }
+    }
+  }
+
+  healthy
+}
+
+const WRITE_PROBE_FILE_NAME: &str = ".mwc_write_probe";
+fn check_dir(dir: PathBuf) -> CheckResult {
+  if !dir.is_dir() {
+    return CheckResult::Fail(format!("{} does not exist", dir.to_string_lossy()));
+  }
+
+  match probe_write(&dir) {
+    Ok(()) => CheckResult::Ok(format!("{} is writable", dir.to_string_lossy())),
+    Err(err) => CheckResult::Fail(format!("{} is not writable: {err}", dir.to_string_lossy())),
+  }
+}
+
+fn probe_write(dir: &Path) -> Result<(), std::io::Error> {
+  let probe = dir.join(WRITE_PROBE_FILE_NAME);
+  std::fs::write(&probe, [])?;
+  std::fs::remove_file(&probe)
+}
@@ -0,0 +1,39 @@
+use std::{path::Path, time::Duration};
+
+use log::{debug, warn};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::{
+  sync::{broadcast, mpsc},
+  time::sleep,
+};
+
+const DEBOUNCE_PERIOD: Duration = Duration::from_millis(200);
+
+// changes are watched until the returned watcher is dropped
+pub fn watch_dir(
+  path: &Path,
+  changes: broadcast::Sender<()>,
+) -> Result<RecommendedWatcher, notify::Error> {
+  let (events_tx, mut events_rx) = mpsc::unbounded_channel::<()>();
+  let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+    Ok(event) if !event.kind.is_access() => {
+      let _ = events_tx.send(());
+    }
+    Ok(_) => {}
+    Err(err) => warn!("could not watch the served directory: {err}"),
+  })?;
+  watcher.watch(path, RecursiveMode::Recursive)?;
+
+  tokio::spawn(async move {
+    while events_rx.recv().await.is_some() {
+      // bursts of changes (e.g. a rebuild of the whole directory) result in a single reload
+      sleep(DEBOUNCE_PERIOD).await;
+      while events_rx.try_recv().is_ok() {}
+
+      debug!("served directory changed - notifying reload listeners");
+      let _ = changes.send(());
+    }
+  });
+
+  Ok(watcher)
+}
@@ -0,0 +1,123 @@
+use mpv_web_client::{ApiCredentials, Config};
+use reqwest::StatusCode;
+
+use crate::common::{INDEX_HTML, start_serve_dir_server};
+
+mod common;
+
+async fn get_status(url: &str, path: &str) -> StatusCode {
+  reqwest::get(format!("{url}{path}"))
+    .await
+    .expect("request failed")
+    .status()
+}
+
+#[tokio::test]
+async fn peer_outside_allowlist_reaches_only_unrestricted_routes() {
+  let url = start_serve_dir_server(
+    "denied",
+    Config {
+      api_allowlist: vec!["10.0.0.0/8".parse().unwrap()],
+      ..Config::default()
+    },
+  );
+
+  assert_eq!(
+    get_status(&url, "/api/servers").await,
+    StatusCode::FORBIDDEN
+  );
+  assert_eq!(
+    get_status(&url, "/api/servers/logs/tail?name=any").await,
+    StatusCode::FORBIDDEN
+  );
+  assert_eq!(get_status(&url, "/api/version").await, StatusCode::OK);
+  let response = reqwest::get(format!("{url}/"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.text().await.unwrap(), INDEX_HTML);
+}
+
+#[tokio::test]
+async fn peer_inside_allowlist_reaches_process_control_routes() {
+  let url = start_serve_dir_server(
+    "allowed",
+    Config {
+      api_allowlist: vec![
+        "10.0.0.0/8".parse().unwrap(),
+        "127.0.0.0/8".parse().unwrap(),
+      ],
+      ..Config::default()
+    },
+  );
+
+  assert_eq!(get_status(&url, "/api/servers").await, StatusCode::OK);
+}
+
+const API_USER: &str = "fixture-user";
+const API_PASSWORD: &str = "fixture-password";
+
+fn start_protected_server(name: &str) -> String {
+  start_serve_dir_server(
+    name,
+    Config {
+      api_credentials: Some(ApiCredentials {
+        user: API_USER.to_owned(),
+        password: API_PASSWORD.to_owned(),
+      }),
+      ..Config::default()
+    },
+  )
+}
+
+#[tokio::test]
+async fn api_calls_without_valid_credentials_are_unauthorized() {
+  let url = start_protected_server("unauthorized");
+  let client = reqwest::Client::new();
+
+  let response = client
+    .get(format!("{url}/api/servers"))
+    .send()
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  let challenge = response.headers()["www-authenticate"].to_str().unwrap();
+  assert!(challenge.starts_with("Basic realm="), "{challenge}");
+
+  for (user, password) in [(API_USER, "not-the-password"), ("someone", API_PASSWORD)] {
+    let response = client
+      .get(format!("{url}/api/servers"))
+      .basic_auth(user, Some(password))
+      .send()
+      .await
+      .expect("request failed");
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{user}");
+  }
+
+  let response = client
+    .post(format!("{url}/api/servers/spawn"))
+    .body("{}")
+    .send()
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn api_calls_with_valid_credentials_are_served() {
+  let url = start_protected_server("authorized");
+
+  let response = reqwest::Client::new()
+    .get(format!("{url}/api/servers"))
+    .basic_auth(API_USER, Some(API_PASSWORD))
+    .send()
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let response = reqwest::get(format!("{url}/"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.text().await.unwrap(), INDEX_HTML);
+}
@@ -0,0 +1,1230 @@
+use std::{
+  io::{Read, Write},
+  net::TcpListener,
+  os::unix::fs::PermissionsExt,
+  path::{Path, PathBuf},
+  process::{Child, Command},
+  time::Duration,
+};
+
+use mpv_web_client::Config;
+use reqwest::StatusCode;
+use serde_json::Value;
+use tokio::time::sleep;
+
+use crate::common::start_serve_dir_server;
+
+mod common;
+
+// stands in for mpv-web-api - records its arguments and runs until signalled
+const STUB_API_SERVER: &str = "#!/bin/sh
+if [ \"$1\" = \"--version\" ]; then
+  echo \"mpv-web-api 1.0.0\"
+  exit 0
+fi
+if [ -n \"$STUB_EXIT_CODE\" ]; then
+  echo \"$@\" >> \"$STUB_ARGS_FILE\"
+  exit \"$STUB_EXIT_CODE\"
+fi
+echo \"$@\" > \"$STUB_ARGS_FILE\"
+echo \"listening on $2\"
+echo \"stub warning\" >&2
+if [ -n \"$STUB_IGNORE_TERM\" ]; then
+  trap '' TERM
+fi
+exec sleep 30
+";
+
+struct ClientProcess(Child);
+
+impl Drop for ClientProcess {
+  fn drop(&mut self) {
+    let _ = self.0.kill();
+    let _ = self.0.wait();
+  }
+}
+
+fn write_stub_api_server(root: &Path) -> PathBuf {
+  let bin_dir = root.join("bin");
+  std::fs::create_dir_all(&bin_dir).expect("stub dir could not be created");
+  let stub = bin_dir.join("mpv-web-api");
+  std::fs::write(&stub, STUB_API_SERVER).expect("stub could not be written");
+  std::fs::set_permissions(&stub, std::fs::Permissions::from_mode(0o755))
+    .expect("stub could not be made executable");
+  bin_dir
+}
+
+// api servers are looked up on PATH, so the client runs as a separate process with the stub on it
+async fn spawn_client_with_stub(root: &Path) -> (ClientProcess, String) {
+  spawn_client_with_stub_args(root, &[]).await
+}
+
+async fn spawn_client_with_stub_args(root: &Path, args: &[&str]) -> (ClientProcess, String) {
+  let serve_dir = root.join("dist");
+  std::fs::create_dir_all(&serve_dir).expect("fixture dir could not be created");
+  std::fs::write(serve_dir.join("index.html"), "<html>fixture</html>")
+    .expect("fixture could not be written");
+  let bin_dir = write_stub_api_server(root);
+  let path = std::env::join_paths(std::iter::once(bin_dir).chain(std::env::split_paths(
+    &std::env::var_os("PATH").unwrap_or_default(),
+  )))
+  .expect("PATH could not be extended");
+
+  spawn_client_with_path(root, &path, args).await
+}
+
+async fn spawn_client_with_path(
+  root: &Path,
+  path: &std::ffi::OsStr,
+  args: &[&str],
+) -> (ClientProcess, String) {
+  let serve_dir = root.join("dist");
+  let port = TcpListener::bind("127.0.0.1:0")
+    .and_then(|listener| listener.local_addr())
+    .expect("free port could not be found")
+    .port();
+
+  let child = Command::new(env!("CARGO_BIN_EXE_mpv-web-client"))
+    .arg("--serve-dir")
+    .arg(&serve_dir)
+    .arg("--data-dir")
+    .arg(root.join("data"))
+    .args(["--port", &port.to_string(), "--offline"])
+    .args(args)
+    .env("PATH", path)
+    .spawn()
+    .expect("binary could not be run");
+  let process = ClientProcess(child);
+
+  for _ in 0..100 {
+    if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+      return (process, format!("http://127.0.0.1:{port}"));
+    }
+    sleep(Duration::from_millis(50)).await;
+  }
+  panic!("client did not start listening on port {port}");
+}
+
+async fn read_stub_args(path: &Path) -> String {
+  for _ in 0..100 {
+    if let Ok(args) = std::fs::read_to_string(path)
+      && !args.is_empty()
+    {
+      return args;
+    }
+    sleep(Duration::from_millis(50)).await;
+  }
+  panic!("stub api server was not started");
+}
+
+async fn post_json(url: &str, path: &str, body: Value) -> reqwest::Response {
+  reqwest::Client::new()
+    .post(format!("{url}{path}"))
+    .body(body.to_string())
+    .send()
+    .await
+    .expect("request failed")
+}
+
+async fn json_body(response: reqwest::Response) -> Value {
+  serde_json::from_str(&response.text().await.expect("body could not be read"))
+    .expect("body is not valid json")
+}
+
+async fn list_instances(url: &str) -> Vec<Value> {
+  let response = reqwest::get(format!("{url}/api/servers"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+
+  json_body(response).await["instances"]
+    .as_array()
+    .expect("instances are not a list")
+    .clone()
+}
+
+#[tokio::test]
+async fn registered_remote_server_is_listed_as_not_local() {
+  let url = start_serve_dir_server("register", Config::default());
+
+  let response = post_json(
+    &url,
+    "/api/servers/register",
+    serde_json::json!({ "name": "remote", "address": "192.168.0.2:3001" }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::OK);
+  let uuid = json_body(response).await["uuid"].clone();
+
+  let instances = list_instances(&url).await;
+  assert_eq!(instances.len(), 1);
+  assert_eq!(instances[0]["uuid"], uuid);
+  assert_eq!(instances[0]["name"], "remote");
+  assert_eq!(instances[0]["address"], "192.168.0.2:3001");
+  assert_eq!(instances[0]["local"], false);
+  assert_eq!(instances[0]["pid"], Value::Null);
+}
+
+#[tokio::test]
+async fn rejects_invalid_remote_registrations() {
+  let url = start_serve_dir_server("invalid", Config::default());
+  let register = |name: &str, address: &str| {
+    post_json(
+      &url,
+      "/api/servers/register",
+      serde_json::json!({ "name": name, "address": address }),
+    )
+  };
+
+  assert_eq!(
+    register("remote", "192.168.0.2:3001").await.status(),
+    StatusCode::OK
+  );
+  assert_eq!(
+    register("remote", "192.168.0.3:3001").await.status(),
+    StatusCode::CONFLICT
+  );
+  for address in ["", "192.168.0.3", ":3001", "192.168.0.3:port"] {
+    assert_eq!(
+      register("other", address).await.status(),
+      StatusCode::BAD_REQUEST,
+      "{address}"
+    );
+  }
+  assert_eq!(list_instances(&url).await.len(), 1);
+}
+
+#[tokio::test]
+async fn remote_server_is_unregistered_instead_of_stopped() {
+  let url = start_serve_dir_server("unregister", Config::default());
+  let response = post_json(
+    &url,
+    "/api/servers/register",
+    serde_json::json!({ "name": "remote", "address": "192.168.0.2:3001" }),
+  )
+  .await;
+  let uuid = json_body(response).await["uuid"].clone();
+
+  let response = post_json(
+    &url,
+    "/api/servers/stop",
+    serde_json::json!({ "uuid": uuid }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  assert_eq!(list_instances(&url).await.len(), 1);
+
+  let response = post_json(
+    &url,
+    "/api/servers/unregister",
+    serde_json::json!({ "uuid": uuid }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::OK);
+  assert!(list_instances(&url).await.is_empty());
+
+  let response = post_json(
+    &url,
+    "/api/servers/unregister",
+    serde_json::json!({ "uuid": uuid }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn spawns_lists_and_stops_local_server() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-spawn-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+  let args_file = root.join("stub-args");
+
+  let response = post_json(
+    &url,
+    "/api/servers/spawn",
+    serde_json::json!({
+      "name": "local",
+      "dir": [root.join("dist")],
+      "env": { "STUB_ARGS_FILE": args_file },
+    }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::OK);
+  let spawned = json_body(response).await;
+  let address = spawned["address"].as_str().expect("address is missing");
+  let stub_args = read_stub_args(&args_file).await;
+  assert!(
+    stub_args.contains(&format!("--addr {address}")),
+    "{stub_args}"
+  );
+
+  let instances = list_instances(&url).await;
+  assert_eq!(instances.len(), 1);
+  assert_eq!(instances[0]["uuid"], spawned["uuid"]);
+  assert_eq!(instances[0]["name"], "local");
+  assert_eq!(instances[0]["local"], true);
+  assert!(instances[0]["pid"].is_u64());
+
+  let stop = serde_json::json!({ "uuid": spawned["uuid"] });
+  let response = post_json(&url, "/api/servers/stop", stop.clone()).await;
+  assert_eq!(response.status(), StatusCode::OK);
+  assert!(list_instances(&url).await.is_empty());
+
+  let response = post_json(&url, "/api/servers/stop", stop).await;
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn stopped_server_logs_archive_is_fetched_by_name() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-archive-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+  let args_file = root.join("stub-args");
+
+  let response = post_json(
+    &url,
+    "/api/servers/spawn",
+    serde_json::json!({
+      "name": "archived",
+      "dir": [root.join("dist")],
+      "env": { "STUB_ARGS_FILE": args_file },
+    }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::OK);
+  let uuid = json_body(response).await["uuid"].clone();
+  read_stub_args(&args_file).await;
+
+  let response = reqwest::get(format!("{url}/api/servers/logs/archive?name=archived"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+  let response = post_json(
+    &url,
+    "/api/servers/stop",
+    serde_json::json!({ "uuid": uuid }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let response = reqwest::get(format!("{url}/api/servers/logs/archive?name=archived"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.headers()["content-type"], "application/gzip");
+  let disposition = response.headers()["content-disposition"].to_str().unwrap();
+  assert!(disposition.starts_with("attachment"), "{disposition}");
+  let archive = response.bytes().await.expect("body could not be read");
+  assert_eq!(&archive[..2], &[0x1f, 0x8b]);
+
+  let response = reqwest::get(format!("{url}/api/servers/logs/archive?name=unknown"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+async fn recent_lines(url: &str, query: &str) -> Vec<Value> {
+  for _ in 0..100 {
+    let response = reqwest::get(format!("{url}/api/servers/logs/tail?{query}"))
+      .await
+      .expect("request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let lines = json_body(response).await["lines"]
+      .as_array()
+      .expect("lines are not a list")
+      .clone();
+    if lines.len() == 2 {
+      return lines;
+    }
+    sleep(Duration::from_millis(50)).await;
+  }
+  panic!("stub api server output was not captured");
+}
+
+#[tokio::test]
+async fn recent_logs_are_tailed_by_name() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-tail-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+  let args_file = root.join("stub-args");
+
+  let response = post_json(
+    &url,
+    "/api/servers/spawn",
+    serde_json::json!({
+      "name": "tailed",
+      "dir": [root.join("dist")],
+      "env": { "STUB_ARGS_FILE": args_file },
+    }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::OK);
+  let address = json_body(response).await["address"].clone();
+
+  let lines = recent_lines(&url, "name=tailed").await;
+  let stdout = lines
+    .iter()
+    .find(|line| line["stream"] == "stdout")
+    .expect("stdout line is missing");
+  assert_eq!(
+    stdout["line"],
+    format!("listening on {}", address.as_str().unwrap())
+  );
+  assert!(
+    lines
+      .iter()
+      .any(|line| line["stream"] == "stderr" && line["line"] == "stub warning")
+  );
+
+  let response = reqwest::get(format!("{url}/api/servers/logs/tail?name=tailed&lines=1"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(
+    json_body(response).await["lines"].as_array().unwrap().len(),
+    1
+  );
+
+  let response = reqwest::get(format!("{url}/api/servers/logs/tail?name=unknown"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+async fn get_logs(url: &str, query: &str) -> String {
+  let response = reqwest::get(format!("{url}/api/servers/logs?{query}"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK, "{query}");
+  assert_eq!(response.headers()["content-type"], "text/plain");
+  response.text().await.unwrap()
+}
+
+#[tokio::test]
+async fn running_server_logs_are_fetched_by_stream() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-logs-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+  let args_file = root.join("stub-args");
+
+  let response = post_json(
+    &url,
+    "/api/servers/spawn",
+    serde_json::json!({
+      "name": "logged",
+      "dir": [root.join("dist")],
+      "env": { "STUB_ARGS_FILE": args_file },
+    }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = json_body(response).await;
+  let listening = format!("listening on {}", body["address"].as_str().unwrap());
+  let uuid = body["uuid"].as_str().unwrap().to_owned();
+
+  // output is written to the log files asynchronously
+  let mut combined = String::new();
+  for _ in 0..100 {
+    combined = get_logs(&url, "name=logged").await;
+    if combined.contains(&listening) && combined.contains("stub warning") {
+      break;
+    }
+    sleep(Duration::from_millis(50)).await;
+  }
+  assert!(combined.contains(&listening), "{combined}");
+  assert!(combined.contains("stub warning"), "{combined}");
+
+  let stdout = get_logs(&url, &format!("uuid={uuid}&stream=stdout")).await;
+  assert_eq!(stdout, format!("{listening}\n"));
+  let stderr = get_logs(&url, "name=logged&stream=stderr").await;
+  assert_eq!(stderr, "stub warning\n");
+  let tail = get_logs(&url, "name=logged&stream=both&tail=1").await;
+  assert_eq!(tail, format!("{listening}\nstub warning\n"));
+
+  let response = reqwest::get(format!("{url}/api/servers/logs?name=unknown"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn logs_query_is_deserialized_before_fetching_logs() {
+  let root =
+    std::env::temp_dir().join(format!("mwc-api-servers-logs-query-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+  let args_file = root.join("stub-args");
+  let response = post_json(
+    &url,
+    "/api/servers/spawn",
+    serde_json::json!({
+      "name": "queried",
+      "dir": [root.join("dist")],
+      "env": { "STUB_ARGS_FILE": args_file },
+    }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::OK);
+  read_stub_args(&args_file).await;
+
+  get_logs(&url, "name=queried&stream=stderr&tail=5").await;
+  for query in [
+    "name=queried&stream=stdin",
+    "name=queried&tail=-1",
+    "uuid=not-an-uuid",
+  ] {
+    let response = reqwest::get(format!("{url}/api/servers/logs?{query}"))
+      .await
+      .expect("request failed");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{query}");
+    let err_msg = json_body(response).await["err_msg"].clone();
+    assert!(
+      err_msg
+        .as_str()
+        .unwrap()
+        .contains("incorrect request query provided"),
+      "{query}: {err_msg}"
+    );
+  }
+  let response = reqwest::get(format!("{url}/api/servers/logs"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn spawn_without_binary_on_path_is_reported() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-no-binary-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let serve_dir = root.join("dist");
+  let empty_bin_dir = root.join("empty-bin");
+  std::fs::create_dir_all(&serve_dir).expect("fixture dir could not be created");
+  std::fs::create_dir_all(&empty_bin_dir).expect("fixture dir could not be created");
+  std::fs::write(serve_dir.join("index.html"), "<html>fixture</html>")
+    .expect("fixture could not be written");
+  let (_client, url) = spawn_client_with_path(&root, empty_bin_dir.as_os_str(), &[]).await;
+
+  let response = post_json(
+    &url,
+    "/api/servers/spawn",
+    serde_json::json!({ "name": "missing", "dir": [serve_dir] }),
+  )
+  .await;
+
+  assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+  assert_eq!(
+    json_body(response).await["err_msg"],
+    "could not spawn a new api instance: mpv-web-api binary not found on PATH; install it or add its location to PATH"
+  );
+  assert!(list_instances(&url).await.is_empty());
+}
+
+#[tokio::test]
+async fn spawn_recreates_removed_logs_dir() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-no-logs-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+  let logs_dir = root.join("data").join("logs");
+  std::fs::remove_dir_all(&logs_dir).expect("logs dir could not be removed");
+  let args_file = root.join("stub-args");
+
+  let response = post_json(
+    &url,
+    "/api/servers/spawn",
+    serde_json::json!({
+      "name": "relogged",
+      "dir": [root.join("dist")],
+      "env": { "STUB_ARGS_FILE": args_file },
+    }),
+  )
+  .await;
+
+  assert_eq!(response.status(), StatusCode::OK);
+  read_stub_args(&args_file).await;
+  assert_eq!(files_in(&logs_dir, "_stdout").len(), 1);
+  assert_eq!(files_in(&logs_dir, "_stderr").len(), 1);
+}
+
+#[tokio::test]
+async fn duplicate_spawn_name_is_a_conflict() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-duplicate-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+  let spawn = serde_json::json!({
+    "name": "duplicate",
+    "dir": [root.join("dist")],
+    "env": { "STUB_ARGS_FILE": root.join("stub-args") },
+  });
+
+  let response = post_json(&url, "/api/servers/spawn", spawn.clone()).await;
+  assert_eq!(response.status(), StatusCode::OK);
+  let first = json_body(response).await;
+
+  let response = post_json(&url, "/api/servers/spawn", spawn).await;
+  assert_eq!(response.status(), StatusCode::CONFLICT);
+  let err_msg = json_body(response).await["err_msg"].to_string();
+  assert!(err_msg.contains("already exists"), "{err_msg}");
+
+  let instances = list_instances(&url).await;
+  assert_eq!(instances.len(), 1);
+  assert_eq!(instances[0]["uuid"], first["uuid"]);
+  assert!(instances[0]["pid"].is_u64());
+}
+
+fn free_port() -> u16 {
+  TcpListener::bind("127.0.0.1:0")
+    .and_then(|listener| listener.local_addr())
+    .expect("free port could not be found")
+    .port()
+}
+
+fn saved_server(name: &str, port: u16, root: &Path) -> Value {
+  serde_json::json!({
+    "name": name,
+    "restart": false,
+    "server_args": {
+      "port": port,
+      "dir": [root.join("dist")],
+      "watch_dir": false,
+      "env": { "STUB_ARGS_FILE": root.join(format!("{name}-args")) },
+      "clear_env": false,
+      "extra_args": [],
+    },
+  })
+}
+
+#[tokio::test]
+async fn saved_server_running_outside_the_client_is_not_tracked() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-respawn-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let saved_path = root.join("data").join("api_servers.json");
+  std::fs::create_dir_all(root.join("data")).expect("fixture dir could not be created");
+  let (free, busy) = (free_port(), TcpListener::bind("127.0.0.1:0").unwrap());
+  let saved = serde_json::json!([
+    saved_server("free", free, &root),
+    saved_server("busy", busy.local_addr().unwrap().port(), &root),
+  ]);
+  std::fs::write(&saved_path, saved.to_string()).expect("saved servers could not be written");
+  let (_client, url) = spawn_client_with_stub(&root).await;
+
+  let response = post_json(&url, "/api/servers/respawn-saved", Value::Null).await;
+  assert_eq!(response.status(), StatusCode::OK);
+  let outcomes = json_body(response).await;
+  assert_eq!(outcomes["respawned"].as_array().unwrap().len(), 1);
+  assert_eq!(outcomes["respawned"][0]["name"], "free");
+  assert_eq!(outcomes["failed"].as_array().unwrap().len(), 1);
+  assert_eq!(outcomes["failed"][0]["name"], "busy");
+  let err_msg = outcomes["failed"][0]["err_msg"].as_str().unwrap();
+  assert!(err_msg.contains("already running"), "{err_msg}");
+
+  let stub_args = read_stub_args(&root.join("free-args")).await;
+  assert!(
+    stub_args.contains(&format!("--addr 127.0.0.1:{free}")),
+    "{stub_args}"
+  );
+  let instances = list_instances(&url).await;
+  assert_eq!(instances.len(), 1);
+  assert_eq!(instances[0]["name"], "free");
+  assert_eq!(instances[0]["local"], true);
+
+  let saved: Value = serde_json::from_str(&std::fs::read_to_string(&saved_path).unwrap()).unwrap();
+  let saved_names: Vec<&str> = saved
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|server| server["name"].as_str().unwrap())
+    .collect();
+  assert!(saved_names.contains(&"busy"), "{saved_names:?}");
+  assert!(saved_names.contains(&"free"), "{saved_names:?}");
+
+  let response = post_json(&url, "/api/servers/stop-all", Value::Null).await;
+  assert_eq!(response.status(), StatusCode::OK);
+}
+
+fn count_starts(args_file: &Path) -> usize {
+  std::fs::read_to_string(args_file)
+    .map(|args| args.lines().count())
+    .unwrap_or_default()
+}
+
+#[tokio::test]
+async fn only_unexpected_exits_are_restarted() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-restart-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+  let spawn_exiting = |name: &str, exit_code: &str| {
+    post_json(
+      &url,
+      "/api/servers/spawn",
+      serde_json::json!({
+        "name": name,
+        "dir": [root.join("dist")],
+        "restart": true,
+        "env": {
+          "STUB_ARGS_FILE": root.join(format!("{name}-args")),
+          "STUB_EXIT_CODE": exit_code,
+        },
+      }),
+    )
+  };
+
+  assert_eq!(spawn_exiting("clean", "0").await.status(), StatusCode::OK);
+  assert_eq!(
+    spawn_exiting("crashing", "1").await.status(),
+    StatusCode::OK
+  );
+
+  let crashing_args = root.join("crashing-args");
+  for _ in 0..100 {
+    if count_starts(&crashing_args) >= 2 {
+      break;
+    }
+    sleep(Duration::from_millis(50)).await;
+  }
+  assert!(
+    count_starts(&crashing_args) >= 2,
+    "crashed server was not restarted"
+  );
+
+  // a restart would be due a second after the exit was noticed
+  sleep(Duration::from_millis(1500)).await;
+  assert_eq!(count_starts(&root.join("clean-args")), 1);
+}
+
+const MAX_BODY_SIZE: usize = 64;
+
+// written by hand, since the client would not send a declared length that differs from the body
+fn raw_post_status(url: &str, path: &str, headers: &str, body: &str) -> String {
+  let mut stream =
+    std::net::TcpStream::connect(url.trim_start_matches("http://")).expect("could not connect");
+  write!(
+    stream,
+    "POST {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{headers}\r\n{body}"
+  )
+  .expect("request could not be written");
+
+  let mut response = String::new();
+  let _ = stream.read_to_string(&mut response);
+  response.lines().next().unwrap_or_default().to_owned()
+}
+
+#[tokio::test]
+async fn rejects_request_bodies_over_the_limit() {
+  let url = start_serve_dir_server(
+    "body-limit",
+    Config {
+      max_request_body_size: MAX_BODY_SIZE,
+      ..Config::default()
+    },
+  );
+  let register = serde_json::json!({ "name": "remote", "address": "192.168.0.2:3001" });
+  assert!(register.to_string().len() <= MAX_BODY_SIZE);
+  assert_eq!(
+    post_json(&url, "/api/servers/register", register)
+      .await
+      .status(),
+    StatusCode::OK
+  );
+
+  let declared_too_large = raw_post_status(
+    &url,
+    "/api/servers/register",
+    &format!("Content-Length: {}\r\n", MAX_BODY_SIZE * 1024),
+    "{}",
+  );
+  assert_eq!(declared_too_large, "HTTP/1.1 400 Bad Request");
+
+  let chunk = format!(r#"{{"name": "{}"}}"#, "x".repeat(MAX_BODY_SIZE));
+  let streamed_too_large = raw_post_status(
+    &url,
+    "/api/servers/register",
+    "Transfer-Encoding: chunked\r\n",
+    &format!("{:x}\r\n{chunk}\r\n0\r\n\r\n", chunk.len()),
+  );
+  assert_eq!(streamed_too_large, "HTTP/1.1 400 Bad Request");
+  assert_eq!(list_instances(&url).await.len(), 1);
+}
+
+#[tokio::test]
+async fn restart_keeps_name_and_address_with_a_fresh_process() {
+  let root = std::env::temp_dir().join(format!(
+    "mwc-api-servers-restart-name-{}",
+    std::process::id()
+  ));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+  let args_file = root.join("stub-args");
+
+  let response = post_json(
+    &url,
+    "/api/servers/spawn",
+    serde_json::json!({
+      "name": "restarted",
+      "dir": [root.join("dist")],
+      "env": { "STUB_ARGS_FILE": args_file },
+    }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::OK);
+  read_stub_args(&args_file).await;
+  let before = list_instances(&url).await.remove(0);
+
+  let restart = serde_json::json!({ "name": "restarted" });
+  let response = post_json(&url, "/api/servers/restart", restart).await;
+  assert_eq!(response.status(), StatusCode::OK);
+  let restarted = json_body(response).await;
+  assert_eq!(restarted["name"], "restarted");
+  assert_eq!(restarted["address"], before["address"]);
+
+  let instances = list_instances(&url).await;
+  assert_eq!(instances.len(), 1);
+  assert_eq!(instances[0]["uuid"], restarted["uuid"]);
+  assert_eq!(instances[0]["address"], before["address"]);
+  assert!(instances[0]["pid"].is_u64());
+  assert_ne!(instances[0]["pid"], before["pid"]);
+
+  let response = post_json(
+    &url,
+    "/api/servers/restart",
+    serde_json::json!({ "name": "unknown" }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+fn files_in(dir: &Path, suffix: &str) -> Vec<String> {
+  let mut names: Vec<String> = std::fs::read_dir(dir)
+    .expect("dir could not be read")
+    .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+    .filter(|name| name.ends_with(suffix))
+    .collect();
+  names.sort();
+  names
+}
+
+#[tokio::test]
+async fn respawned_name_does_not_clobber_previous_logs() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-rerun-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+  let logs_dir = root.join("data").join("logs");
+  let args_file = root.join("stub-args");
+  let spawn = serde_json::json!({
+    "name": "rerun",
+    "dir": [root.join("dist")],
+    "env": { "STUB_ARGS_FILE": args_file },
+  });
+
+  let mut stdout_logs: Vec<String> = Vec::new();
+  for run in 1..=2 {
+    let _ = std::fs::remove_file(&args_file);
+    let response = post_json(&url, "/api/servers/spawn", spawn.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let uuid = json_body(response).await["uuid"].clone();
+    read_stub_args(&args_file).await;
+    recent_lines(&url, "name=rerun").await;
+
+    let current = files_in(&logs_dir, "_stdout");
+    assert_eq!(current.len(), 1, "{current:?}");
+    stdout_logs.extend(current);
+
+    let response = post_json(
+      &url,
+      "/api/servers/stop",
+      serde_json::json!({ "uuid": uuid }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(files_in(&logs_dir, "_logs_archive.tar.gz").len(), run);
+  }
+
+  assert_ne!(stdout_logs[0], stdout_logs[1]);
+}
+
+// a reaped or zombie process is no longer running
+fn process_is_running(pid: u64) -> bool {
+  std::fs::read_to_string(format!("/proc/{pid}/stat")).is_ok_and(|stat| {
+    !stat
+      .rsplit(')')
+      .next()
+      .unwrap_or_default()
+      .starts_with(" Z")
+  })
+}
+
+#[tokio::test]
+async fn stop_all_terminates_every_server_and_archives_logs() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-stop-all-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+  let names = ["first", "second", "third"];
+  for name in names {
+    let args_file = root.join(format!("{name}-args"));
+    let response = post_json(
+      &url,
+      "/api/servers/spawn",
+      serde_json::json!({
+        "name": name,
+        "dir": [root.join("dist")],
+        "env": { "STUB_ARGS_FILE": args_file },
+      }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    read_stub_args(&args_file).await;
+  }
+  let pids: Vec<u64> = list_instances(&url)
+    .await
+    .iter()
+    .map(|instance| instance["pid"].as_u64().expect("pid is missing"))
+    .collect();
+  assert_eq!(pids.len(), names.len());
+  assert!(pids.iter().all(|pid| process_is_running(*pid)));
+
+  let response = post_json(&url, "/api/servers/stop-all", Value::Null).await;
+  assert_eq!(response.status(), StatusCode::OK);
+
+  assert!(list_instances(&url).await.is_empty());
+  for pid in pids {
+    assert!(!process_is_running(pid), "process {pid} is still running");
+  }
+  for name in names {
+    let response = reqwest::get(format!("{url}/api/servers/logs/archive?name={name}"))
+      .await
+      .expect("request failed");
+    assert_eq!(response.status(), StatusCode::OK, "{name}");
+  }
+}
+
+#[tokio::test]
+async fn shutdown_stops_spawned_servers() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-shutdown-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (mut client, url) = spawn_client_with_stub(&root).await;
+  let args_file = root.join("stub-args");
+
+  let response = post_json(
+    &url,
+    "/api/servers/spawn",
+    serde_json::json!({
+      "name": "orphan",
+      "dir": [root.join("dist")],
+      "env": { "STUB_ARGS_FILE": args_file },
+    }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::OK);
+  read_stub_args(&args_file).await;
+  let pid = list_instances(&url).await[0]["pid"]
+    .as_u64()
+    .expect("pid is missing");
+  assert!(process_is_running(pid));
+
+  nix::sys::signal::kill(
+    nix::unistd::Pid::from_raw(client.0.id() as i32),
+    nix::sys::signal::Signal::SIGTERM,
+  )
+  .expect("SIGTERM could not be sent");
+  for _ in 0..100 {
+    if client
+      .0
+      .try_wait()
+      .expect("status could not be read")
+      .is_some()
+    {
+      break;
+    }
+    sleep(Duration::from_millis(50)).await;
+  }
+
+  assert!(
+    client
+      .0
+      .try_wait()
+      .expect("status could not be read")
+      .is_some(),
+    "client did not shut down"
+  );
+  assert!(!process_is_running(pid), "api server outlived the client");
+  assert_eq!(
+    files_in(&root.join("data").join("logs"), "_logs_archive.tar.gz").len(),
+    1
+  );
+}
+
+#[tokio::test]
+async fn server_ignoring_sigterm_is_killed_after_grace_period() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-sigkill-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) =
+    spawn_client_with_stub_args(&root, &["--api-server-stop-grace-period", "1"]).await;
+  let args_file = root.join("stub-args");
+
+  let response = post_json(
+    &url,
+    "/api/servers/spawn",
+    serde_json::json!({
+      "name": "stubborn",
+      "dir": [root.join("dist")],
+      "env": { "STUB_ARGS_FILE": args_file, "STUB_IGNORE_TERM": "1" },
+    }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::OK);
+  let uuid = json_body(response).await["uuid"].clone();
+  read_stub_args(&args_file).await;
+  let pid = list_instances(&url).await[0]["pid"]
+    .as_u64()
+    .expect("pid is missing");
+
+  let stopping = std::time::Instant::now();
+  let response = post_json(
+    &url,
+    "/api/servers/stop",
+    serde_json::json!({ "uuid": uuid }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::OK);
+  assert!(stopping.elapsed() >= Duration::from_secs(1));
+  assert!(!process_is_running(pid), "process {pid} is still running");
+  assert!(list_instances(&url).await.is_empty());
+}
+
+async fn spawn_stubborn_server(url: &str, root: &std::path::Path, name: &str) {
+  let args_file = root.join(format!("{name}-args"));
+  let response = post_json(
+    url,
+    "/api/servers/spawn",
+    serde_json::json!({
+      "name": name,
+      "dir": [root.join("dist")],
+      "env": { "STUB_ARGS_FILE": args_file, "STUB_IGNORE_TERM": "1" },
+    }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::OK);
+  read_stub_args(&args_file).await;
+}
+
+#[tokio::test]
+async fn routes_are_served_while_a_server_is_stopping() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-stopping-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) =
+    spawn_client_with_stub_args(&root, &["--api-server-stop-grace-period", "3"]).await;
+  spawn_stubborn_server(&url, &root, "stubborn").await;
+  let uuid = list_instances(&url).await[0]["uuid"].clone();
+
+  let stop_url = url.clone();
+  let stopping = tokio::spawn(async move {
+    post_json(
+      &stop_url,
+      "/api/servers/stop",
+      serde_json::json!({ "uuid": uuid }),
+    )
+    .await
+    .status()
+  });
+  tokio::time::sleep(Duration::from_millis(500)).await;
+
+  let listing = std::time::Instant::now();
+  assert!(list_instances(&url).await.is_empty());
+  assert!(listing.elapsed() < Duration::from_secs(1));
+  assert_eq!(stopping.await.unwrap(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn stop_all_waits_for_servers_concurrently() {
+  let root = std::env::temp_dir().join(format!(
+    "mwc-api-servers-stop-all-grace-{}",
+    std::process::id()
+  ));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) =
+    spawn_client_with_stub_args(&root, &["--api-server-stop-grace-period", "2"]).await;
+  for name in ["first", "second", "third"] {
+    spawn_stubborn_server(&url, &root, name).await;
+  }
+
+  let stopping = std::time::Instant::now();
+  let response = post_json(&url, "/api/servers/stop-all", Value::Null).await;
+  assert_eq!(response.status(), StatusCode::OK);
+  assert!(stopping.elapsed() < Duration::from_secs(4));
+  assert!(list_instances(&url).await.is_empty());
+}
+
+#[tokio::test]
+async fn stopping_an_exited_server_succeeds() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-exited-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+  // a restartable server exiting cleanly is reaped by the supervisor, the other one is left unreaped
+  for (name, restart, exit_code) in [("reaped", true, "0"), ("unreaped", false, "3")] {
+    let args_file = root.join(format!("{name}-args"));
+    let response = post_json(
+      &url,
+      "/api/servers/spawn",
+      serde_json::json!({
+        "name": name,
+        "dir": [root.join("dist")],
+        "restart": restart,
+        "env": { "STUB_ARGS_FILE": args_file, "STUB_EXIT_CODE": exit_code },
+      }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    read_stub_args(&args_file).await;
+  }
+
+  let mut instances = Vec::new();
+  for _ in 0..100 {
+    instances = list_instances(&url).await;
+    let exited = instances.iter().all(|instance| {
+      instance["pid"]
+        .as_u64()
+        .is_none_or(|pid| !process_is_running(pid))
+    });
+    if exited && instances.iter().any(|instance| instance["pid"].is_null()) {
+      break;
+    }
+    sleep(Duration::from_millis(50)).await;
+  }
+  assert_eq!(instances.len(), 2);
+  assert!(
+    instances.iter().any(|instance| instance["pid"].is_null()),
+    "clean exit was not noticed"
+  );
+
+  for instance in instances {
+    let response = post_json(
+      &url,
+      "/api/servers/stop",
+      serde_json::json!({ "uuid": instance["uuid"] }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK, "{}", instance["name"]);
+  }
+  assert!(list_instances(&url).await.is_empty());
+}
+
+#[tokio::test]
+async fn servers_spawned_without_port_get_distinct_addresses() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-ports-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+
+  let mut addresses: Vec<String> = Vec::new();
+  for name in ["first", "second"] {
+    let args_file = root.join(format!("{name}-args"));
+    let response = post_json(
+      &url,
+      "/api/servers/spawn",
+      serde_json::json!({
+        "name": name,
+        "dir": [root.join("dist")],
+        "env": { "STUB_ARGS_FILE": args_file },
+      }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let address = json_body(response).await["address"]
+      .as_str()
+      .expect("address is missing")
+      .to_owned();
+    let stub_args = read_stub_args(&args_file).await;
+    assert!(
+      stub_args.contains(&format!("--addr {address}")),
+      "{stub_args}"
+    );
+    addresses.push(address);
+  }
+
+  assert_ne!(addresses[0], addresses[1]);
+  // the stub does not listen, so a usable address is still free to bind
+  for address in &addresses {
+    TcpListener::bind(address).unwrap_or_else(|err| panic!("{address} is not usable: {err}"));
+  }
+}
+
+#[tokio::test]
+async fn spawn_with_missing_dir_is_rejected_before_starting() {
+  let root = std::env::temp_dir().join(format!(
+    "mwc-api-servers-missing-dir-{}",
+    std::process::id()
+  ));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) = spawn_client_with_stub(&root).await;
+  let args_file = root.join("stub-args");
+  let missing = root.join("missing");
+  let not_a_dir = root.join("dist").join("index.html");
+
+  let response = post_json(
+    &url,
+    "/api/servers/spawn",
+    serde_json::json!({
+      "name": "misconfigured",
+      "dir": [root.join("dist"), missing, not_a_dir],
+      "env": { "STUB_ARGS_FILE": args_file },
+    }),
+  )
+  .await;
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  let err_msg = json_body(response).await["err_msg"]
+    .as_str()
+    .expect("err_msg is missing")
+    .to_owned();
+  assert!(
+    err_msg.contains(&missing.to_string_lossy().into_owned()),
+    "{err_msg}"
+  );
+  assert!(
+    err_msg.contains(&not_a_dir.to_string_lossy().into_owned()),
+    "{err_msg}"
+  );
+  let valid = format!("\"{}\"", root.join("dist").to_string_lossy());
+  assert!(!err_msg.contains(&valid), "{err_msg}");
+
+  sleep(Duration::from_millis(200)).await;
+  assert!(!args_file.exists(), "stub api server was started");
+  assert!(list_instances(&url).await.is_empty());
+}
+
+#[tokio::test]
+async fn server_that_never_binds_is_stopped_without_blocking_other_routes() {
+  let root = std::env::temp_dir().join(format!("mwc-api-servers-not-ready-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let (_client, url) =
+    spawn_client_with_stub_args(&root, &["--api-server-ready-timeout", "2"]).await;
+  let args_file = root.join("stub-args");
+
+  let spawn = serde_json::json!({
+    "name": "unready",
+    "dir": [root.join("dist")],
+    "wait_ready": true,
+    "env": { "STUB_ARGS_FILE": args_file },
+  });
+  let spawn_url = url.clone();
+  let spawning =
+    tokio::spawn(async move { post_json(&spawn_url, "/api/servers/spawn", spawn).await });
+  read_stub_args(&args_file).await;
+
+  let listing = std::time::Instant::now();
+  let instances = list_instances(&url).await;
+  assert!(
+    listing.elapsed() < Duration::from_secs(1),
+    "listing waited for the spawn"
+  );
+  assert_eq!(instances.len(), 1);
+  let pid = instances[0]["pid"].as_u64().expect("pid is missing");
+
+  let response = spawning.await.expect("spawn request panicked");
+  assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+  let err_msg = json_body(response).await["err_msg"].to_string();
+  assert!(err_msg.contains("did not become reachable"), "{err_msg}");
+  assert!(list_instances(&url).await.is_empty());
+  assert!(!process_is_running(pid), "process {pid} is still running");
+  let saved =
+    std::fs::read_to_string(root.join("data").join("api_servers.json")).unwrap_or_default();
+  assert!(!saved.contains("unready"), "{saved}");
+}
@@ -0,0 +1,63 @@
+// every test binary includes this module, but not all of them use each helper
+#![allow(dead_code)]
+
+use std::{net::TcpListener, thread, time::Duration};
+
+use mpv_web_client::{Config, run};
+
+pub const INDEX_HTML: &str = "<html>fixture</html>";
+
+// serves a fresh directory holding only INDEX_HTML, with data kept next to it
+pub fn start_serve_dir_server(name: &str, config: Config) -> String {
+  let root = std::env::temp_dir().join(format!("mwc-serve-dir-{name}-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let serve_dir = root.join("dist");
+  std::fs::create_dir_all(&serve_dir).expect("fixture dir could not be created");
+  std::fs::write(serve_dir.join("index.html"), INDEX_HTML).expect("fixture could not be written");
+
+  start_server(Config {
+    serve_dir: Some(serve_dir),
+    data_dir: Some(root.join("data")),
+    ..config
+  })
+}
+
+// runs the server on its own runtime for the rest of the test process and returns its url
+pub fn start_server(config: Config) -> String {
+  start_online_server(Config {
+    offline: true,
+    ..config
+  })
+}
+
+// same as start_server, but remote releases are checked - tests point them at a stub
+pub fn start_online_server(config: Config) -> String {
+  let port = TcpListener::bind("127.0.0.1:0")
+    .and_then(|listener| listener.local_addr())
+    .expect("free port could not be found")
+    .port();
+  let config = Config {
+    port: Some(port),
+    ..config
+  };
+  thread::spawn(move || {
+    tokio::runtime::Runtime::new()
+      .expect("runtime could not be created")
+      .block_on(run(config))
+      .expect("server failed");
+  });
+
+  wait_for_server(port);
+  format!("http://127.0.0.1:{port}")
+}
+
+fn wait_for_server(port: u16) {
+  for _ in 0..100 {
+    if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+      return;
+    }
+    thread::sleep(Duration::from_millis(50));
+  }
+
+  panic!("server did not start listening on port {port}");
+}
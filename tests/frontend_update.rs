@@ -0,0 +1,634 @@
+use std::{
+  net::TcpListener,
+  path::{Path, PathBuf},
+  process::{Child, Command},
+  sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+  thread,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{Compression, write::GzEncoder};
+use http_body_util::Full;
+use hyper::{
+  Request, Response,
+  body::{Bytes, Incoming},
+  server::conn::http1,
+  service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use mpv_web_client::Config;
+use reqwest::{StatusCode, header::RETRY_AFTER};
+use tokio::time::timeout;
+
+use crate::common::start_online_server;
+
+mod common;
+
+const RATE_LIMIT_RESET_IN_SECS: u64 = 120;
+
+const SLOW_PACKAGE_DELAY: Duration = Duration::from_secs(3);
+
+static RATE_LIMITED_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+static SLOW_PACKAGE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+type StubHandler = fn(&Request<Incoming>, &str) -> Response<Full<Bytes>>;
+
+// serves the releases api on its own runtime - handlers receive the stub url for linking assets
+fn spawn_releases_api(handler: StubHandler) -> String {
+  let listener = TcpListener::bind("127.0.0.1:0").expect("stub could not bind");
+  let url = format!("http://{}", listener.local_addr().unwrap());
+  listener.set_nonblocking(true).unwrap();
+  let stub_url = url.clone();
+  thread::spawn(move || {
+    tokio::runtime::Runtime::new()
+      .expect("runtime could not be created")
+      .block_on(async {
+        let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+        while let Ok((stream, _)) = listener.accept().await {
+          let stub_url = stub_url.clone();
+          let service = service_fn(move |req| {
+            let response = handler(&req, &stub_url);
+            async move { Ok::<_, hyper::Error>(response) }
+          });
+          tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+        }
+      });
+  });
+
+  format!("{url}/releases")
+}
+
+// accepts connections but never responds, like a remote stuck mid-request
+fn spawn_hanging_releases_api() -> String {
+  let listener = TcpListener::bind("127.0.0.1:0").expect("stub could not bind");
+  let url = format!("http://{}/releases", listener.local_addr().unwrap());
+  thread::spawn(move || {
+    let mut connections = Vec::new();
+    for stream in listener.incoming().flatten() {
+      connections.push(stream);
+    }
+  });
+
+  url
+}
+
+// responds to every request the way GitHub does once the rate limit is exhausted
+fn rate_limited(_: &Request<Incoming>, _: &str) -> Response<Full<Bytes>> {
+  RATE_LIMITED_REQUESTS.fetch_add(1, Ordering::SeqCst);
+  let reset = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap()
+    .as_secs()
+    + RATE_LIMIT_RESET_IN_SECS;
+  Response::builder()
+    .status(StatusCode::FORBIDDEN)
+    .header("x-ratelimit-remaining", "0")
+    .header("x-ratelimit-reset", reset.to_string())
+    .body(Full::default())
+    .unwrap()
+}
+
+// publishes release 1.0.0 with a downloadable package
+fn release_1_0_0(req: &Request<Incoming>, stub_url: &str) -> Response<Full<Bytes>> {
+  release_response(req, stub_url, "1.0.0", package_1_0_0())
+}
+
+fn package_1_0_0() -> Vec<u8> {
+  let manifest = "[version_info]\nversion = \"1.0.0\"\ncommit = \"fixture\"\n";
+  package(&[
+    ("pkg_manifest.toml", manifest),
+    ("index.html", "<html>1.0.0</html>"),
+  ])
+}
+
+// publishes release 1.0.0 with a package that takes a while to download
+fn slow_release_1_0_0(req: &Request<Incoming>, stub_url: &str) -> Response<Full<Bytes>> {
+  if req.uri().path().ends_with(".tar.gz") {
+    SLOW_PACKAGE_REQUESTED.store(true, Ordering::SeqCst);
+    thread::sleep(SLOW_PACKAGE_DELAY);
+  }
+  release_1_0_0(req, stub_url)
+}
+
+// publishes release 3.0.0 with a package that lacks its entrypoint
+fn release_3_0_0_without_entrypoint(
+  req: &Request<Incoming>,
+  stub_url: &str,
+) -> Response<Full<Bytes>> {
+  let manifest = "[version_info]\nversion = \"3.0.0\"\ncommit = \"fixture\"\n";
+  let package = package(&[("pkg_manifest.toml", manifest)]);
+  release_response(req, stub_url, "3.0.0", package)
+}
+
+// publishes 4.0.0 declaring an empty package and 4.0.1 whose package download is empty
+fn releases_with_empty_packages(req: &Request<Incoming>, stub_url: &str) -> Response<Full<Bytes>> {
+  let release = |version: &str, size: usize| {
+    format!(
+      r#"{{"tag_name": "{version}", "name": "{version}", "assets": [{{"name": "pkg-{version}.tar.gz", "browser_download_url": "{stub_url}/pkg-{version}.tar.gz", "content_type": "application/gzip", "size": {size}}}]}}"#
+    )
+  };
+  match req.uri().path() {
+    "/releases/tags/4.0.0" => Response::new(Full::from(release("4.0.0", 0))),
+    "/releases/tags/4.0.1" => Response::new(Full::from(release("4.0.1", 1024))),
+    "/pkg-4.0.0.tar.gz" | "/pkg-4.0.1.tar.gz" => Response::new(Full::default()),
+    _ => Response::builder()
+      .status(StatusCode::NOT_FOUND)
+      .body(Full::default())
+      .unwrap(),
+  }
+}
+
+// publishes 1.0.0 as the latest release, for installs done at startup
+fn latest_release_1_0_0(req: &Request<Incoming>, stub_url: &str) -> Response<Full<Bytes>> {
+  let package = package_1_0_0();
+  match req.uri().path() {
+    "/releases/latest" => Response::new(Full::from(release_json(stub_url, "1.0.0", package.len()))),
+    "/pkg-1.0.0.tar.gz" => Response::new(Full::from(package)),
+    _ => not_found(),
+  }
+}
+
+// publishes 5.0.0 as the latest release, declaring more bytes than its package has
+fn latest_release_5_0_0_short(req: &Request<Incoming>, stub_url: &str) -> Response<Full<Bytes>> {
+  let manifest = "[version_info]\nversion = \"5.0.0\"\ncommit = \"fixture\"\n";
+  let package = package(&[
+    ("pkg_manifest.toml", manifest),
+    ("index.html", "<html>5.0.0</html>"),
+  ]);
+  match req.uri().path() {
+    "/releases/latest" => Response::new(Full::from(release_json(
+      stub_url,
+      "5.0.0",
+      package.len() + 1024,
+    ))),
+    "/pkg-5.0.0.tar.gz" => Response::new(Full::from(package)),
+    _ => not_found(),
+  }
+}
+
+fn release_json(stub_url: &str, version: &str, size: usize) -> String {
+  format!(
+    r#"{{"tag_name": "{version}", "name": "{version}", "assets": [{{"name": "pkg-{version}.tar.gz", "browser_download_url": "{stub_url}/pkg-{version}.tar.gz", "content_type": "application/gzip", "size": {size}}}]}}"#
+  )
+}
+
+fn not_found() -> Response<Full<Bytes>> {
+  Response::builder()
+    .status(StatusCode::NOT_FOUND)
+    .body(Full::default())
+    .unwrap()
+}
+
+fn release_response(
+  req: &Request<Incoming>,
+  stub_url: &str,
+  version: &str,
+  package: Vec<u8>,
+) -> Response<Full<Bytes>> {
+  let pkg_path = format!("/pkg-{version}.tar.gz");
+  let path = req.uri().path();
+  if path == format!("/releases/tags/{version}") {
+    let release = format!(
+      r#"{{"tag_name": "{version}", "name": "{version}", "assets": [{{"name": "pkg-{version}.tar.gz", "browser_download_url": "{stub_url}{pkg_path}", "content_type": "application/gzip", "size": {}}}]}}"#,
+      package.len()
+    );
+    Response::new(Full::from(release))
+  } else if path == pkg_path {
+    Response::new(Full::from(package))
+  } else {
+    Response::builder()
+      .status(StatusCode::NOT_FOUND)
+      .body(Full::default())
+      .unwrap()
+  }
+}
+
+fn package(files: &[(&str, &str)]) -> Vec<u8> {
+  let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+  for (name, content) in files {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_cksum();
+    archive
+      .append_data(&mut header, name, content.as_bytes())
+      .expect("fixture file could not be added");
+  }
+
+  archive
+    .into_inner()
+    .and_then(|encoder| encoder.finish())
+    .expect("fixture package could not be written")
+}
+
+struct ClientProcess(Child);
+
+impl Drop for ClientProcess {
+  fn drop(&mut self) {
+    let _ = self.0.kill();
+    let _ = self.0.wait();
+  }
+}
+
+fn fixture_root(name: &str) -> PathBuf {
+  let root = std::env::temp_dir().join(format!("mwc-update-{name}-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  std::fs::create_dir_all(&root).expect("fixture dir could not be created");
+  root
+}
+
+fn start_client(root: &Path, releases_url: &str, config: Config) -> String {
+  start_online_server(Config {
+    data_dir: Some(root.join("data")),
+    releases_url: releases_url.to_owned(),
+    ..config
+  })
+}
+
+// signals are delivered to the whole process, so the client runs as a separate one
+fn spawn_offline_client(root: &Path) -> (ClientProcess, String) {
+  let port = TcpListener::bind("127.0.0.1:0")
+    .and_then(|listener| listener.local_addr())
+    .expect("free port could not be found")
+    .port();
+
+  let child = Command::new(env!("CARGO_BIN_EXE_mpv-web-client"))
+    .arg("--data-dir")
+    .arg(root.join("data"))
+    .args(["--port", &port.to_string(), "--offline"])
+    .spawn()
+    .expect("binary could not be run");
+  let process = ClientProcess(child);
+
+  for _ in 0..100 {
+    if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+      return (process, format!("http://127.0.0.1:{port}"));
+    }
+    thread::sleep(Duration::from_millis(50));
+  }
+  panic!("client did not start listening on port {port}");
+}
+
+fn install_2_0_0(root: &Path) {
+  install_version(root, "2.0.0");
+}
+
+async fn request_update(url: &str, version: &str, query: &str) -> reqwest::Response {
+  reqwest::Client::new()
+    .post(format!("{url}/api/frontend/update{query}"))
+    .body(format!("{{\"version\": \"{version}\"}}"))
+    .send()
+    .await
+    .expect("request failed")
+}
+
+// the subscription is active once the first chunk arrives
+async fn subscribe_progress(url: &str) -> reqwest::Response {
+  let mut events = reqwest::get(format!("{url}/api/frontend/update/progress"))
+    .await
+    .expect("request failed");
+  assert_eq!(events.status(), StatusCode::OK);
+  events.chunk().await.expect("stream failed");
+  events
+}
+
+// returns everything received up to and including the first event of the given stage
+async fn read_progress_until(events: &mut reqwest::Response, stage: &str) -> String {
+  let mut received = String::new();
+  while !received.contains(&format!("\"stage\":\"{stage}\"")) {
+    let chunk = timeout(Duration::from_secs(10), events.chunk())
+      .await
+      .unwrap_or_else(|_| panic!("no {stage} event received in:\n{received}"))
+      .expect("stream failed")
+      .expect("stream ended");
+    received.push_str(&String::from_utf8_lossy(&chunk));
+  }
+  received
+}
+
+#[tokio::test]
+async fn rate_limited_update_starts_cooldown() {
+  let root = fixture_root("cooldown");
+  std::fs::create_dir_all(root.join("dist")).expect("fixture dir could not be created");
+  let url = start_client(
+    &root,
+    &spawn_releases_api(rate_limited),
+    Config {
+      serve_dir: Some(root.join("dist")),
+      ..Config::default()
+    },
+  );
+
+  let response = request_update(&url, "1.0.0", "").await;
+  assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+  let retry_after: u64 = response.headers()[RETRY_AFTER]
+    .to_str()
+    .unwrap()
+    .parse()
+    .expect("retry-after is not in seconds");
+  assert!(
+    (1..=RATE_LIMIT_RESET_IN_SECS).contains(&retry_after),
+    "{retry_after}"
+  );
+  assert_eq!(RATE_LIMITED_REQUESTS.load(Ordering::SeqCst), 1);
+
+  let response = request_update(&url, "1.0.0", "").await;
+  assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+  assert!(response.headers().contains_key(RETRY_AFTER));
+  assert_eq!(
+    RATE_LIMITED_REQUESTS.load(Ordering::SeqCst),
+    1,
+    "update during cooldown must not reach the remote"
+  );
+}
+
+#[tokio::test]
+async fn refused_downgrade_reports_conflicting_versions() {
+  let root = fixture_root("downgrade");
+  install_2_0_0(&root);
+  let url = start_client(&root, &spawn_releases_api(release_1_0_0), Config::default());
+
+  let response = request_update(&url, "1.0.0", "?force=false").await;
+
+  assert_eq!(response.status(), StatusCode::CONFLICT);
+  let body: serde_json::Value =
+    serde_json::from_str(&response.text().await.unwrap()).expect("body is not json");
+  assert_eq!(body["provided_version"], "1.0.0");
+  assert_eq!(body["installed_version"], "2.0.0");
+}
+
+#[tokio::test]
+async fn progress_stream_ends_update_with_done_event() {
+  let root = fixture_root("progress");
+  std::fs::create_dir_all(root.join("dist")).expect("fixture dir could not be created");
+  let url = start_client(
+    &root,
+    &spawn_releases_api(release_1_0_0),
+    Config {
+      serve_dir: Some(root.join("dist")),
+      ..Config::default()
+    },
+  );
+  let mut events = subscribe_progress(&url).await;
+
+  let response = request_update(&url, "1.0.0", "").await;
+  assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+  let received = read_progress_until(&mut events, "done").await;
+  for stage in ["downloading", "extracting", "installing"] {
+    assert!(
+      received.contains(&format!("\"stage\":\"{stage}\"")),
+      "{received}"
+    );
+  }
+  assert!(!received.contains("\"stage\":\"error\""), "{received}");
+  assert_eq!(installed_frontend_version(&url).await, "1.0.0");
+}
+
+async fn installed_frontend_version(url: &str) -> String {
+  let response = reqwest::get(format!("{url}/api/version"))
+    .await
+    .expect("request failed");
+  let body: serde_json::Value =
+    serde_json::from_str(&response.text().await.unwrap()).expect("body is not json");
+  body["frontend"]["version"]
+    .as_str()
+    .expect("no frontend version")
+    .to_owned()
+}
+
+#[tokio::test]
+async fn startup_proceeds_when_remote_hangs() {
+  let root = fixture_root("hanging");
+  install_2_0_0(&root);
+
+  let url = start_client(
+    &root,
+    &spawn_hanging_releases_api(),
+    Config {
+      frontend_init_timeout: 1,
+      ..Config::default()
+    },
+  );
+
+  let response = reqwest::get(format!("{url}/"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.text().await.unwrap(), "<html>2.0.0</html>");
+}
+
+#[tokio::test]
+async fn fetched_package_is_removed_after_install() {
+  let root = fixture_root("fetched-removed");
+
+  let url = start_client(
+    &root,
+    &spawn_releases_api(latest_release_1_0_0),
+    Config::default(),
+  );
+
+  assert_eq!(installed_frontend_version(&url).await, "1.0.0");
+  // the archive is fetched under the release name
+  let fetched_pkg = root.join("data").join("tmp").join("1.0.0");
+  assert!(!fetched_pkg.exists(), "{}", fetched_pkg.display());
+}
+
+#[tokio::test]
+async fn provided_package_is_kept_after_install() {
+  let root = fixture_root("provided-kept");
+  let provided_pkg = root.join("pkg-1.0.0.tar.gz");
+  std::fs::write(&provided_pkg, package_1_0_0()).expect("package could not be written");
+
+  let url = start_client(
+    &root,
+    &spawn_releases_api(latest_release_1_0_0),
+    Config {
+      pkg: Some(provided_pkg.clone()),
+      offline: true,
+      ..Config::default()
+    },
+  );
+
+  assert_eq!(installed_frontend_version(&url).await, "1.0.0");
+  assert!(provided_pkg.is_file());
+}
+
+#[tokio::test]
+async fn failed_update_keeps_previous_version_serving() {
+  let root = fixture_root("fallback");
+  install_2_0_0(&root);
+  let url = start_client(
+    &root,
+    &spawn_releases_api(release_3_0_0_without_entrypoint),
+    Config::default(),
+  );
+
+  let mut events = subscribe_progress(&url).await;
+
+  let response = request_update(&url, "3.0.0", "").await;
+  assert_eq!(response.status(), StatusCode::ACCEPTED);
+  let received = read_progress_until(&mut events, "error").await;
+  assert!(received.contains("entrypoint"), "{received}");
+
+  let response = reqwest::get(format!("{url}/"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.text().await.unwrap(), "<html>2.0.0</html>");
+  assert_eq!(installed_frontend_version(&url).await, "2.0.0");
+  assert!(!root.join("data").join("frontend").join("3.0.0").exists());
+}
+
+#[tokio::test]
+async fn empty_package_is_rejected_before_extraction() {
+  let root = fixture_root("empty");
+  install_2_0_0(&root);
+  let url = start_client(
+    &root,
+    &spawn_releases_api(releases_with_empty_packages),
+    Config::default(),
+  );
+
+  let mut events = subscribe_progress(&url).await;
+
+  for (version, expected_err) in [
+    ("4.0.0", "declares a size of 0 bytes"),
+    ("4.0.1", "downloaded package is empty"),
+  ] {
+    let response = request_update(&url, version, "").await;
+
+    assert_eq!(response.status(), StatusCode::ACCEPTED, "{version}");
+    let received = read_progress_until(&mut events, "error").await;
+    assert!(received.contains(expected_err), "{version}: {received}");
+  }
+}
+
+#[tokio::test]
+async fn frontend_is_served_while_update_downloads() {
+  let root = fixture_root("concurrent");
+  install_2_0_0(&root);
+  let url = start_client(
+    &root,
+    &spawn_releases_api(slow_release_1_0_0),
+    Config::default(),
+  );
+  let mut events = subscribe_progress(&url).await;
+
+  // the update runs in the background, so it is accepted before the download finishes
+  let response = timeout(SLOW_PACKAGE_DELAY / 3, request_update(&url, "1.0.0", ""))
+    .await
+    .expect("update request blocked by the download");
+  assert_eq!(response.status(), StatusCode::ACCEPTED);
+  while !SLOW_PACKAGE_REQUESTED.load(Ordering::SeqCst) {
+    tokio::time::sleep(Duration::from_millis(20)).await;
+  }
+
+  let response = request_update(&url, "1.0.0", "").await;
+  assert_eq!(response.status(), StatusCode::CONFLICT);
+  let response = timeout(SLOW_PACKAGE_DELAY / 3, reqwest::get(format!("{url}/")))
+    .await
+    .expect("serving blocked by the update")
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.text().await.unwrap(), "<html>2.0.0</html>");
+
+  read_progress_until(&mut events, "done").await;
+  assert_eq!(installed_frontend_version(&url).await, "1.0.0");
+}
+
+// the client extracts into a temp dir under its data dir
+fn frontend_temp_dir(root: &Path) -> PathBuf {
+  root.join("data").join("tmp").join("frontend")
+}
+
+#[tokio::test]
+async fn streamed_install_ignores_stale_temp_files() {
+  let root = fixture_root("stream-stale");
+  std::fs::create_dir_all(frontend_temp_dir(&root)).expect("fixture dir could not be created");
+  std::fs::write(frontend_temp_dir(&root).join("stale.js"), "stale")
+    .expect("stale file could not be written");
+
+  let url = start_client(
+    &root,
+    &spawn_releases_api(latest_release_1_0_0),
+    Config {
+      stream_install: true,
+      ..Config::default()
+    },
+  );
+
+  let response = reqwest::get(format!("{url}/"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.text().await.unwrap(), "<html>1.0.0</html>");
+  let installed_dir = root.join("data").join("frontend").join("1.0.0");
+  assert!(installed_dir.join("index.html").exists());
+  assert!(!installed_dir.join("stale.js").exists());
+}
+
+#[tokio::test]
+async fn short_streamed_install_leaves_no_partial_files() {
+  let root = fixture_root("stream-short");
+  install_2_0_0(&root);
+
+  let url = start_client(
+    &root,
+    &spawn_releases_api(latest_release_5_0_0_short),
+    Config {
+      stream_install: true,
+      update: true,
+      ..Config::default()
+    },
+  );
+
+  let response = reqwest::get(format!("{url}/"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.text().await.unwrap(), "<html>2.0.0</html>");
+  assert!(!frontend_temp_dir(&root).exists());
+  assert!(!root.join("data").join("frontend").join("5.0.0").exists());
+}
+
+fn install_version(root: &Path, version: &str) {
+  let installed_dir = root.join("data").join("frontend").join(version);
+  std::fs::create_dir_all(&installed_dir).expect("fixture dir could not be created");
+  std::fs::write(
+    installed_dir.join("index.html"),
+    format!("<html>{version}</html>"),
+  )
+  .expect("entrypoint could not be written");
+  std::fs::write(
+    root.join("data").join("pkg_manifest.toml"),
+    format!("[version_info]\nversion = \"{version}\"\ncommit = \"fixture\"\n"),
+  )
+  .expect("manifest could not be written");
+}
+
+#[tokio::test]
+async fn sighup_reloads_package_installed_on_disk() {
+  let root = fixture_root("sighup");
+  install_2_0_0(&root);
+  let (process, url) = spawn_offline_client(&root);
+  assert_eq!(installed_frontend_version(&url).await, "2.0.0");
+
+  install_version(&root, "2.1.0");
+  nix::sys::signal::kill(
+    nix::unistd::Pid::from_raw(process.0.id() as i32),
+    nix::sys::signal::Signal::SIGHUP,
+  )
+  .expect("SIGHUP could not be sent");
+
+  for _ in 0..100 {
+    if installed_frontend_version(&url).await == "2.1.0" {
+      let response = reqwest::get(format!("{url}/"))
+        .await
+        .expect("request failed");
+      assert_eq!(response.text().await.unwrap(), "<html>2.1.0</html>");
+      return;
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+  }
+  panic!("installed version was not reloaded after SIGHUP");
+}
@@ -0,0 +1,274 @@
+use std::sync::OnceLock;
+
+use futures::{SinkExt, StreamExt};
+use http_body_util::{BodyExt, Full};
+use hyper::{
+  Request, Response,
+  body::{Bytes, Incoming},
+  server::conn::http1,
+  service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use mpv_web_client::{ApiCredentials, Config};
+use reqwest::StatusCode;
+use tokio::net::TcpListener;
+use tokio_tungstenite::{accept_async, connect_async, tungstenite::Message};
+
+use crate::common::start_server;
+
+mod common;
+
+const UPSTREAM_NAME: &str = "upstream";
+const HTTP_UPSTREAM_NAME: &str = "http-upstream";
+const UNREACHABLE_UPSTREAM_NAME: &str = "unreachable-upstream";
+const GUARDED_UPSTREAM_NAME: &str = "guarded-upstream";
+const API_USER: &str = "fixture-user";
+const API_PASSWORD: &str = "fixture-password";
+
+fn server_url() -> &'static str {
+  static URL: OnceLock<String> = OnceLock::new();
+  URL.get_or_init(|| {
+    let root = std::env::temp_dir().join(format!("mwc-proxy-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("dist")).expect("fixture dir could not be created");
+
+    start_server(Config {
+      serve_dir: Some(root.join("dist")),
+      data_dir: Some(root.join("data")),
+      ..Config::default()
+    })
+  })
+}
+
+// registers a websocket echo server under UPSTREAM_NAME on first use
+async fn ensure_echo_upstream() {
+  static REGISTERED: tokio::sync::OnceCell<()> = tokio::sync::OnceCell::const_new();
+  REGISTERED
+    .get_or_init(|| async {
+      let address = spawn_upstream(|listener| async move { serve_echo(listener).await });
+      register(UPSTREAM_NAME, &address).await;
+    })
+    .await;
+}
+
+// registers an http server under HTTP_UPSTREAM_NAME on first use
+async fn ensure_http_upstream() {
+  static REGISTERED: tokio::sync::OnceCell<()> = tokio::sync::OnceCell::const_new();
+  REGISTERED
+    .get_or_init(|| async {
+      let address = spawn_upstream(|listener| async move { serve_http(listener).await });
+      register(HTTP_UPSTREAM_NAME, &address).await;
+    })
+    .await;
+}
+
+// upstreams run on their own runtime, since every test has a separate one
+fn spawn_upstream<F, Fut>(serve: F) -> String
+where
+  F: FnOnce(TcpListener) -> Fut + Send + 'static,
+  Fut: Future<Output = ()>,
+{
+  let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("upstream could not bind");
+  let address = listener.local_addr().unwrap().to_string();
+  listener.set_nonblocking(true).unwrap();
+  std::thread::spawn(move || {
+    tokio::runtime::Runtime::new()
+      .expect("runtime could not be created")
+      .block_on(async { serve(TcpListener::from_std(listener).unwrap()).await });
+  });
+
+  address
+}
+
+async fn register(name: &str, address: &str) {
+  let response = reqwest::Client::new()
+    .post(format!("{}/api/servers/register", server_url()))
+    .body(format!(
+      "{{\"name\": \"{name}\", \"address\": \"{address}\"}}"
+    ))
+    .send()
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+}
+
+async fn serve_http(listener: TcpListener) {
+  while let Ok((stream, _)) = listener.accept().await {
+    tokio::spawn(async move {
+      let service = service_fn(|req: Request<Incoming>| async move {
+        let forwarded_for = req
+          .headers()
+          .get("x-forwarded-for")
+          .and_then(|value| value.to_str().ok())
+          .unwrap_or_default()
+          .to_owned();
+        let method = req.method().clone();
+        let uri = req.uri().clone();
+        let body = req.into_body().collect().await?.to_bytes();
+        let reply = format!(
+          "{method} {uri} {forwarded_for} {}",
+          String::from_utf8_lossy(&body)
+        );
+        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(reply))))
+      });
+      let _ = http1::Builder::new()
+        .serve_connection(TokioIo::new(stream), service)
+        .await;
+    });
+  }
+}
+
+async fn serve_echo(listener: TcpListener) {
+  while let Ok((stream, _)) = listener.accept().await {
+    tokio::spawn(async move {
+      let Ok(mut socket) = accept_async(stream).await else {
+        return;
+      };
+      while let Some(Ok(message)) = socket.next().await {
+        if message.is_text() || message.is_binary() {
+          let _ = socket.send(message).await;
+        }
+      }
+    });
+  }
+}
+
+fn websocket_url(name: &str) -> String {
+  format!(
+    "{}/api/servers/{name}/ws",
+    server_url().replacen("http://", "ws://", 1)
+  )
+}
+
+#[tokio::test]
+async fn proxies_websocket_messages_both_ways() {
+  ensure_echo_upstream().await;
+
+  let (mut socket, _) = connect_async(websocket_url(UPSTREAM_NAME))
+    .await
+    .expect("websocket connection failed");
+  socket
+    .send(Message::text("ping"))
+    .await
+    .expect("message could not be sent");
+
+  let echoed = socket
+    .next()
+    .await
+    .expect("connection closed")
+    .expect("message could not be received");
+  assert_eq!(echoed, Message::text("ping"));
+}
+
+#[tokio::test]
+async fn rejects_websocket_to_unknown_instance() {
+  let response = reqwest::get(format!("{}/api/servers/missing/ws", server_url()))
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn proxies_http_request_to_instance() {
+  ensure_http_upstream().await;
+
+  let response = reqwest::Client::new()
+    .post(format!(
+      "{}/proxy/{HTTP_UPSTREAM_NAME}/api/status?verbose=1",
+      server_url()
+    ))
+    .body("payload")
+    .send()
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(
+    response.text().await.unwrap(),
+    "POST /api/status?verbose=1 127.0.0.1 payload"
+  );
+}
+
+#[tokio::test]
+async fn rejects_http_proxy_to_unknown_instance() {
+  let response = reqwest::get(format!("{}/proxy/missing/api/status", server_url()))
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn reports_unreachable_instance_as_bad_gateway() {
+  let address = std::net::TcpListener::bind("127.0.0.1:0")
+    .and_then(|listener| listener.local_addr())
+    .expect("free port could not be found")
+    .to_string();
+  register(UNREACHABLE_UPSTREAM_NAME, &address).await;
+
+  let response = reqwest::get(format!(
+    "{}/proxy/{UNREACHABLE_UPSTREAM_NAME}/api/status",
+    server_url()
+  ))
+  .await
+  .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+}
+
+// fails every request that carries credentials, which belong to the client api only
+async fn serve_rejecting_authorization(listener: TcpListener) {
+  while let Ok((stream, _)) = listener.accept().await {
+    tokio::spawn(async move {
+      let service = service_fn(|req: Request<Incoming>| async move {
+        let mut response = Response::new(Full::new(Bytes::new()));
+        if req.headers().contains_key("authorization") {
+          *response.status_mut() = hyper::StatusCode::INTERNAL_SERVER_ERROR;
+        }
+        Ok::<_, hyper::Error>(response)
+      });
+      let _ = http1::Builder::new()
+        .serve_connection(TokioIo::new(stream), service)
+        .await;
+    });
+  }
+}
+
+#[tokio::test]
+async fn client_credentials_are_not_forwarded_to_instance() {
+  let root = std::env::temp_dir().join(format!("mwc-proxy-credentials-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  std::fs::create_dir_all(root.join("dist")).expect("fixture dir could not be created");
+  let url = start_server(Config {
+    serve_dir: Some(root.join("dist")),
+    data_dir: Some(root.join("data")),
+    api_credentials: Some(ApiCredentials {
+      user: API_USER.to_owned(),
+      password: API_PASSWORD.to_owned(),
+    }),
+    ..Config::default()
+  });
+  let address =
+    spawn_upstream(|listener| async move { serve_rejecting_authorization(listener).await });
+  let client = reqwest::Client::new();
+  let response = client
+    .post(format!("{url}/api/servers/register"))
+    .basic_auth(API_USER, Some(API_PASSWORD))
+    .body(format!(
+      "{{\"name\": \"{GUARDED_UPSTREAM_NAME}\", \"address\": \"{address}\"}}"
+    ))
+    .send()
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let response = client
+    .get(format!("{url}/proxy/{GUARDED_UPSTREAM_NAME}/api/status"))
+    .basic_auth(API_USER, Some(API_PASSWORD))
+    .send()
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+}
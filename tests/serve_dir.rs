@@ -0,0 +1,89 @@
+use std::{
+  io::{Read, Write},
+  net::TcpStream,
+  path::PathBuf,
+  sync::OnceLock,
+};
+
+use mpv_web_client::Config;
+use reqwest::StatusCode;
+
+use crate::common::start_server;
+
+mod common;
+
+const INDEX_HTML: &str = "<html>dev index</html>";
+const APP_JS: &str = "console.log(\"dev\");";
+const SECRET: &str = "outside of the served directory";
+
+fn server_url() -> &'static str {
+  static URL: OnceLock<String> = OnceLock::new();
+  URL.get_or_init(|| {
+    let root = std::env::temp_dir().join(format!("mwc-serve-dir-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let serve_dir: PathBuf = root.join("dist");
+    std::fs::create_dir_all(serve_dir.join("assets")).expect("fixture dir could not be created");
+    std::fs::write(serve_dir.join("index.html"), INDEX_HTML).expect("fixture could not be written");
+    std::fs::write(serve_dir.join("assets").join("app.js"), APP_JS)
+      .expect("fixture could not be written");
+    std::fs::write(root.join("secret.txt"), SECRET).expect("fixture could not be written");
+
+    start_server(Config {
+      serve_dir: Some(serve_dir),
+      data_dir: Some(root.join("data")),
+      ..Config::default()
+    })
+  })
+}
+
+#[tokio::test]
+async fn serves_assets_from_directory() {
+  let response = reqwest::get(format!("{}/assets/app.js", server_url()))
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.text().await.unwrap(), APP_JS);
+}
+
+#[tokio::test]
+async fn falls_back_to_directory_index() {
+  for path in ["/", "/some/client/route", "/assets/missing.js"] {
+    let response = reqwest::get(format!("{}{path}", server_url()))
+      .await
+      .expect("request failed");
+
+    assert_eq!(response.status(), StatusCode::OK, "path {path}");
+    assert_eq!(response.text().await.unwrap(), INDEX_HTML, "path {path}");
+  }
+}
+
+// http clients normalize dot segments, so the request is written by hand
+fn raw_get(path: &str) -> String {
+  let address = server_url().trim_start_matches("http://");
+  let mut stream = TcpStream::connect(address).expect("connection failed");
+  write!(
+    stream,
+    "GET {path} HTTP/1.1\r\nHost: {address}\r\nConnection: close\r\n\r\n"
+  )
+  .expect("request failed");
+  let mut response = String::new();
+  stream
+    .read_to_string(&mut response)
+    .expect("response could not be read");
+  response
+}
+
+#[test]
+fn rejects_paths_outside_of_directory() {
+  for path in ["/../secret.txt", "/assets/../../secret.txt"] {
+    let response = raw_get(path);
+
+    assert!(
+      response.starts_with("HTTP/1.1 404"),
+      "path {path}: {response}"
+    );
+    assert!(!response.contains(SECRET), "path {path}");
+    assert!(!response.contains(INDEX_HTML), "path {path}");
+  }
+}
@@ -0,0 +1,523 @@
+use std::{
+  io::{Read, Write},
+  path::{Path, PathBuf},
+  sync::OnceLock,
+};
+
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use mpv_web_client::{Config, Error, run};
+use reqwest::{
+  StatusCode,
+  header::{
+    ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_SECURITY_POLICY,
+    CONTENT_TYPE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE, REFERRER_POLICY, VARY,
+    X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+  },
+};
+
+use crate::common::{start_serve_dir_server, start_server};
+
+mod common;
+
+const MANIFEST: &str = "[version_info]\nversion = \"1.0.0\"\ncommit = \"fixture\"\n";
+const INDEX_HTML: &str = "<html>fixture index</html>";
+const APP_JS: &str = "console.log(\"fixture\");";
+const STYLE_CSS: &str = "body { color: black; }";
+// the module header is enough for the content to be recognised as wasm
+const MODULE_WASM: &[u8] = b"\0asm\x01\0\0\0";
+const BUNDLE_JS_LINE: &str = "console.log(\"bundled fixture\");\n";
+
+// large enough to be served compressed under the default compression size threshold
+fn bundle_js() -> String {
+  BUNDLE_JS_LINE.repeat(64)
+}
+
+// tests share a single server, started with the fixture package on first use
+fn server_url() -> &'static str {
+  static URL: OnceLock<String> = OnceLock::new();
+  URL.get_or_init(|| {
+    let root = std::env::temp_dir().join(format!("mwc-it-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).expect("fixture dir could not be created");
+    let pkg = write_fixture_package(&root);
+
+    start_server(Config {
+      pkg: Some(pkg),
+      data_dir: Some(root.join("data")),
+      ..Config::default()
+    })
+  })
+}
+
+fn write_fixture_package(root: &Path) -> PathBuf {
+  let path = root.join("fixture-1.0.0.tar.gz");
+  let file = std::fs::File::create(&path).expect("fixture package could not be created");
+  let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+  let mut append = |name: &str, content: &[u8]| {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive
+      .append_data(&mut header, name, content)
+      .expect("fixture file could not be added");
+  };
+  append("pkg_manifest.toml", MANIFEST.as_bytes());
+  append("index.html", INDEX_HTML.as_bytes());
+  append("app.js", APP_JS.as_bytes());
+  append("app.js.gz", &gzip(APP_JS.as_bytes()));
+  append("style.css", STYLE_CSS.as_bytes());
+  append("module.wasm", MODULE_WASM);
+  append("bundle.js", bundle_js().as_bytes());
+  append("bundle.js.gz", &gzip(bundle_js().as_bytes()));
+
+  archive
+    .into_inner()
+    .and_then(|encoder| encoder.finish())
+    .expect("fixture package could not be written");
+  path
+}
+
+// serves a directory with the entrypoint and provided files, at paths relative to it
+fn start_dir_server(name: &str, files: &[(&str, &str)], config: Config) -> String {
+  let root = std::env::temp_dir().join(format!("mwc-it-{name}-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let serve_dir = root.join("dist");
+  for (path, content) in [("index.html", INDEX_HTML)].iter().chain(files) {
+    let path = serve_dir.join(path);
+    std::fs::create_dir_all(path.parent().unwrap()).expect("fixture dir could not be created");
+    std::fs::write(path, content).expect("fixture could not be written");
+  }
+
+  start_server(Config {
+    serve_dir: Some(serve_dir),
+    data_dir: Some(root.join("data")),
+    ..config
+  })
+}
+
+fn gzip(content: &[u8]) -> Vec<u8> {
+  let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+  encoder.write_all(content).expect("gzip failed");
+  encoder.finish().expect("gzip failed")
+}
+
+#[tokio::test]
+async fn serves_entrypoint_for_root_and_unknown_paths() {
+  for path in ["/", "/some/client/route"] {
+    let response = reqwest::get(format!("{}{path}", server_url()))
+      .await
+      .expect("request failed");
+
+    assert_eq!(response.status(), StatusCode::OK, "path {path}");
+    assert_eq!(response.text().await.unwrap(), INDEX_HTML, "path {path}");
+  }
+}
+
+#[tokio::test]
+async fn serves_gzipped_asset_when_accepted() {
+  let response = reqwest::Client::new()
+    .get(format!("{}/bundle.js", server_url()))
+    .header(ACCEPT_ENCODING, "gzip")
+    .send()
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+  assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+  assert_eq!(response.headers()[VARY], "Accept-Encoding");
+  let body = response.bytes().await.unwrap();
+  let mut decoded = String::new();
+  GzDecoder::new(body.as_ref())
+    .read_to_string(&mut decoded)
+    .expect("body is not gzipped");
+  assert_eq!(decoded, bundle_js());
+}
+
+#[tokio::test]
+async fn serves_plain_asset_below_compression_size() {
+  let response = reqwest::Client::new()
+    .get(format!("{}/app.js", server_url()))
+    .header(ACCEPT_ENCODING, "gzip")
+    .send()
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  assert!(response.headers().get(CONTENT_ENCODING).is_none());
+  assert_eq!(response.text().await.unwrap(), APP_JS);
+}
+
+#[tokio::test]
+async fn serves_plain_asset_without_accepted_encoding() {
+  let response = reqwest::Client::new()
+    .get(format!("{}/app.js", server_url()))
+    .header(ACCEPT_ENCODING, "identity")
+    .send()
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  assert!(response.headers().get(CONTENT_ENCODING).is_none());
+  assert_eq!(response.text().await.unwrap(), APP_JS);
+}
+
+#[tokio::test]
+async fn serves_stylesheet_as_utf8_text() {
+  let response = reqwest::get(format!("{}/style.css", server_url()))
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.headers()[CONTENT_TYPE], "text/css; charset=utf-8");
+  assert_eq!(response.text().await.unwrap(), STYLE_CSS);
+}
+
+#[tokio::test]
+async fn serves_wasm_module_without_charset() {
+  let response = reqwest::get(format!("{}/module.wasm", server_url()))
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.headers()[CONTENT_TYPE], "application/wasm");
+  assert_eq!(response.bytes().await.unwrap().as_ref(), MODULE_WASM);
+}
+
+#[tokio::test]
+async fn serves_custom_extension_with_configured_mime_type() {
+  let url = start_dir_server(
+    "mime-types",
+    &[("scene.mwcscene", "fixture scene")],
+    Config {
+      mime_types: vec![(".MWCScene".to_owned(), "model/x-fixture".to_owned())],
+      ..Config::default()
+    },
+  );
+
+  let response = reqwest::get(format!("{url}/scene.mwcscene"))
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.headers()[CONTENT_TYPE], "model/x-fixture");
+  assert_eq!(response.text().await.unwrap(), "fixture scene");
+}
+
+#[tokio::test]
+async fn nested_index_is_preferred_over_entrypoint() {
+  let docs_index = "<html>docs index</html>";
+  let url = start_dir_server(
+    "nested-index",
+    &[("docs/index.html", docs_index)],
+    Config::default(),
+  );
+
+  for path in ["/docs", "/docs/"] {
+    let response = reqwest::get(format!("{url}{path}"))
+      .await
+      .expect("request failed");
+    assert_eq!(response.status(), StatusCode::OK, "path {path}");
+    assert_eq!(response.text().await.unwrap(), docs_index, "path {path}");
+  }
+  let response = reqwest::get(format!("{url}/other/"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.text().await.unwrap(), INDEX_HTML);
+}
+
+#[tokio::test]
+async fn serves_whole_file_with_tiny_stream_chunks() {
+  let content = bundle_js();
+  let url = start_dir_server(
+    "tiny-chunks",
+    &[("bundle.js", &content)],
+    Config {
+      stream_chunk_size: 1,
+      ..Config::default()
+    },
+  );
+
+  let response = reqwest::Client::new()
+    .get(format!("{url}/bundle.js"))
+    .header(ACCEPT_ENCODING, "identity")
+    .send()
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(
+    response.headers()[CONTENT_LENGTH],
+    content.len().to_string()
+  );
+  assert_eq!(response.text().await.unwrap(), content);
+}
+
+#[tokio::test]
+async fn sets_security_headers_on_assets() {
+  let response = reqwest::get(format!("{}/app.js", server_url()))
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.headers()[X_CONTENT_TYPE_OPTIONS], "nosniff");
+  assert_eq!(response.headers()[X_FRAME_OPTIONS], "SAMEORIGIN");
+  assert_eq!(response.headers()[REFERRER_POLICY], "same-origin");
+  assert!(response.headers().get(CONTENT_SECURITY_POLICY).is_none());
+}
+
+#[tokio::test]
+async fn sets_configured_content_security_policy() {
+  let policy = "default-src 'self'";
+  let url = start_serve_dir_server(
+    "csp",
+    Config {
+      content_security_policy: Some(policy.to_owned()),
+      ..Config::default()
+    },
+  );
+
+  let response = reqwest::get(format!("{url}/"))
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.headers()[CONTENT_SECURITY_POLICY], policy);
+  assert_eq!(response.headers()[X_CONTENT_TYPE_OPTIONS], "nosniff");
+}
+
+#[tokio::test]
+async fn rejects_unknown_api_route() {
+  let response = reqwest::get(format!("{}/api/does-not-exist", server_url()))
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+  assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+}
+
+#[tokio::test]
+async fn rejects_invalid_method_on_api_route() {
+  let response = reqwest::Client::new()
+    .delete(format!("{}/api/idle", server_url()))
+    .send()
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+  assert_eq!(response.headers()["allow"], "GET");
+}
+
+#[tokio::test]
+async fn reports_allowed_method_for_body_routes() {
+  let response = reqwest::get(format!("{}/api/servers/spawn", server_url()))
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+  assert_eq!(response.headers()["allow"], "POST");
+}
+
+#[tokio::test]
+async fn reports_allowed_method_for_listing_routes() {
+  for path in ["/api/servers", "/api/frontend/latest"] {
+    let response = reqwest::Client::new()
+      .post(format!("{}{path}", server_url()))
+      .send()
+      .await
+      .expect("request failed");
+
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED, "{path}");
+    assert_eq!(response.headers()["allow"], "GET", "{path}");
+  }
+}
+
+#[tokio::test]
+async fn sets_content_length_on_json_error() {
+  let response = reqwest::get(format!("{}/api/does-not-exist", server_url()))
+    .await
+    .expect("request failed");
+
+  let content_length = response.headers()[CONTENT_LENGTH].clone();
+  let body = response.bytes().await.unwrap();
+  assert_eq!(content_length, body.len().to_string().as_str());
+}
+
+#[tokio::test]
+async fn sets_content_length_on_empty_success() {
+  let response = reqwest::Client::new()
+    .post(format!("{}/api/idle/resume", server_url()))
+    .send()
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.headers().get_all(CONTENT_LENGTH).iter().count(), 1);
+  assert_eq!(response.headers()[CONTENT_LENGTH], "0");
+}
+
+async fn get_app_js_range(if_range: &str) -> reqwest::Response {
+  reqwest::Client::new()
+    .get(format!("{}/app.js", server_url()))
+    .header(ACCEPT_ENCODING, "identity")
+    .header(RANGE, "bytes=0-6")
+    .header(IF_RANGE, if_range)
+    .send()
+    .await
+    .expect("request failed")
+}
+
+async fn app_js_validator(name: reqwest::header::HeaderName) -> String {
+  let response = reqwest::Client::new()
+    .get(format!("{}/app.js", server_url()))
+    .header(ACCEPT_ENCODING, "identity")
+    .send()
+    .await
+    .expect("request failed");
+
+  response.headers()[name].to_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn serves_range_when_if_range_matches() {
+  for validator in [ETAG, LAST_MODIFIED] {
+    let if_range = app_js_validator(validator.clone()).await;
+
+    let response = get_app_js_range(&if_range).await;
+
+    assert_eq!(
+      response.status(),
+      StatusCode::PARTIAL_CONTENT,
+      "{validator}"
+    );
+    assert_eq!(response.text().await.unwrap(), &APP_JS[..7], "{validator}");
+  }
+}
+
+#[tokio::test]
+async fn serves_full_file_when_if_range_does_not_match() {
+  for if_range in ["\"outdated\"", "Sat, 01 Jan 2000 00:00:00 GMT"] {
+    let response = get_app_js_range(if_range).await;
+
+    assert_eq!(response.status(), StatusCode::OK, "{if_range}");
+    assert_eq!(response.text().await.unwrap(), APP_JS, "{if_range}");
+  }
+}
+
+#[tokio::test]
+async fn reports_version_without_optional_manifest_metadata() {
+  let response = reqwest::get(format!("{}/api/version", server_url()))
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  let body: serde_json::Value =
+    serde_json::from_str(&response.text().await.unwrap()).expect("body is not json");
+  assert_eq!(body["frontend"]["version"], "1.0.0");
+  assert_eq!(body["frontend"]["commit"], "fixture");
+  assert!(body["frontend"]["min_api_version"].is_null());
+  assert!(body["frontend"]["title"].is_null());
+}
+
+async fn scrape_metrics() -> String {
+  let response = reqwest::get(format!("{}/api/metrics", server_url()))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+  assert!(
+    response.headers()[CONTENT_TYPE]
+      .to_str()
+      .unwrap()
+      .starts_with("text/plain")
+  );
+  response.text().await.unwrap()
+}
+
+fn metric(scrape: &str, name: &str) -> u64 {
+  scrape
+    .lines()
+    .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+    .and_then(|value| value.parse().ok())
+    .unwrap_or_else(|| panic!("metric {name} missing in:\n{scrape}"))
+}
+
+#[tokio::test]
+async fn metrics_reflect_served_requests() {
+  let before = scrape_metrics().await;
+
+  let response = reqwest::Client::new()
+    .get(format!("{}/app.js", server_url()))
+    .header(ACCEPT_ENCODING, "identity")
+    .send()
+    .await
+    .expect("request failed");
+  assert_eq!(response.text().await.unwrap(), APP_JS);
+  let response = reqwest::get(format!("{}/api/does-not-exist", server_url()))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+  // other tests share the server, so counters are only known to have grown at least this much
+  let after = scrape_metrics().await;
+  let grown = |name: &str| metric(&after, name) - metric(&before, name);
+  assert!(grown("mpv_web_client_requests_total") >= 3);
+  assert!(grown("mpv_web_client_responses_total{class=\"2xx\"}") >= 2);
+  assert!(grown("mpv_web_client_responses_total{class=\"4xx\"}") >= 1);
+  assert!(grown("mpv_web_client_served_bytes_total") >= APP_JS.len() as u64);
+  assert!(metric(&after, "mpv_web_client_active_connections") >= 1);
+  metric(&after, "mpv_web_client_not_modified_total");
+}
+
+#[tokio::test]
+async fn servers_in_one_process_use_their_own_data_dirs() {
+  let root = std::env::temp_dir().join(format!("mwc-it-second-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let installed_dir = root.join("data").join("frontend").join("3.0.0");
+  std::fs::create_dir_all(&installed_dir).expect("fixture dir could not be created");
+  std::fs::write(
+    root.join("data").join("pkg_manifest.toml"),
+    "[version_info]\nversion = \"3.0.0\"\ncommit = \"second\"\n",
+  )
+  .expect("manifest could not be written");
+  std::fs::write(installed_dir.join("index.html"), "<html>3.0.0</html>")
+    .expect("entrypoint could not be written");
+  let first_url = server_url();
+
+  let second_url = start_server(Config {
+    data_dir: Some(root.join("data")),
+    ..Config::default()
+  });
+
+  for (url, index) in [
+    (first_url, INDEX_HTML),
+    (second_url.as_str(), "<html>3.0.0</html>"),
+  ] {
+    let response = reqwest::get(format!("{url}/"))
+      .await
+      .expect("request failed");
+    assert_eq!(response.text().await.unwrap(), index, "{url}");
+  }
+}
+
+#[tokio::test]
+async fn rejects_zero_rate_limit() {
+  let root = std::env::temp_dir().join(format!("mwc-it-zero-rate-{}", std::process::id()));
+  for (rate_limit, rate_limit_burst) in [(Some(0), None), (Some(1), Some(0))] {
+    let result = run(Config {
+      serve_dir: Some(root.clone()),
+      data_dir: Some(root.join("data")),
+      offline: true,
+      rate_limit,
+      rate_limit_burst,
+      ..Config::default()
+    })
+    .await;
+
+    assert!(
+      matches!(result, Err(Error::InvalidRateLimit)),
+      "{rate_limit:?} {rate_limit_burst:?}: {result:?}"
+    );
+  }
+}
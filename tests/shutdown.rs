@@ -0,0 +1,147 @@
+use std::{
+  net::{TcpListener, TcpStream},
+  process::Command,
+  time::Duration,
+};
+
+use mpv_web_client::Config;
+use reqwest::StatusCode;
+use tokio::time::sleep;
+
+use crate::common::start_serve_dir_server;
+
+mod common;
+
+const SHUTDOWN_TOKEN: &str = "fixture-token";
+const SHUTDOWN_TOKEN_HEADER: &str = "X-Shutdown-Token";
+
+fn start_shutdown_server(name: &str, shutdown_token: Option<&str>) -> String {
+  start_serve_dir_server(
+    name,
+    Config {
+      shutdown_token: shutdown_token.map(|token| token.to_owned()),
+      ..Config::default()
+    },
+  )
+}
+
+async fn request_shutdown(url: &str, token: Option<&str>) -> reqwest::Response {
+  let mut request = reqwest::Client::new().post(format!("{url}/api/shutdown"));
+  if let Some(token) = token {
+    request = request.header(SHUTDOWN_TOKEN_HEADER, token);
+  }
+  request.send().await.expect("request failed")
+}
+
+async fn assert_still_serving(url: &str) {
+  let response = reqwest::get(url).await.expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+}
+
+async fn wait_until_stopped(url: &str) -> bool {
+  let addr = url.trim_start_matches("http://");
+  for _ in 0..100 {
+    if TcpStream::connect(addr).is_err() {
+      return true;
+    }
+    sleep(Duration::from_millis(50)).await;
+  }
+
+  false
+}
+
+#[tokio::test]
+async fn correct_token_shuts_down_server() {
+  let url = start_shutdown_server("correct", Some(SHUTDOWN_TOKEN));
+
+  let response = request_shutdown(&url, Some(SHUTDOWN_TOKEN)).await;
+
+  assert_eq!(response.status(), StatusCode::OK);
+  assert!(wait_until_stopped(&url).await, "server kept listening");
+}
+
+#[tokio::test]
+async fn wrong_token_is_rejected() {
+  let url = start_shutdown_server("wrong", Some(SHUTDOWN_TOKEN));
+
+  for token in [Some("not-the-token"), None] {
+    let response = request_shutdown(&url, token).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+  }
+  assert_still_serving(&url).await;
+}
+
+#[tokio::test]
+async fn shutdown_is_disabled_without_token() {
+  let url = start_shutdown_server("disabled", None);
+
+  let response = request_shutdown(&url, Some(SHUTDOWN_TOKEN)).await;
+
+  assert_eq!(response.status(), StatusCode::FORBIDDEN);
+  assert_still_serving(&url).await;
+}
+
+#[tokio::test]
+async fn shutdown_requires_post() {
+  let url = start_shutdown_server("method", Some(SHUTDOWN_TOKEN));
+
+  let response = reqwest::Client::new()
+    .get(format!("{url}/api/shutdown"))
+    .header(SHUTDOWN_TOKEN_HEADER, SHUTDOWN_TOKEN)
+    .send()
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+  assert_eq!(response.headers()["allow"], "POST");
+  assert_still_serving(&url).await;
+}
+
+#[tokio::test]
+async fn sigterm_shuts_down_gracefully() {
+  let root = std::env::temp_dir().join(format!("mwc-shutdown-sigterm-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let serve_dir = root.join("dist");
+  std::fs::create_dir_all(&serve_dir).expect("fixture dir could not be created");
+  std::fs::write(serve_dir.join("index.html"), "<html>fixture</html>")
+    .expect("fixture could not be written");
+  let port = TcpListener::bind("127.0.0.1:0")
+    .and_then(|listener| listener.local_addr())
+    .expect("free port could not be found")
+    .port();
+  let url = format!("http://127.0.0.1:{port}");
+
+  let mut child = Command::new(env!("CARGO_BIN_EXE_mpv-web-client"))
+    .arg("--serve-dir")
+    .arg(&serve_dir)
+    .arg("--data-dir")
+    .arg(root.join("data"))
+    .args(["--port", &port.to_string(), "--offline"])
+    .spawn()
+    .expect("binary could not be run");
+  for _ in 0..100 {
+    if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+      break;
+    }
+    sleep(Duration::from_millis(50)).await;
+  }
+  assert_still_serving(&url).await;
+
+  nix::sys::signal::kill(
+    nix::unistd::Pid::from_raw(child.id() as i32),
+    nix::sys::signal::Signal::SIGTERM,
+  )
+  .expect("SIGTERM could not be sent");
+
+  assert!(wait_until_stopped(&url).await, "server kept listening");
+  for _ in 0..100 {
+    if let Some(status) = child.try_wait().expect("process status unavailable") {
+      // the default SIGTERM disposition would terminate the process without an exit code
+      assert!(status.success(), "{status}");
+      return;
+    }
+    sleep(Duration::from_millis(50)).await;
+  }
+  let _ = child.kill();
+  panic!("process did not exit after SIGTERM");
+}
@@ -0,0 +1,155 @@
+use std::{
+  os::unix::fs::PermissionsExt,
+  path::{Path, PathBuf},
+  process::{Command, Output},
+};
+
+const MANIFEST: &str = "[version_info]\nversion = \"1.0.0\"\ncommit = \"fixture\"\n";
+
+fn installed_fixture(name: &str) -> PathBuf {
+  installed_fixture_with_manifest(name, MANIFEST)
+}
+
+fn installed_fixture_with_manifest(name: &str, manifest: &str) -> PathBuf {
+  let root = std::env::temp_dir().join(format!("mwc-verify-{name}-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let installed_dir = root.join("frontend").join("1.0.0");
+  std::fs::create_dir_all(&installed_dir).expect("fixture dir could not be created");
+  std::fs::create_dir_all(root.join("logs")).expect("fixture dir could not be created");
+  std::fs::create_dir_all(root.join("tmp")).expect("fixture dir could not be created");
+  std::fs::write(root.join("pkg_manifest.toml"), manifest).expect("manifest could not be written");
+  std::fs::write(installed_dir.join("index.html"), "<html></html>")
+    .expect("entrypoint could not be written");
+
+  root
+}
+
+fn run_verify(data_dir: &Path) -> Output {
+  verify_command(data_dir)
+    .output()
+    .expect("binary could not be run")
+}
+
+fn verify_command(data_dir: &Path) -> Command {
+  let mut command = Command::new(env!("CARGO_BIN_EXE_mpv-web-client"));
+  command
+    .args(["--verify", "--offline", "--data-dir"])
+    .arg(data_dir);
+  command
+}
+
+#[test]
+fn healthy_installation_passes() {
+  let output = run_verify(&installed_fixture("healthy"));
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(output.status.success(), "{stdout}");
+  assert!(stdout.contains("[ok]   entrypoint"), "{stdout}");
+  assert!(!stdout.contains("[fail]"), "{stdout}");
+}
+
+#[test]
+fn missing_entrypoint_fails() {
+  let data_dir = installed_fixture("no-entrypoint");
+  std::fs::remove_file(data_dir.join("frontend").join("1.0.0").join("index.html"))
+    .expect("entrypoint could not be removed");
+
+  let output = run_verify(&data_dir);
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(!output.status.success(), "{stdout}");
+  assert!(
+    stdout
+      .contains("[fail] entrypoint: frontend cannot be served due to lack of an entrypoint file"),
+    "{stdout}"
+  );
+}
+
+#[test]
+fn reports_missing_manifest_field() {
+  let data_dir =
+    installed_fixture_with_manifest("missing-commit", "[version_info]\nversion = \"1.0.0\"\n");
+
+  let output = run_verify(&data_dir);
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(!output.status.success(), "{stdout}");
+  assert!(
+    stdout.contains("manifest missing required field version_info.commit"),
+    "{stdout}"
+  );
+}
+
+#[test]
+fn reports_invalid_manifest_version() {
+  let data_dir = installed_fixture_with_manifest(
+    "bad-version",
+    "[version_info]\nversion = \"one\"\ncommit = \"fixture\"\n",
+  );
+
+  let output = run_verify(&data_dir);
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(!output.status.success(), "{stdout}");
+  assert!(
+    stdout.contains("manifest field version_info.version \"one\" is not a valid version"),
+    "{stdout}"
+  );
+}
+
+#[test]
+fn treats_empty_entrypoint_as_default() {
+  let data_dir = installed_fixture_with_manifest(
+    "empty-entrypoint",
+    "[version_info]\nversion = \"1.0.0\"\ncommit = \"fixture\"\nentrypoint = \"\"\n",
+  );
+
+  let output = run_verify(&data_dir);
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(output.status.success(), "{stdout}");
+  assert!(stdout.contains("[ok]   entrypoint"), "{stdout}");
+}
+
+#[test]
+fn distinguishes_manifest_syntax_errors() {
+  let data_dir = installed_fixture_with_manifest("bad-syntax", "[version_info\n");
+
+  let output = run_verify(&data_dir);
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(!output.status.success(), "{stdout}");
+  assert!(
+    stdout.contains("manifest is not a valid toml document"),
+    "{stdout}"
+  );
+}
+
+#[test]
+fn warns_when_frontend_requires_newer_api() {
+  let data_dir = installed_fixture_with_manifest(
+    "newer-api",
+    "[version_info]\nversion = \"1.0.0\"\ncommit = \"fixture\"\nmin_api_version = \"2.0.0\"\n",
+  );
+  let bin_dir = data_dir.join("bin");
+  std::fs::create_dir_all(&bin_dir).expect("stub dir could not be created");
+  let stub = bin_dir.join("mpv-web-api");
+  std::fs::write(&stub, "#!/bin/sh\necho \"mpv-web-api 1.4.0\"\n")
+    .expect("stub could not be written");
+  std::fs::set_permissions(&stub, std::fs::Permissions::from_mode(0o755))
+    .expect("stub could not be made executable");
+
+  let output = verify_command(&data_dir)
+    .env("PATH", &bin_dir)
+    .output()
+    .expect("binary could not be run");
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(output.status.success(), "{stdout}");
+  assert!(
+    stdout.contains(
+      "[warn] api version: installed frontend requires mpv-web-api version 2.0.0 or newer, but version 1.4.0 is available"
+    ),
+    "{stdout}"
+  );
+}
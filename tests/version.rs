@@ -0,0 +1,53 @@
+use std::sync::OnceLock;
+
+use mpv_web_client::Config;
+use reqwest::StatusCode;
+use serde_json::Value;
+
+use crate::common::start_server;
+
+mod common;
+
+const MANIFEST: &str = r#"[version_info]
+version = "1.2.0"
+commit = "fixture"
+build_time = "2025-06-01T12:00:00Z"
+min_api_version = "0.5.0"
+title = "Fixture frontend"
+"#;
+
+fn server_url() -> &'static str {
+  static URL: OnceLock<String> = OnceLock::new();
+  URL.get_or_init(|| {
+    let root = std::env::temp_dir().join(format!("mwc-version-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let data_dir = root.join("data");
+    let installed_dir = data_dir.join("frontend").join("1.2.0");
+    std::fs::create_dir_all(&installed_dir).expect("fixture dir could not be created");
+    std::fs::write(data_dir.join("pkg_manifest.toml"), MANIFEST)
+      .expect("manifest could not be written");
+    std::fs::write(installed_dir.join("index.html"), "<html></html>")
+      .expect("entrypoint could not be written");
+
+    start_server(Config {
+      data_dir: Some(data_dir),
+      ..Config::default()
+    })
+  })
+}
+
+#[tokio::test]
+async fn reports_optional_manifest_metadata() {
+  let response = reqwest::get(format!("{}/api/version", server_url()))
+    .await
+    .expect("request failed");
+
+  assert_eq!(response.status(), StatusCode::OK);
+  let body: Value =
+    serde_json::from_str(&response.text().await.unwrap()).expect("body is not json");
+  assert_eq!(body["client_version"], env!("CARGO_PKG_VERSION"));
+  assert_eq!(body["frontend"]["version"], "1.2.0");
+  assert_eq!(body["frontend"]["build_time"], "2025-06-01T12:00:00Z");
+  assert_eq!(body["frontend"]["min_api_version"], "0.5.0");
+  assert_eq!(body["frontend"]["title"], "Fixture frontend");
+}
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use mpv_web_client::Config;
+use reqwest::{StatusCode, header::CONTENT_TYPE};
+use tokio::time::timeout;
+
+use crate::common::start_server;
+
+mod common;
+
+#[tokio::test]
+async fn modifying_served_file_emits_reload_event() {
+  let root = std::env::temp_dir().join(format!("mwc-watch-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&root);
+  let serve_dir = root.join("dist");
+  std::fs::create_dir_all(&serve_dir).expect("fixture dir could not be created");
+  std::fs::write(serve_dir.join("index.html"), "<html>v1</html>")
+    .expect("fixture could not be written");
+  let url = start_server(Config {
+    serve_dir: Some(serve_dir.clone()),
+    watch: true,
+    data_dir: Some(root.join("data")),
+    ..Config::default()
+  });
+
+  let mut response = reqwest::get(format!("{url}/api/frontend/reload-events"))
+    .await
+    .expect("request failed");
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.headers()[CONTENT_TYPE], "text/event-stream");
+  // the subscription is active once the first chunk arrives
+  response.chunk().await.expect("stream failed");
+
+  std::fs::write(serve_dir.join("index.html"), "<html>v2</html>")
+    .expect("fixture could not be modified");
+
+  let event = timeout(Duration::from_secs(5), response.chunk())
+    .await
+    .expect("no reload event received")
+    .expect("stream failed")
+    .expect("stream ended");
+  assert!(String::from_utf8_lossy(&event).contains("event: reload"));
+}